@@ -0,0 +1,176 @@
+//! Client helpers for the staking program.
+//!
+//! Instructions that pay referral commissions (stake, increase_stake, compound, otc_buy,
+//! add_referrer) take the referrer staking accounts through remaining accounts, in upline
+//! order. The program checks them against the upline cached in `StakingAccount`, so the
+//! accounts to pass are derived from that cache here instead of walking `referrer` pointers.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::AccountMeta;
+use anchor_lang::AccountDeserialize;
+use anchor_spl::associated_token::get_associated_token_address;
+use solana_client::client_error::{ClientError, ClientErrorKind};
+use solana_client::rpc_client::RpcClient;
+use staking::state::{StakingAccount, MAX_UPLINE_LEVELS};
+
+pub fn staking_account_address(user: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"staking", user.as_ref()], &staking::ID).0
+}
+
+// Both keys are staking accounts, not wallets
+pub fn referral_entry_address(referrer: &Pubkey, referee: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"referral", referrer.as_ref(), referee.as_ref()], &staking::ID).0
+}
+
+// Source is the position, or the referral entry for credits made by add_referrer
+pub fn referral_credit_address(source: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"referral-credit", source.as_ref()], &staking::ID).0
+}
+
+pub fn fetch_staking_account(rpc: &RpcClient, user: &Pubkey) -> Result<StakingAccount, ClientError> {
+    let data = rpc.get_account_data(&staking_account_address(user))?;
+
+    StakingAccount::try_deserialize(&mut data.as_slice())
+        .map_err(|err| ClientErrorKind::Custom(err.to_string()).into())
+}
+
+// Cached upline without the default keys past the top of the tree
+pub fn upline(staking_account: &StakingAccount) -> &[Pubkey] {
+    &staking_account.upline[..StakingAccount::upline_depth(&staking_account.upline)]
+}
+
+// Remaining accounts for stake, increase_stake and compound_rewards
+pub fn referrer_account_metas(staking_account: &StakingAccount) -> Vec<AccountMeta> {
+    upline(staking_account)
+        .iter()
+        .map(|key| AccountMeta::new(*key, false))
+        .collect()
+}
+
+/*
+ * Remaining accounts for otc_buy: the upline, then where each level's commission goes.
+ * That is the referrer's wallet for SOL, or its associated token account of the quote mint.
+ */
+pub fn otc_buy_account_metas(
+    rpc: &RpcClient,
+    staking_account: &StakingAccount,
+    quote_mint: &Pubkey,
+) -> Result<Vec<AccountMeta>, ClientError> {
+    let mut metas = referrer_account_metas(staking_account);
+
+    for key in upline(staking_account) {
+        let data = rpc.get_account_data(key)?;
+        let referrer = StakingAccount::try_deserialize(&mut data.as_slice())
+            .map_err(|err| ClientError::from(ClientErrorKind::Custom(err.to_string())))?;
+
+        let recipient = if *quote_mint == Pubkey::default() {
+            referrer.user
+        } else {
+            get_associated_token_address(&referrer.user, quote_mint)
+        };
+
+        metas.push(AccountMeta::new(recipient, false));
+    }
+
+    Ok(metas)
+}
+
+// Remaining accounts for add_referrer: the new referrer followed by its own upline
+pub fn add_referrer_account_metas(referrer_user: &Pubkey, referrer: &StakingAccount) -> Vec<AccountMeta> {
+    let new_upline = StakingAccount::upline_below(staking_account_address(referrer_user), referrer);
+    let depth = StakingAccount::upline_depth(&new_upline);
+
+    new_upline[..depth]
+        .iter()
+        .map(|key| AccountMeta::new(*key, false))
+        .collect()
+}
+
+// Referral entry to pass next to the staking account, None for users without a referrer
+pub fn referral_entry_for(user: &Pubkey, staking_account: &StakingAccount) -> Option<Pubkey> {
+    if staking_account.referrer == Pubkey::default() {
+        return None;
+    }
+
+    Some(referral_entry_address(&staking_account.referrer, &staking_account_address(user)))
+}
+
+/*
+ * Remaining accounts for rebuild_upline, found by following the referrer of each account.
+ * Only needed for caches that were never filled, the others are kept in sync by sync_upline.
+ */
+pub fn rebuild_upline_account_metas(
+    rpc: &RpcClient,
+    staking_account: &StakingAccount,
+) -> Result<Vec<AccountMeta>, ClientError> {
+    let mut metas = Vec::new();
+    let mut next_referrer = staking_account.referrer;
+
+    while next_referrer != Pubkey::default() && metas.len() < MAX_UPLINE_LEVELS {
+        let data = rpc.get_account_data(&next_referrer)?;
+        let referrer = StakingAccount::try_deserialize(&mut data.as_slice())
+            .map_err(|err| ClientError::from(ClientErrorKind::Custom(err.to_string())))?;
+
+        metas.push(AccountMeta::new_readonly(next_referrer, false));
+        next_referrer = referrer.referrer;
+    }
+
+    Ok(metas)
+}
+
+/*
+ * Accounts to sync after the referrer of `root` changed, parents before children.
+ * Each pair is (staking_account, referrer_staking_account) for one sync_upline call.
+ * Referrals are found with getProgramAccounts on the referral entries of each level.
+ */
+pub fn upline_sync_order(rpc: &RpcClient, root: &Pubkey) -> Result<Vec<(Pubkey, Pubkey)>, ClientError> {
+    let mut order = Vec::new();
+    let mut level = vec![*root];
+
+    for _ in 0..MAX_UPLINE_LEVELS {
+        let mut next_level = Vec::new();
+
+        for referrer in &level {
+            for referee in referees(rpc, referrer)? {
+                order.push((referee, *referrer));
+                next_level.push(referee);
+            }
+        }
+
+        if next_level.is_empty() {
+            break;
+        }
+
+        level = next_level;
+    }
+
+    Ok(order)
+}
+
+fn referees(rpc: &RpcClient, referrer: &Pubkey) -> Result<Vec<Pubkey>, ClientError> {
+    use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
+    use solana_client::rpc_filter::{Memcmp, RpcFilterType};
+    use staking::state::ReferralEntry;
+
+    let config = RpcProgramAccountsConfig {
+        filters: Some(vec![
+            RpcFilterType::DataSize(ReferralEntry::LEN as u64),
+            RpcFilterType::Memcmp(Memcmp::new_base58_encoded(8, referrer.as_ref())),
+        ]),
+        account_config: RpcAccountInfoConfig::default(),
+        ..RpcProgramAccountsConfig::default()
+    };
+
+    let accounts = rpc.get_program_accounts_with_config(&staking::ID, config)?;
+
+    let mut referees = Vec::with_capacity(accounts.len());
+
+    for (_, account) in accounts {
+        let entry = ReferralEntry::try_deserialize(&mut account.data.as_slice())
+            .map_err(|err| ClientError::from(ClientErrorKind::Custom(err.to_string())))?;
+
+        referees.push(entry.referee);
+    }
+
+    Ok(referees)
+}
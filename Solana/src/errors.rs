@@ -0,0 +1,189 @@
+use anchor_lang::prelude::*;
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Arithmetic overflow")]
+    Overflow,
+    #[msg("Arithmetic underflow")]
+    Underflow,
+    #[msg("Invalid amount")]
+    InvalidAmount,
+    #[msg("Invalid mint amount")]
+    InvalidMintAmount,
+    #[msg("Max supply reached")]
+    MaxSupplyReached,
+    #[msg("APY must be between 1000 and 100000 BPS")]
+    InvalidAPY,
+    #[msg("Deposit fee must not exceed 2500 BPS")]
+    InvalidDepositFee,
+    #[msg("Withdrawal fee must not exceed 2500 BPS")]
+    InvalidWithdrawalFee,
+    #[msg("Level percentage must not exceed 100")]
+    InvalidLevelPercentage,
+    #[msg("Only admin can perform this action")]
+    OnlyAdmin,
+    #[msg("Invalid treasury account")]
+    InvalidTreasury,
+    #[msg("Invalid user")]
+    InvalidUser,
+    #[msg("Claim too soon")]
+    ClaimTooSoon,
+    #[msg("Stake expired")]
+    StakeExpired,
+    #[msg("Address is blacklisted")]
+    Blacklisted,
+    #[msg("Airdrop already claimed")]
+    AirdropAlreadyClaimed,
+    #[msg("Only ten referral levels are supported")]
+    OnlyTenReferralsLevel,
+    #[msg("Referrer accounts should be provided")]
+    ShouldProvideReferrer,
+    #[msg("Staked accounts should be provided")]
+    ShouldProvideStakedAccounts,
+    #[msg("Referrer mismatch")]
+    ReferrerMismatch,
+    #[msg("Invalid referrer")]
+    InvalidReferrer,
+    #[msg("Referrer is not initialized")]
+    ReferrerNotInitialized,
+    #[msg("Referrer cannot be the user")]
+    ReferrerIsUser,
+    #[msg("User already has a referrer")]
+    AlreadyHaveReferrer,
+    #[msg("Failed to deserialize account")]
+    DeserializationError,
+    #[msg("Failed to serialize account")]
+    SerializationError,
+    #[msg("Transfer failed")]
+    TransferFailed,
+    #[msg("Stake has not matured yet")]
+    StakeNotMatured,
+    #[msg("Stake has already matured, use unstake")]
+    StakeMatured,
+    #[msg("Invalid early exit penalty schedule")]
+    InvalidEarlyExitPenalty,
+    #[msg("Invalid lock tier")]
+    InvalidTier,
+    #[msg("Lock tier is not active")]
+    InactiveTier,
+    #[msg("Lock duration must be positive")]
+    InvalidLockDuration,
+    #[msg("Config changes must be queued through the timelock")]
+    TimelockRequired,
+    #[msg("Effective time is earlier than the minimal timelock delay allows")]
+    TimelockTooShort,
+    #[msg("Config change is not due yet")]
+    TimelockNotReady,
+    #[msg("Config change is already due and can no longer be cancelled")]
+    TimelockAlreadyDue,
+    #[msg("Invalid timelock delay")]
+    InvalidTimelockDelay,
+    #[msg("Compound fee must not exceed 2500 BPS")]
+    InvalidCompoundFee,
+    #[msg("No rewards available to compound")]
+    NoRewardsToCompound,
+    #[msg("Staked account is provided more than once")]
+    DuplicateStakedAccount,
+    #[msg("Invalid staked account")]
+    InvalidStakedAccount,
+    #[msg("Positions must be under the same lock tier")]
+    TierMismatch,
+    #[msg("Should provide positions to merge")]
+    NothingToMerge,
+    #[msg("Split amount must be lower than the staked amount")]
+    InvalidSplitAmount,
+    #[msg("Position is already tokenized")]
+    PositionAlreadyTokenized,
+    #[msg("Position is tokenized, only its holder can use it")]
+    PositionTokenized,
+    #[msg("Should provide the position NFT")]
+    ShouldProvidePositionToken,
+    #[msg("Instruction is paused")]
+    Paused,
+    #[msg("Invalid pause flags")]
+    InvalidPauseFlags,
+    #[msg("Signer does not hold the required role")]
+    MissingRole,
+    #[msg("Invalid roles")]
+    InvalidRoles,
+    #[msg("Role registry is full")]
+    RoleRegistryFull,
+    #[msg("No pending roles to accept")]
+    NoPendingRoles,
+    #[msg("Invalid multisig signers or threshold")]
+    InvalidMultisig,
+    #[msg("Signer is not part of the multisig")]
+    NotMultisigSigner,
+    #[msg("Proposal is already approved by this signer")]
+    AlreadyApproved,
+    #[msg("Proposal is already executed")]
+    ProposalAlreadyExecuted,
+    #[msg("Proposal does not have enough approvals")]
+    ThresholdNotMet,
+    #[msg("Proposal does not match the instruction")]
+    ProposalMismatch,
+    #[msg("Multisig signers changed after the proposal was created")]
+    ProposalStale,
+    #[msg("Instruction must be executed through an approved proposal")]
+    MultisigRequired,
+    #[msg("Admin mint cap for this epoch reached")]
+    AdminMintCapReached,
+    #[msg("Invalid emissions budget")]
+    InvalidEmissionsBudget,
+    #[msg("Emissions budget exhausted, try again next epoch")]
+    EmissionsBudgetExhausted,
+    #[msg("Invalid reward vault")]
+    InvalidRewardVault,
+    #[msg("Reward vault does not hold enough tokens to pay the rewards")]
+    RewardVaultEmpty,
+    #[msg("Should provide the referral entry of the user")]
+    ShouldProvideReferralEntry,
+    #[msg("Referral entry does not belong to this referral link")]
+    InvalidReferralEntry,
+    #[msg("Referrer accounts do not cover the whole upline")]
+    IncompleteReferrerChain,
+    #[msg("Should provide the referral credit account")]
+    ShouldProvideReferralCredit,
+    #[msg("Referral credit was recorded for a different upline")]
+    ReferralCreditUplineChanged,
+    #[msg("Referral credit source is still active")]
+    ReferralCreditActive,
+    #[msg("Referral correction needs a non-zero delta, reason code and memo hash")]
+    InvalidReferralCorrection,
+    #[msg("Referral ranks must be ordered and unlock at most ten levels")]
+    InvalidReferralRanks,
+    #[msg("OTC commission levels add up to more than 100%")]
+    InvalidOtcCommission,
+    #[msg("Quote token accounts of the buyer and treasury should be provided")]
+    ShouldProvideQuoteAccounts,
+    #[msg("Commission accounts of the upline should be provided")]
+    ShouldProvideCommissionAccounts,
+    #[msg("Commission account does not belong to the referrer")]
+    InvalidCommissionAccount,
+    #[msg("Invalid OTC desk settings")]
+    InvalidOtcDesk,
+    #[msg("OTC price must be positive and newer than the latest one")]
+    InvalidOtcPrice,
+    #[msg("No OTC price available")]
+    OtcPriceUnavailable,
+    #[msg("OTC price oracle has not been updated recently enough")]
+    OtcPriceStale,
+    #[msg("OTC price is above the maximum price of the buyer")]
+    OtcPriceAboveMax,
+    #[msg("OTC purchase is below the minimum size")]
+    OtcPurchaseTooSmall,
+    #[msg("OTC purchase is above the maximum size")]
+    OtcPurchaseTooLarge,
+    #[msg("OTC purchase exceeds the daily cap of the wallet")]
+    OtcDailyCapExceeded,
+    #[msg("Account already has the current layout")]
+    AccountAlreadyMigrated,
+    #[msg("APY history is full, positions still relying on the oldest rate must checkpoint first")]
+    ApyScheduleFull,
+    #[msg("Lock tier changes need the tier registry")]
+    TierRegistryRequired,
+    #[msg("Referral credit does not belong to this position")]
+    InvalidReferralCredit,
+    #[msg("The legacy tier keeps the one-year lock of migrated positions")]
+    LegacyTierDurationFixed,
+}
@@ -0,0 +1,537 @@
+use anchor_lang::prelude::*;
+
+use crate::state::{
+    ConfigChange, EarlyExitPenalty, OtcCommission, OtcDeskSettings, ProposalAction, ReferralRank,
+};
+
+#[event]
+pub struct EarlyUnstaked {
+    pub user: Pubkey,
+    pub user_staked_account: Pubkey,
+    pub amount_staked: u64,
+    pub penalty: u64,
+    pub penalty_bps: u16,
+    pub penalty_burned: bool,
+    pub rewards: u64,
+    pub withdrawal_fee: u64,                  // Charged on the released principal and the rewards
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ConfigChangeQueued {
+    pub id: u64,
+    pub change: ConfigChange,
+    pub proposer: Pubkey,
+    pub queued_at: i64,
+    pub effective_at: i64,
+}
+
+#[event]
+pub struct ConfigChangeExecuted {
+    pub id: u64,
+    pub change: ConfigChange,
+    pub executor: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ConfigChangeCancelled {
+    pub id: u64,
+    pub change: ConfigChange,
+    pub authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AddressBlacklisted {
+    pub address: Pubkey,
+    pub reason: u8,
+    pub admin: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct BlacklistReasonUpdated {
+    pub address: Pubkey,
+    pub old_reason: u8,
+    pub new_reason: u8,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AddressUnblacklisted {
+    pub address: Pubkey,
+    pub reason: u8,
+    pub admin: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PauseUpdated {
+    pub old_flags: u8,
+    pub new_flags: u8,
+    pub authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RoleGrantProposed {
+    pub member: Pubkey,
+    pub roles: u8,
+    pub authority: Pubkey,                    // Admin, or the executor of an approved proposal
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RoleGranted {
+    pub member: Pubkey,
+    pub roles: u8,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RoleRevoked {
+    pub member: Pubkey,
+    pub roles: u8,
+    pub authority: Pubkey,                    // Admin, or the executor of an approved proposal
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MultisigUpdated {
+    pub signers: Vec<Pubkey>,
+    pub threshold: u8,
+    pub nonce: u32,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ProposalCreated {
+    pub id: u64,
+    pub action: ProposalAction,
+    pub proposer: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ProposalApproved {
+    pub id: u64,
+    pub signer: Pubkey,
+    pub approvals: u8,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ProposalExecuted {
+    pub id: u64,
+    pub action: ProposalAction,
+    pub executor: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct EmissionsBudgetUpdated {
+    pub epoch_duration: i64,
+    pub reward_cap: u64,
+    pub admin_mint_cap: u64,
+    pub epoch_start: i64,
+    pub authority: Pubkey,
+}
+
+#[event]
+pub struct RewardsFunded {
+    pub funder: Pubkey,
+    pub amount: u64,
+    pub vault_balance: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct GlobalConfigInitialized {
+    pub admin: Pubkey,
+    pub treasury: Pubkey,
+    pub mint: Pubkey,
+    pub apy_bps: u32,
+    pub deposit_fee_bps: u16,
+    pub withdrawal_fee_bps: u16,
+    pub lvl_percentages: [u8; 10],
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct StakingAccountInitialized {
+    pub user: Pubkey,
+    pub staking_account: Pubkey,
+    pub referrer: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct Staked {
+    pub user: Pubkey,
+    pub user_staked_account: Pubkey,
+    pub identifier: u64,
+    pub tier_id: u8,
+    pub amount: u64,
+    pub deposit_fee: u64,
+    pub net_amount: u64,
+    pub lock_duration: i64,
+    pub referrer: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AirdropStaked {
+    pub user: Pubkey,
+    pub user_staked_account: Pubkey,
+    pub identifier: u64,
+    pub tier_id: u8,
+    pub amount: u64,
+    pub lock_duration: i64,
+    pub operator: Pubkey,
+    pub by_admin: bool,                       // Staked through stake_by_admin, which skips the one airdrop per user check
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct StakeIncreased {
+    pub user: Pubkey,
+    pub user_staked_account: Pubkey,
+    pub amount: u64,
+    pub deposit_fee: u64,
+    pub net_amount: u64,
+    pub amount_staked: u64,
+    pub settled_rewards: u64,
+    pub lock_duration: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PositionsMerged {
+    pub user: Pubkey,
+    pub target: Pubkey,
+    pub merged: Vec<Pubkey>,
+    pub amount_staked: u64,
+    pub maturity: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PositionSplit {
+    pub user: Pubkey,
+    pub source: Pubkey,
+    pub new_position: Pubkey,
+    pub amount: u64,
+    pub remaining: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PositionTokenized {
+    pub user: Pubkey,
+    pub user_staked_account: Pubkey,
+    pub position_mint: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ReferralCommissionCredited {
+    pub referrer: Pubkey,                     // Staking account of the referrer
+    pub level: u8,
+    pub staked_amount: u64,
+    pub commission: u64,
+    pub settled_rewards: u64,                 // Rewards accrued at the old rate, moved to deferred_rewards
+    pub total_referral_staked: u64,
+    pub daily_referral_rewards: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RewardsClaimed {
+    pub user: Pubkey,
+    pub positions: Vec<Pubkey>,
+    pub rewards: u64,
+    pub paid: u64,
+    pub deferred: u64,
+    pub withdrawal_fee: u64,
+    pub from_vault: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ReferralRewardsClaimed {
+    pub user: Pubkey,
+    pub staking_account: Pubkey,
+    pub rewards: u64,
+    pub paid: u64,
+    pub deferred: u64,
+    pub from_vault: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RewardsCompounded {
+    pub user: Pubkey,
+    pub positions: Vec<Pubkey>,
+    pub new_position: Pubkey,
+    pub tier_id: u8,
+    pub rewards: u64,
+    pub compounded: u64,
+    pub deferred: u64,
+    pub compound_fee: u64,
+    pub net_amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct Unstaked {
+    pub user: Pubkey,
+    pub holder: Pubkey,
+    pub user_staked_account: Pubkey,
+    pub amount_staked: u64,
+    pub rewards: u64,
+    pub deferred: u64,
+    pub withdrawal_fee: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ReferrerAdded {
+    pub user: Pubkey,
+    pub referrer: Pubkey,                     // Staking account of the referrer
+    pub previous_referrer: Pubkey,            // Staking account of the replaced referrer, default if none
+    pub staked_amount: u64,
+    pub authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ReferralInfoAdjusted {
+    pub staking_account: Pubkey,
+    pub correction_index: u64,                // Position of the correction in the account's history
+    pub reason: u8,
+    pub memo_hash: [u8; 32],
+    pub total_referral_staked_delta: i64,
+    pub daily_referral_rewards_delta: i64,
+    pub old_total_referral_staked: u64,
+    pub new_total_referral_staked: u64,
+    pub old_daily_referral_rewards: u64,
+    pub new_daily_referral_rewards: u64,
+    pub settled_rewards: u64,                 // Rewards accrued at the old rate, moved to deferred_rewards
+    pub authority: Pubkey,                    // Operator, or proposer when approved through the multisig
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OtcPurchase {
+    pub buyer: Pubkey,
+    pub user_staked_account: Pubkey,
+    pub tier_id: u8,
+    pub sol_amount: u64,
+    pub toon_amount: u64,
+    pub price: u64,                           // Quote units per whole token the purchase was priced at
+    pub treasury_amount: u64,
+    pub commission_amount: u64,               // Paid to the upline wallets, see OtcCommissionPaid
+    pub quote_mint: Pubkey,                   // Default key when paid in SOL
+    pub operator: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct TokensMinted {
+    pub authority: Pubkey,
+    pub destination: Pubkey,
+    pub amount: u64,
+    pub supply: u64,                          // Mint supply after minting
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct FreezeAuthorityRevoked {
+    pub mint: Pubkey,
+    pub authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OwnershipTransferStarted {
+    pub admin: Pubkey,
+    pub pending_admin: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OwnershipTransferAccepted {
+    pub previous_admin: Pubkey,
+    pub new_admin: Pubkey,
+    pub timestamp: i64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq)]
+pub enum FeeKind {
+    Withdrawal,
+    Compound,
+}
+
+#[event]
+pub struct ReferralApyUpdated {
+    pub old_apy_bps: u32,
+    pub new_apy_bps: u32,
+    pub authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct FeesUpdated {
+    pub kind: FeeKind,
+    pub old_fee_bps: u16,
+    pub new_fee_bps: u16,
+    pub authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct LevelPercentagesUpdated {
+    pub old_percentages: [u8; 10],
+    pub new_percentages: [u8; 10],
+    pub authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct TimelockDelayUpdated {
+    pub old_delay: i64,
+    pub new_delay: i64,
+    pub authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct TreasuryUpdated {
+    pub old_treasury: Pubkey,
+    pub new_treasury: Pubkey,
+    pub authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AccrualModeUpdated {
+    pub continuous_accrual: bool,
+    pub authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RewardModeUpdated {
+    pub vault_rewards: bool,
+    pub authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct EarlyExitPenaltyUpdated {
+    pub old_penalty: EarlyExitPenalty,
+    pub new_penalty: EarlyExitPenalty,
+    pub authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct LockTierUpdated {
+    pub tier_id: u8,
+    pub duration: i64,
+    pub apy_bps: u32,
+    pub deposit_fee_bps: u16,
+    pub active: bool,
+    pub authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ReferralLinkCreated {
+    pub referrer: Pubkey,                     // Staking account of the referrer
+    pub referee: Pubkey,                      // Staking account of the referred user
+    pub referral_entry: Pubkey,
+    pub level: u8,
+    pub volume: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct UplineSynced {
+    pub staking_account: Pubkey,
+    pub referrer: Pubkey,
+    pub depth: u8,                            // Number of levels in the new upline
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ReferralCommissionUnwound {
+    pub referrer: Pubkey,                     // Staking account of the referrer
+    pub level: u8,
+    pub source: Pubkey,                       // Position or referral entry the credit belonged to
+    pub volume: u64,
+    pub commission: u64,
+    pub settled_rewards: u64,                 // Rewards accrued at the old rate, moved to deferred_rewards
+    pub total_referral_staked: u64,
+    pub daily_referral_rewards: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ReferralRanksUpdated {
+    pub ranks: Vec<ReferralRank>,             // New rank table, lowest rank first, empty unlocks every level
+    pub authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OtcCommissionPaid {
+    pub buyer: Pubkey,
+    pub referrer: Pubkey,                     // Staking account of the referrer
+    pub recipient: Pubkey,                    // Wallet, or its token account of the quote mint
+    pub level: u8,
+    pub amount: u64,
+    pub quote_mint: Pubkey,                   // Default key when paid in SOL
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OtcCommissionUpdated {
+    pub old_commission: OtcCommission,
+    pub new_commission: OtcCommission,
+    pub authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OtcDeskUpdated {
+    pub old_settings: OtcDeskSettings,
+    pub new_settings: OtcDeskSettings,
+    pub authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OtcPricePushed {
+    pub price: u64,                           // Quote units per whole token
+    pub feeder: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AccountMigrated {
+    pub account: Pubkey,
+    pub old_len: u32,                         // Data length in the old layout
+    pub new_len: u32,
+    pub payer: Pubkey,                        // Paid the rent for the grown account
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RewardsCheckpointed {
+    pub account: Pubkey,                      // Position or staking account
+    pub settled: u64,                         // Rewards moved to pending or deferred rewards
+    pub cranker: Pubkey,
+    pub timestamp: i64,
+}
@@ -1,5 +1,11 @@
 use crate::{
-    constants, errors::ErrorCode, state::{GlobalConfig, StakingAccount}
+    errors::ErrorCode,
+    events::{ReferralLinkCreated, ReferrerAdded, UplineSynced},
+    instructions::apply_referral_rewards_for_all_referrer,
+    state::{
+        BlacklistEntry, GlobalConfig, ReferralCredit, ReferralCreditSource, ReferralEntry, RoleRegistry,
+        StakingAccount, MAX_UPLINE_LEVELS,
+    },
 };
 
 use anchor_lang::prelude::*;
@@ -8,11 +14,18 @@ use anchor_lang::prelude::*;
 #[instruction(new_referrer: Pubkey)]
 pub struct AddReferrer<'info> {
     #[account(
+        mut,
         seeds = [b"config"],
         bump = global_config.config_bumps
     )]
     pub global_config: Account<'info, GlobalConfig>,
 
+    #[account(
+        seeds = [b"roles"],
+        bump = role_registry.bump
+    )]
+    pub role_registry: Account<'info, RoleRegistry>,
+
     #[account(
         mut,
         seeds = [b"staking", user_key.key().as_ref()],
@@ -26,15 +39,61 @@ pub struct AddReferrer<'info> {
     )]
     pub referrer_staking_account: Account<'info, StakingAccount>,
 
+    #[account(
+        init,
+        payer = user,
+        space = ReferralEntry::LEN,
+        seeds = [b"referral", referrer_staking_account.key().as_ref(), staking_account.key().as_ref()],
+        bump
+    )]
+    pub referral_entry: Account<'info, ReferralEntry>,
+
+    // Commission credited to the upline for the stake already held, required when some level was paid
+    #[account(
+        init,
+        payer = user,
+        space = ReferralCredit::LEN,
+        seeds = [b"referral-credit", referral_entry.key().as_ref()],
+        bump
+    )]
+    pub referral_credit: Option<Account<'info, ReferralCredit>>,
+
+    // Link of the new referrer to its own referrer, required when the new referrer has one
+    #[account(
+        seeds = [b"referral", referrer_staking_account.referrer.as_ref(), referrer_staking_account.key().as_ref()],
+        bump = referrer_referral_entry.bump
+    )]
+    pub referrer_referral_entry: Option<Account<'info, ReferralEntry>>,
+
+    // Link to the replaced referrer, required when an operator changes an existing referrer
+    #[account(
+        mut,
+        close = user,
+        seeds = [b"referral", staking_account.referrer.as_ref(), staking_account.key().as_ref()],
+        bump = previous_referral_entry.bump
+    )]
+    pub previous_referral_entry: Option<Account<'info, ReferralEntry>>,
+
     /// CHECK: user account
     pub user_key: UncheckedAccount<'info>,
 
+    /// CHECK: blacklist entry of the user, empty unless the user is blacklisted
+    #[account(
+        seeds = [b"blacklist", user_key.key().as_ref()],
+        bump,
+        constraint = !BlacklistEntry::is_blacklisted(&blacklist_entry) @ ErrorCode::Blacklisted
+    )]
+    pub blacklist_entry: UncheckedAccount<'info>,
+
     #[account(mut)]
     pub user: Signer<'info>,
+    pub system_program: Program<'info, System>,
 }
 
 pub fn add_referrer(ctx: Context<AddReferrer>, new_referrer: Pubkey) -> Result<()> {
-    let global_config = &ctx.accounts.global_config;
+    ctx.accounts.global_config.require_not_paused(GlobalConfig::PAUSE_STAKE)?;
+
+    let global_config = &mut ctx.accounts.global_config;
     let staking_account = &mut ctx.accounts.staking_account;
     let referrer_account = &ctx.accounts.referrer_staking_account;
     let signer_key = ctx.accounts.user.key();
@@ -44,7 +103,10 @@ pub fn add_referrer(ctx: Context<AddReferrer>, new_referrer: Pubkey) -> Result<(
     require!(new_referrer != staking_account.user, ErrorCode::InvalidReferrer);
 
     if signer_key != user_key {
-        require!(signer_key == global_config.admin, ErrorCode::OnlyAdmin);
+        require!(
+            ctx.accounts.role_registry.has_role(&signer_key, RoleRegistry::REFERRAL_OPERATOR),
+            ErrorCode::MissingRole
+        );
     } else {
         require!(staking_account.referrer == Pubkey::default(), ErrorCode::AlreadyHaveReferrer);
     }
@@ -52,84 +114,156 @@ pub fn add_referrer(ctx: Context<AddReferrer>, new_referrer: Pubkey) -> Result<(
     let staked_amount = staking_account.total_staked;
 
     let remaining_accounts = ctx.remaining_accounts;
+    let new_upline = StakingAccount::upline_below(referrer_account.key(), referrer_account);
 
-    require!(remaining_accounts.len() > 0, ErrorCode::ShouldProvideReferrer);
-
-    if remaining_accounts.len() > global_config.lvl_percentages.len() {
-        return Err(ErrorCode::OnlyTenReferralsLevel.into());
-    }
-
-    apply_referral_rewards_for_all_referrer(
+    let credits = apply_referral_rewards_for_all_referrer(
         remaining_accounts,
         global_config,
+        &new_upline,
         staked_amount,
-        referrer_account.key(),
-        staking_account.key()
+    )?;
+
+    let previous_referrer = staking_account.referrer;
+
+    if previous_referrer != Pubkey::default() {
+        require!(
+            ctx.accounts.previous_referral_entry.is_some(),
+            ErrorCode::ShouldProvideReferralEntry
+        );
+    }
+
+    let parent_entry = ctx.accounts.referrer_referral_entry.as_deref();
+
+    if referrer_account.referrer != Pubkey::default() {
+        require!(parent_entry.is_some(), ErrorCode::ShouldProvideReferralEntry);
+    }
+
+    let now = Clock::get()?.unix_timestamp;
+    let referral_entry = &mut ctx.accounts.referral_entry;
+
+    referral_entry.referrer = referrer_account.key();
+    referral_entry.referee = staking_account.key();
+    referral_entry.level = ReferralEntry::level_below(parent_entry);
+    referral_entry.created_at = now;
+    referral_entry.volume = staked_amount;
+    referral_entry.bump = ctx.bumps.referral_entry;
+
+    emit!(ReferralLinkCreated {
+        referrer: referral_entry.referrer,
+        referee: referral_entry.referee,
+        referral_entry: referral_entry.key(),
+        level: referral_entry.level,
+        volume: staked_amount,
+        timestamp: now,
+    });
+
+    ReferralCredit::record(
+        ctx.accounts.referral_credit.as_deref_mut(),
+        ctx.bumps.referral_credit,
+        (ctx.accounts.referral_entry.key(), ReferralCreditSource::Link),
+        staking_account.key(),
+        &new_upline,
+        &credits,
     )?;
 
     staking_account.referrer = referrer_account.key();
+    staking_account.upline = new_upline;
+
+    emit!(ReferrerAdded {
+        user: user_key,
+        referrer: referrer_account.key(),
+        previous_referrer,
+        staked_amount,
+        authority: signer_key,
+        timestamp: now,
+    });
 
     msg!("Referrer added successfully {}", new_referrer);
 
     Ok(())
 }
 
-fn apply_referral_rewards_for_all_referrer(
-    remaining_accounts: &[AccountInfo],
-    global_config: &Account<GlobalConfig>,
-    stake_amount: u64,
-    first_referrer: Pubkey,
-    referral: Pubkey
-) -> Result<()> {
-    let mut next_referrer = first_referrer;
+#[derive(Accounts)]
+pub struct SyncUpline<'info> {
+    #[account(mut)]
+    pub staking_account: Account<'info, StakingAccount>,
+
+    #[account(address = staking_account.referrer @ ErrorCode::ReferrerMismatch)]
+    pub referrer_staking_account: Account<'info, StakingAccount>,
+}
+
+/*
+ * Rebuilds the cached upline from the referrer's own cache.
+ * Needed by the referrals of an account whose referrer was changed, top-down one level at a time.
+ * Permissionless, the result only depends on data already stored on chain.
+ */
+pub fn sync_upline(ctx: Context<SyncUpline>) -> Result<()> {
+    let staking_account = &mut ctx.accounts.staking_account;
+    let referrer_account = &ctx.accounts.referrer_staking_account;
 
-    for (index, account_info) in remaining_accounts.iter().enumerate() {
-        if next_referrer != account_info.key() {
-            return Err(ErrorCode::ReferrerMismatch.into());
-        }
+    staking_account.upline = StakingAccount::upline_below(referrer_account.key(), referrer_account);
 
-        let referrer_account = &mut account_info.try_borrow_mut_data()?[..];
+    emit!(UplineSynced {
+        staking_account: staking_account.key(),
+        referrer: referrer_account.key(),
+        depth: StakingAccount::upline_depth(&staking_account.upline) as u8,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
 
-        let mut referrer_data: StakingAccount = StakingAccount::try_deserialize(&mut &referrer_account[..])
-            .map_err(|_| ErrorCode::DeserializationError)?;
+    msg!("Upline synced for {}", staking_account.key());
 
-        next_referrer = referrer_data.referrer;
+    Ok(())
+}
 
-        if index == 0 {
-            if referrer_data.referral_history.len() < constants::MAX_REFERRAL {
-                referrer_data.referral_history.push(referral);
-            }
-        }
+#[derive(Accounts)]
+pub struct RebuildUpline<'info> {
+    #[account(mut)]
+    pub staking_account: Account<'info, StakingAccount>,
+}
 
-        let commission_percentage = global_config.lvl_percentages.get(index).copied().unwrap_or(0);
+/*
+ * Rebuilds the cached upline by following the referrer of each account, for trees whose caches
+ * are empty, like those of accounts created before the cache existed.
+ * Remaining accounts are the referrer staking accounts in order, up to the top of the tree or
+ * MAX_UPLINE_LEVELS. Permissionless, every link is read from the accounts themselves.
+ */
+pub fn rebuild_upline(ctx: Context<RebuildUpline>) -> Result<()> {
+    let staking_account = &mut ctx.accounts.staking_account;
+    let referrer_accounts = ctx.remaining_accounts;
 
-        let commission = stake_amount
-            .checked_mul(commission_percentage as u64)
-            .ok_or(ErrorCode::Overflow)?
-            .checked_div(100)
-            .ok_or(ErrorCode::Overflow)?;
+    require!(referrer_accounts.len() <= MAX_UPLINE_LEVELS, ErrorCode::OnlyTenReferralsLevel);
 
-        if commission > 0 {
-            if referrer_data.total_referral_staked == 0 {
-                referrer_data.last_referral_rewards_claimed = Clock::get()?.unix_timestamp;
-            }
+    let mut upline = [Pubkey::default(); MAX_UPLINE_LEVELS];
+    let mut next_referrer = staking_account.referrer;
 
-            referrer_data.total_referral_staked = referrer_data
-                .total_referral_staked
-                .checked_add(stake_amount)
-                .ok_or(ErrorCode::Overflow)?;
+    for (index, account_info) in referrer_accounts.iter().enumerate() {
+        require!(next_referrer != Pubkey::default(), ErrorCode::ReferrerMismatch);
+        require_keys_eq!(account_info.key(), next_referrer, ErrorCode::ReferrerMismatch);
+        require_keys_eq!(*account_info.owner, crate::ID, ErrorCode::InvalidReferrer);
 
-            referrer_data.daily_referral_rewards = referrer_data
-                .daily_referral_rewards
-                .checked_add(commission)
-                .ok_or(ErrorCode::Overflow)?;
-        }
+        let referrer_data = {
+            let data = account_info.try_borrow_data()?;
+            StakingAccount::try_deserialize(&mut &data[..]).map_err(|_| ErrorCode::DeserializationError)?
+        };
 
-        let mut cursor = std::io::Cursor::new(referrer_account);
+        upline[index] = account_info.key();
+        next_referrer = referrer_data.referrer;
+    }
 
-        StakingAccount::try_serialize(&referrer_data, &mut cursor)
-            .map_err(|_| ErrorCode::SerializationError)?;
+    if referrer_accounts.len() < MAX_UPLINE_LEVELS {
+        require!(next_referrer == Pubkey::default(), ErrorCode::IncompleteReferrerChain);
     }
 
+    staking_account.upline = upline;
+
+    emit!(UplineSynced {
+        staking_account: staking_account.key(),
+        referrer: staking_account.referrer,
+        depth: StakingAccount::upline_depth(&upline) as u8,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    msg!("Upline rebuilt for {}", staking_account.key());
+
     Ok(())
 }
@@ -0,0 +1,216 @@
+use anchor_lang::prelude::*;
+
+use crate::{
+    errors::ErrorCode,
+    events::ReferralInfoAdjusted,
+    instructions::authorize_privileged,
+    state::{
+        GlobalConfig, Multisig, Proposal, ProposalAction, ReferralCorrection, ReferralCorrectionLog,
+        RoleRegistry, StakingAccount,
+    },
+};
+
+#[derive(Accounts)]
+pub struct AdjustReferralInfo<'info> {
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = global_config.config_bumps
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(mut)]
+    pub staking_account: Account<'info, StakingAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = ReferralCorrectionLog::LEN,
+        seeds = [b"referral-corrections", staking_account.key().as_ref()],
+        bump
+    )]
+    pub referral_correction_log: Account<'info, ReferralCorrectionLog>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = ReferralCorrection::LEN,
+        seeds = [
+            b"referral-correction",
+            staking_account.key().as_ref(),
+            referral_correction_log.correction_count.to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub referral_correction: Account<'info, ReferralCorrection>,
+
+    #[account(
+        seeds = [b"roles"],
+        bump = role_registry.bump
+    )]
+    pub role_registry: Account<'info, RoleRegistry>,
+
+    #[account(
+        seeds = [b"multisig"],
+        bump = multisig.bump
+    )]
+    pub multisig: Option<Account<'info, Multisig>>,
+
+    // Approved proposal, replaces the role check of the signer
+    #[account(
+        mut,
+        seeds = [b"proposal", proposal.id.to_le_bytes().as_ref()],
+        bump = proposal.bump
+    )]
+    pub proposal: Option<Account<'info, Proposal>>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+pub fn validate_referral_adjustment(
+    total_referral_staked_delta: i64,
+    daily_referral_rewards_delta: i64,
+    reason: u8,
+    memo_hash: &[u8; 32],
+) -> Result<()> {
+    require!(
+        (total_referral_staked_delta != 0 || daily_referral_rewards_delta != 0)
+            && reason != 0
+            && *memo_hash != [0; 32],
+        ErrorCode::InvalidReferralCorrection
+    );
+
+    Ok(())
+}
+
+/*
+ * Corrects referral balances by a delta instead of overwriting them:
+ * 1. Rewards accrued at the current rate are settled into deferred_rewards
+ * 2. The deltas are applied, balances can not go below zero
+ * 3. The correction is kept in its own ReferralCorrection record, counted in the account's
+ *    ReferralCorrectionLog and emitted with before/after values
+ */
+pub fn adjust_referral_info(
+    ctx: Context<AdjustReferralInfo>,
+    total_referral_staked_delta: i64,
+    daily_referral_rewards_delta: i64,
+    reason: u8,
+    memo_hash: [u8; 32],
+) -> Result<()> {
+    validate_referral_adjustment(
+        total_referral_staked_delta,
+        daily_referral_rewards_delta,
+        reason,
+        &memo_hash,
+    )?;
+
+    authorize_privileged(
+        &ctx.accounts.global_config,
+        &ctx.accounts.role_registry,
+        &ctx.accounts.authority.key(),
+        RoleRegistry::REFERRAL_OPERATOR,
+        ctx.accounts.multisig.as_deref(),
+        ctx.accounts.proposal.as_deref_mut(),
+        ProposalAction::AdjustReferralInfo {
+            staking_account: ctx.accounts.staking_account.key(),
+            total_referral_staked_delta,
+            daily_referral_rewards_delta,
+            reason,
+            memo_hash,
+        },
+    )?;
+
+    let now = Clock::get()?.unix_timestamp;
+    let authority = ctx
+        .accounts
+        .proposal
+        .as_ref()
+        .map_or(ctx.accounts.authority.key(), |proposal| proposal.proposer);
+    let staking_account = &mut ctx.accounts.staking_account;
+
+    let checkpoint = staking_account.referral_checkpoint();
+    let settled_rewards = staking_account.settle_referral_rewards(&ctx.accounts.global_config, now)?;
+
+    // Nothing accrued while the rate was zero, the new rate starts now
+    if staking_account.daily_referral_rewards == 0 {
+        staking_account.last_referral_rewards_claimed = now;
+    }
+
+    let old_total_referral_staked = staking_account.total_referral_staked;
+    let old_daily_referral_rewards = staking_account.daily_referral_rewards;
+
+    staking_account.total_referral_staked =
+        apply_delta(old_total_referral_staked, total_referral_staked_delta)?;
+    staking_account.daily_referral_rewards =
+        apply_delta(old_daily_referral_rewards, daily_referral_rewards_delta)?;
+    staking_account.repin_referral_checkpoint(&mut ctx.accounts.global_config.apy_history, checkpoint)?;
+
+    let referral_correction_log = &mut ctx.accounts.referral_correction_log;
+    let correction_index = referral_correction_log.correction_count;
+
+    referral_correction_log.staking_account = staking_account.key();
+    referral_correction_log.correction_count = correction_index
+        .checked_add(1)
+        .ok_or(ErrorCode::Overflow)?;
+    referral_correction_log.net_total_referral_staked = referral_correction_log
+        .net_total_referral_staked
+        .checked_add(total_referral_staked_delta)
+        .ok_or(ErrorCode::Overflow)?;
+    referral_correction_log.net_daily_referral_rewards = referral_correction_log
+        .net_daily_referral_rewards
+        .checked_add(daily_referral_rewards_delta)
+        .ok_or(ErrorCode::Overflow)?;
+    referral_correction_log.bump = ctx.bumps.referral_correction_log;
+
+    let referral_correction = &mut ctx.accounts.referral_correction;
+
+    referral_correction.staking_account = staking_account.key();
+    referral_correction.correction_index = correction_index;
+    referral_correction.total_referral_staked_delta = total_referral_staked_delta;
+    referral_correction.daily_referral_rewards_delta = daily_referral_rewards_delta;
+    referral_correction.reason = reason;
+    referral_correction.memo_hash = memo_hash;
+    referral_correction.authority = authority;
+    referral_correction.corrected_at = now;
+    referral_correction.bump = ctx.bumps.referral_correction;
+
+    emit!(ReferralInfoAdjusted {
+        staking_account: staking_account.key(),
+        correction_index,
+        reason,
+        memo_hash,
+        total_referral_staked_delta,
+        daily_referral_rewards_delta,
+        old_total_referral_staked,
+        new_total_referral_staked: staking_account.total_referral_staked,
+        old_daily_referral_rewards,
+        new_daily_referral_rewards: staking_account.daily_referral_rewards,
+        settled_rewards,
+        authority,
+        timestamp: now,
+    });
+
+    msg!(
+        "Referral info adjusted by {} staked, {} daily rewards, reason {}",
+        total_referral_staked_delta,
+        daily_referral_rewards_delta,
+        reason
+    );
+
+    Ok(())
+}
+
+fn apply_delta(value: u64, delta: i64) -> Result<u64> {
+    let adjusted = if delta >= 0 {
+        value.checked_add(delta as u64).ok_or(ErrorCode::Overflow)?
+    } else {
+        value
+            .checked_sub(delta.unsigned_abs())
+            .ok_or(ErrorCode::Underflow)?
+    };
+
+    Ok(adjusted)
+}
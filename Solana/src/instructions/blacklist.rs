@@ -0,0 +1,147 @@
+use crate::errors::ErrorCode;
+use crate::events::{AddressBlacklisted, AddressUnblacklisted, BlacklistReasonUpdated};
+use crate::state::{BlacklistEntry, GlobalConfig, RoleRegistry};
+use anchor_lang::prelude::*;
+
+#[derive(Accounts)]
+#[instruction(address: Pubkey)]
+pub struct AddToBlacklist<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = global_config.config_bumps
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = BlacklistEntry::LEN,
+        seeds = [b"blacklist", address.as_ref()],
+        bump
+    )]
+    pub blacklist_entry: Account<'info, BlacklistEntry>,
+
+    #[account(
+        seeds = [b"roles"],
+        bump = role_registry.bump
+    )]
+    pub role_registry: Account<'info, RoleRegistry>,
+
+    #[account(
+        mut,
+        constraint = role_registry.has_role(&authority.key(), RoleRegistry::CONFIG_MANAGER) @ ErrorCode::MissingRole
+    )]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+pub fn add_to_blacklist(ctx: Context<AddToBlacklist>, address: Pubkey, reason: u8) -> Result<()> {
+    let blacklist_entry = &mut ctx.accounts.blacklist_entry;
+    let now = Clock::get()?.unix_timestamp;
+
+    blacklist_entry.address = address;
+    blacklist_entry.reason = reason;
+    blacklist_entry.added_by = ctx.accounts.authority.key();
+    blacklist_entry.added_at = now;
+    blacklist_entry.bump = ctx.bumps.blacklist_entry;
+
+    emit!(AddressBlacklisted {
+        address,
+        reason,
+        admin: blacklist_entry.added_by,
+        timestamp: now,
+    });
+
+    msg!("Address blacklisted: {}, reason: {}", address, reason);
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct UpdateBlacklist<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = global_config.config_bumps
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"blacklist", blacklist_entry.address.as_ref()],
+        bump = blacklist_entry.bump
+    )]
+    pub blacklist_entry: Account<'info, BlacklistEntry>,
+
+    #[account(
+        seeds = [b"roles"],
+        bump = role_registry.bump
+    )]
+    pub role_registry: Account<'info, RoleRegistry>,
+
+    #[account(
+        mut,
+        constraint = role_registry.has_role(&authority.key(), RoleRegistry::CONFIG_MANAGER) @ ErrorCode::MissingRole
+    )]
+    pub authority: Signer<'info>,
+}
+
+pub fn update_blacklist_reason(ctx: Context<UpdateBlacklist>, reason: u8) -> Result<()> {
+    let blacklist_entry = &mut ctx.accounts.blacklist_entry;
+    let old_reason = blacklist_entry.reason;
+
+    blacklist_entry.reason = reason;
+
+    emit!(BlacklistReasonUpdated {
+        address: blacklist_entry.address,
+        old_reason,
+        new_reason: reason,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    msg!("Blacklist reason updated for {}: {}", blacklist_entry.address, reason);
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct RemoveFromBlacklist<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = global_config.config_bumps
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    // Closing the entry is what lifts the blacklist
+    #[account(
+        mut,
+        close = authority,
+        seeds = [b"blacklist", blacklist_entry.address.as_ref()],
+        bump = blacklist_entry.bump
+    )]
+    pub blacklist_entry: Account<'info, BlacklistEntry>,
+
+    #[account(
+        seeds = [b"roles"],
+        bump = role_registry.bump
+    )]
+    pub role_registry: Account<'info, RoleRegistry>,
+
+    #[account(
+        mut,
+        constraint = role_registry.has_role(&authority.key(), RoleRegistry::CONFIG_MANAGER) @ ErrorCode::MissingRole
+    )]
+    pub authority: Signer<'info>,
+}
+
+pub fn remove_from_blacklist(ctx: Context<RemoveFromBlacklist>) -> Result<()> {
+    let blacklist_entry = &ctx.accounts.blacklist_entry;
+
+    emit!(AddressUnblacklisted {
+        address: blacklist_entry.address,
+        reason: blacklist_entry.reason,
+        admin: ctx.accounts.authority.key(),
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    msg!("Address removed from blacklist: {}", blacklist_entry.address);
+    Ok(())
+}
@@ -0,0 +1,113 @@
+use crate::{
+    errors::ErrorCode,
+    events::RewardsCheckpointed,
+    state::{GlobalConfig, StakingAccount, TierRegistry, UserStakedAccount},
+};
+
+use anchor_lang::prelude::*;
+use anchor_spl::token::TokenAccount;
+
+#[derive(Accounts)]
+pub struct CheckpointPosition<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = global_config.config_bumps
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"tiers"],
+        bump = tier_registry.load()?.bump
+    )]
+    pub tier_registry: AccountLoader<'info, TierRegistry>,
+
+    #[account(mut)]
+    pub user_staked_account: Account<'info, UserStakedAccount>,
+
+    // Holder's position NFT account, required only when the holder checkpoints a tokenized position
+    pub position_token_account: Option<Account<'info, TokenAccount>>,
+
+    pub cranker: Signer<'info>,
+}
+
+/*
+ * Settles a position into pending_rewards so its checkpoint no longer relies on old APY changes.
+ * Nothing is paid, the settled rewards stay on the position for its next claim.
+ * In daily mode settling restarts the claim period, so anyone but the holder may only run it
+ * for an idle position that blocks new changes of a full tier history.
+ */
+pub fn checkpoint_position(ctx: Context<CheckpointPosition>) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let user_staked_account = &mut ctx.accounts.user_staked_account;
+
+    let mut tier_registry = ctx.accounts.tier_registry.load_mut()?;
+    let tier = tier_registry.get_tier_mut(user_staked_account.tier_id)?;
+
+    require!(
+        user_staked_account.is_authorized(
+            &ctx.accounts.cranker.key(),
+            ctx.accounts.position_token_account.as_deref()
+        ) || tier.apy_history.is_blocked_by(user_staked_account.last_claimed_timestamp),
+        ErrorCode::InvalidUser
+    );
+
+    let settled = user_staked_account.settle_rewards(
+        tier,
+        ctx.accounts.global_config.continuous_accrual,
+        now,
+    )?;
+
+    emit!(RewardsCheckpointed {
+        account: user_staked_account.key(),
+        settled,
+        cranker: ctx.accounts.cranker.key(),
+        timestamp: now,
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct CheckpointReferralRewards<'info> {
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = global_config.config_bumps
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(mut)]
+    pub staking_account: Account<'info, StakingAccount>,
+
+    pub cranker: Signer<'info>,
+}
+
+// Same for referral rewards and the global APY history, settled rewards move to deferred_rewards
+pub fn checkpoint_referral_rewards(ctx: Context<CheckpointReferralRewards>) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let staking_account = &mut ctx.accounts.staking_account;
+
+    let checkpoint = staking_account.referral_checkpoint();
+
+    require!(
+        staking_account.user == ctx.accounts.cranker.key()
+            || checkpoint.is_some_and(|checkpoint| {
+                ctx.accounts.global_config.apy_history.is_blocked_by(checkpoint)
+            }),
+        ErrorCode::InvalidUser
+    );
+
+    let settled = staking_account.settle_referral_rewards(&ctx.accounts.global_config, now)?;
+
+    staking_account.repin_referral_checkpoint(&mut ctx.accounts.global_config.apy_history, checkpoint)?;
+
+    emit!(RewardsCheckpointed {
+        account: staking_account.key(),
+        settled,
+        cranker: ctx.accounts.cranker.key(),
+        timestamp: now,
+    });
+
+    Ok(())
+}
@@ -1,21 +1,31 @@
 use crate::{
     constants::constants,
     errors::ErrorCode,
-    state::{GlobalConfig, StakingAccount, UserStakedAccount},
+    events::RewardsClaimed,
+    instructions::pay_rewards,
+    state::{BlacklistEntry, GlobalConfig, StakingAccount, TierRegistry, UserStakedAccount},
 };
 
 use anchor_lang::prelude::*;
 use anchor_spl::associated_token::get_associated_token_address;
-use anchor_spl::token::{self, Mint, MintTo, Token, TokenAccount, Transfer};
+use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer};
 
 #[derive(Accounts)]
 pub struct ClaimAllRewards<'info> {
     #[account(
+        mut,
         seeds = [b"config"],
         bump = global_config.config_bumps
     )]
     pub global_config: Account<'info, GlobalConfig>,
 
+    #[account(
+        mut,
+        seeds = [b"tiers"],
+        bump = tier_registry.load()?.bump
+    )]
+    pub tier_registry: AccountLoader<'info, TierRegistry>,
+
     #[account(
         mut,
         seeds = [b"staking", user.key().as_ref()],
@@ -43,6 +53,18 @@ pub struct ClaimAllRewards<'info> {
     #[account(mut)]
     pub treasury_ata: Account<'info, TokenAccount>,
 
+    // Transfer manager ATA holding the rewards, required only in vault mode
+    #[account(mut)]
+    pub reward_vault: Option<Account<'info, TokenAccount>>,
+
+    /// CHECK: blacklist entry of the user, empty unless the user is blacklisted
+    #[account(
+        seeds = [b"blacklist", user.key().as_ref()],
+        bump,
+        constraint = !BlacklistEntry::is_blacklisted(&blacklist_entry) @ ErrorCode::Blacklisted
+    )]
+    pub blacklist_entry: UncheckedAccount<'info>,
+
     #[account(mut)]
     pub user: Signer<'info>,
 
@@ -54,13 +76,15 @@ pub struct ClaimAllRewards<'info> {
 }
 
 pub fn handler<'a>(ctx: Context<'_, '_, '_, 'a, ClaimAllRewards<'a>>) -> Result<()> {
+    ctx.accounts.global_config.require_not_paused(GlobalConfig::PAUSE_CLAIM)?;
+
     let global_config = &ctx.accounts.global_config;
     let staking_account = &mut ctx.accounts.staking_account;
     let now = Clock::get()?.unix_timestamp;
 
     let remaining_accounts = ctx.remaining_accounts;
 
-    let number_of_stakes = staking_account.user_staked_counter;
+    let number_of_stakes = staking_account.active_stakes();
     let should_provided_accounts = if number_of_stakes <= constants::CLAIM_ALL_LIMIT {
         number_of_stakes
     } else {
@@ -74,10 +98,6 @@ pub fn handler<'a>(ctx: Context<'_, '_, '_, 'a, ClaimAllRewards<'a>>) -> Result<
         return Err(ErrorCode::ShouldProvideStakedAccounts.into());
     }
 
-    if constants::BLACKLIST_ADDRESSES.contains(&staking_account.user.to_string().as_str()) {
-        return Err(ErrorCode::Blacklisted.into());
-    }
-
     let treasury =
         get_associated_token_address(&ctx.accounts.global_config.treasury, &global_config.mint);
 
@@ -85,11 +105,19 @@ pub fn handler<'a>(ctx: Context<'_, '_, '_, 'a, ClaimAllRewards<'a>>) -> Result<
         return Err(ErrorCode::InvalidTreasury.into());
     }
 
-    let reward_lamports_u64 = staking_account.calculate_all_rewards(
-        remaining_accounts,
-        global_config,
-        now
-    )?;
+    let continuous_accrual = global_config.continuous_accrual;
+    let mut tier_registry = ctx.accounts.tier_registry.load_mut()?;
+
+    // Rewards deferred by earlier claims are paid first
+    let reward_lamports_u64 = staking_account
+        .calculate_all_rewards(
+            remaining_accounts,
+            &tier_registry,
+            continuous_accrual,
+            now
+        )?
+        .checked_add(staking_account.deferred_rewards)
+        .ok_or(ErrorCode::Overflow)?;
 
     if reward_lamports_u64 == 0 {
         msg!("No rewards available to claim.");
@@ -98,30 +126,60 @@ pub fn handler<'a>(ctx: Context<'_, '_, '_, 'a, ClaimAllRewards<'a>>) -> Result<
 
     msg!("Calculated rewards: {:?}", reward_lamports_u64);
 
-    // Mint rewards to the user
-    let manager_bumps = ctx.accounts.global_config.transfer_manager_bumps.clone();
-    let manager_seeds: &[&[&[u8]]] = &[&[b"transfer_manager", &[manager_bumps]]];
+    let mint_supply = ctx.accounts.mint.supply;
+    let granted = ctx
+        .accounts
+        .global_config
+        .reserve_rewards(reward_lamports_u64, mint_supply, now)?;
 
-    let context = CpiContext::new(
-        ctx.accounts.token_program.to_account_info(),
-        MintTo {
-            mint: ctx.accounts.mint.to_account_info(),
-            to: ctx.accounts.user_token_account.to_account_info(),
-            authority: ctx.accounts.transfer_manager.to_account_info(),
-        },
-    )
-    .with_signer(manager_seeds);
+    staking_account.deferred_rewards = reward_lamports_u64
+        .checked_sub(granted)
+        .ok_or(ErrorCode::Underflow)?;
 
-    anchor_spl::token::mint_to(context, reward_lamports_u64)?;
+    update_timestamps(
+        remaining_accounts,
+        &mut tier_registry,
+        continuous_accrual,
+        now
+    )?;
 
-    msg!("Minted rewards to user's account.");
+    msg!("Updated timestamps for all accounts.");
 
-    let withdrawal_tax = reward_lamports_u64
-        .checked_mul(global_config.withdrawal_fee_bps as u64)
+    let withdrawal_tax = granted
+        .checked_mul(ctx.accounts.global_config.withdrawal_fee_bps as u64)
         .ok_or(ErrorCode::Overflow)?
         .checked_div(10_000)
         .ok_or(ErrorCode::Overflow)?;
 
+    emit!(RewardsClaimed {
+        user: ctx.accounts.user.key(),
+        positions: remaining_accounts.iter().map(|account| account.key()).collect(),
+        rewards: reward_lamports_u64,
+        paid: granted,
+        deferred: staking_account.deferred_rewards,
+        withdrawal_fee: withdrawal_tax,
+        from_vault: ctx.accounts.global_config.vault_rewards,
+        timestamp: now,
+    });
+
+    if granted == 0 {
+        msg!("Rewards deferred to the next epoch.");
+        return Ok(());
+    }
+
+    // Mint rewards to the user, or pay them from the reward vault
+    pay_rewards(
+        &ctx.accounts.global_config,
+        &ctx.accounts.mint,
+        &ctx.accounts.transfer_manager,
+        ctx.accounts.reward_vault.as_ref(),
+        ctx.accounts.user_token_account.to_account_info(),
+        &ctx.accounts.token_program,
+        granted,
+    )?;
+
+    msg!("Paid rewards to user's account.");
+
     let cpi_ctx_tax = CpiContext::new(
         ctx.accounts.token_program.to_account_info(),
         Transfer {
@@ -134,15 +192,13 @@ pub fn handler<'a>(ctx: Context<'_, '_, '_, 'a, ClaimAllRewards<'a>>) -> Result<
 
     msg!("Transferred withdrawal tax to treasury.");
 
-    update_timestamps(remaining_accounts, now)?;
-
-    msg!("Updated timestamps for all accounts.");
-
     Ok(())
 }
 
-fn update_timestamps(
+pub(crate) fn update_timestamps(
     remaining_accounts: &[AccountInfo],
+    tier_registry: &mut TierRegistry,
+    continuous_accrual: bool,
     now: i64,
 ) -> Result<()> {
     for account_info in remaining_accounts.iter() {
@@ -150,18 +206,28 @@ fn update_timestamps(
         let mut user_staked_account_data = UserStakedAccount::try_deserialize(&mut &user_staked_account[..])
             .map_err(|_| ErrorCode::DeserializationError)?;
 
-        let init_stake_time = user_staked_account_data.staked_timestamp;
-        let stake_hours = init_stake_time % constants::CLAIM_PERIOD_SECONDS;
-        let today = now - (now % constants::CLAIM_PERIOD_SECONDS);
-        let yesterday = today - constants::CLAIM_PERIOD_SECONDS;
+        // Expired positions were not paid, keep their rewards up to maturity for unstake
+        if user_staked_account_data.maturity_timestamp() < now {
+            continue;
+        }
+
+        if user_staked_account_data.is_tokenized() {
+            continue;
+        }
+
+        // Skipped by calculate_all_rewards, moving the checkpoint would drop their pending rewards
+        if user_staked_account_data.claim_too_soon(continuous_accrual, now) {
+            continue;
+        }
 
-        let new_last_claimed_timestamp = if now >= today + stake_hours {
-            today + stake_hours
+        let new_last_claimed_timestamp = if continuous_accrual {
+            now
         } else {
-            yesterday + stake_hours
+            user_staked_account_data.aligned_claim_timestamp(now)
         };
 
-        user_staked_account_data.last_claimed_timestamp = new_last_claimed_timestamp;
+        let tier = tier_registry.get_tier_mut(user_staked_account_data.tier_id)?;
+        user_staked_account_data.set_last_claimed(tier, new_last_claimed_timestamp)?;
 
         let mut cursor = std::io::Cursor::new(user_staked_account);
 
@@ -1,13 +1,15 @@
 use crate::{
-    constants::constants, errors::ErrorCode, state::{GlobalConfig, StakingAccount}
+    constants::constants, errors::ErrorCode, events::ReferralRewardsClaimed, instructions::pay_rewards,
+    state::{BlacklistEntry, GlobalConfig, StakingAccount}
 };
 
 use anchor_lang::prelude::*;
-use anchor_spl::token::{Mint, MintTo, Token, TokenAccount};
+use anchor_spl::token::{Mint, Token, TokenAccount};
 
 #[derive(Accounts)]
 pub struct ClaimReferralRewards<'info> {
     #[account(
+        mut,
         seeds = [b"config"],
         bump = global_config.config_bumps
     )]
@@ -37,6 +39,18 @@ pub struct ClaimReferralRewards<'info> {
     #[account(mut)]
     pub user_token_account: Account<'info, TokenAccount>,
 
+    // Transfer manager ATA holding the rewards, required only in vault mode
+    #[account(mut)]
+    pub reward_vault: Option<Account<'info, TokenAccount>>,
+
+    /// CHECK: blacklist entry of the user, empty unless the user is blacklisted
+    #[account(
+        seeds = [b"blacklist", user.key().as_ref()],
+        bump,
+        constraint = !BlacklistEntry::is_blacklisted(&blacklist_entry) @ ErrorCode::Blacklisted
+    )]
+    pub blacklist_entry: UncheckedAccount<'info>,
+
     #[account(mut)]
     pub user: Signer<'info>,
 
@@ -44,16 +58,18 @@ pub struct ClaimReferralRewards<'info> {
 }
 
 pub fn handler(ctx: Context<ClaimReferralRewards>) -> Result<()> {
+    ctx.accounts.global_config.require_not_paused(GlobalConfig::PAUSE_REFERRAL_CLAIM)?;
+
     let global_config = &ctx.accounts.global_config;
     let staking_account = &mut ctx.accounts.staking_account;
 
-    if constants::BLACKLIST_ADDRESSES.contains(&staking_account.user.to_string().as_str()) {
-        return Err(ErrorCode::Blacklisted.into());
-    }
-
     let now = Clock::get()?.unix_timestamp;
 
-    if now - staking_account.last_referral_rewards_claimed < constants::CLAIM_PERIOD_SECONDS {
+    // Settled rewards can be claimed right away, new commission settles the referrer's period in progress
+    if !global_config.continuous_accrual
+        && staking_account.deferred_rewards == 0
+        && now - staking_account.last_referral_rewards_claimed < constants::CLAIM_PERIOD_SECONDS
+    {
         msg!(
             "Claim too soon: Last claimed at {:?}",
             staking_account.last_referral_rewards_claimed
@@ -61,7 +77,11 @@ pub fn handler(ctx: Context<ClaimReferralRewards>) -> Result<()> {
         return Err(ErrorCode::ClaimTooSoon.into());
     }
 
-    let reward_lamports_u64 = staking_account.calculate_referral_rewards(global_config, now)?;
+    // Rewards deferred by earlier claims are paid first
+    let reward_lamports_u64 = staking_account
+        .calculate_referral_rewards(global_config, now)?
+        .checked_add(staking_account.deferred_rewards)
+        .ok_or(ErrorCode::Overflow)?;
 
     if reward_lamports_u64 == 0 {
         msg!("No rewards available to claim.");
@@ -70,26 +90,53 @@ pub fn handler(ctx: Context<ClaimReferralRewards>) -> Result<()> {
 
     msg!("Calculated rewards: {:?}", reward_lamports_u64);
 
-    // Mint rewards to the user
-    let manager_bumps = ctx.accounts.global_config.transfer_manager_bumps.clone();
-    let manager_seeds: &[&[&[u8]]] = &[&[b"transfer_manager", &[manager_bumps]]];
-
-    let context = CpiContext::new(
-        ctx.accounts.token_program.to_account_info(),
-        MintTo {
-            mint: ctx.accounts.mint.to_account_info(),
-            to: ctx.accounts.user_token_account.to_account_info(),
-            authority: ctx.accounts.transfer_manager.to_account_info(),
-        },
-    )
-    .with_signer(manager_seeds);
-
-    anchor_spl::token::mint_to(context, reward_lamports_u64)?;
-
-    msg!("Minted rewards to user's account.");
-
     // Update last claimed timestamp
-    staking_account.last_referral_rewards_claimed = now - (now % constants::CLAIM_PERIOD_SECONDS);
+    let checkpoint = staking_account.referral_checkpoint();
+
+    staking_account.last_referral_rewards_claimed = if global_config.continuous_accrual {
+        now
+    } else {
+        now - (now % constants::CLAIM_PERIOD_SECONDS)
+    };
+    staking_account.repin_referral_checkpoint(&mut ctx.accounts.global_config.apy_history, checkpoint)?;
+
+    let mint_supply = ctx.accounts.mint.supply;
+    let granted = ctx
+        .accounts
+        .global_config
+        .reserve_rewards(reward_lamports_u64, mint_supply, now)?;
+
+    staking_account.deferred_rewards = reward_lamports_u64
+        .checked_sub(granted)
+        .ok_or(ErrorCode::Underflow)?;
+
+    emit!(ReferralRewardsClaimed {
+        user: ctx.accounts.user.key(),
+        staking_account: staking_account.key(),
+        rewards: reward_lamports_u64,
+        paid: granted,
+        deferred: staking_account.deferred_rewards,
+        from_vault: ctx.accounts.global_config.vault_rewards,
+        timestamp: now,
+    });
+
+    if granted == 0 {
+        msg!("Rewards deferred to the next epoch.");
+        return Ok(());
+    }
+
+    // Mint rewards to the user, or pay them from the reward vault
+    pay_rewards(
+        &ctx.accounts.global_config,
+        &ctx.accounts.mint,
+        &ctx.accounts.transfer_manager,
+        ctx.accounts.reward_vault.as_ref(),
+        ctx.accounts.user_token_account.to_account_info(),
+        &ctx.accounts.token_program,
+        granted,
+    )?;
+
+    msg!("Paid rewards to user's account.");
 
     Ok(())
 }
@@ -1,22 +1,35 @@
 use crate::{
     constants::constants,
     errors::ErrorCode,
-    state::{GlobalConfig, UserStakedAccount},
+    events::RewardsClaimed,
+    instructions::pay_rewards,
+    state::{BlacklistEntry, GlobalConfig, TierRegistry, UserStakedAccount},
 };
 
 use anchor_lang::prelude::*;
 use anchor_spl::associated_token::get_associated_token_address;
-use anchor_spl::token::{self, Mint, MintTo, Token, TokenAccount, Transfer};
+use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer};
 
 #[derive(Accounts)]
 pub struct ClaimRewards<'info> {
     #[account(
+        mut,
         seeds = [b"config"],
         bump = global_config.config_bumps
     )]
     pub global_config: Account<'info, GlobalConfig>,
 
-    #[account(mut, has_one = user)]
+    #[account(
+        mut,
+        seeds = [b"tiers"],
+        bump = tier_registry.load()?.bump
+    )]
+    pub tier_registry: AccountLoader<'info, TierRegistry>,
+
+    #[account(
+        mut,
+        constraint = user_staked_account.is_authorized(&user.key(), position_token_account.as_deref()) @ ErrorCode::InvalidUser
+    )]
     pub user_staked_account: Account<'info, UserStakedAccount>,
 
     #[account(
@@ -39,6 +52,21 @@ pub struct ClaimRewards<'info> {
     #[account(mut)]
     pub treasury_ata: Account<'info, TokenAccount>,
 
+    // Holder's position NFT account, required only for tokenized positions
+    pub position_token_account: Option<Account<'info, TokenAccount>>,
+
+    // Transfer manager ATA holding the rewards, required only in vault mode
+    #[account(mut)]
+    pub reward_vault: Option<Account<'info, TokenAccount>>,
+
+    /// CHECK: blacklist entry of the user, empty unless the user is blacklisted
+    #[account(
+        seeds = [b"blacklist", user.key().as_ref()],
+        bump,
+        constraint = !BlacklistEntry::is_blacklisted(&blacklist_entry) @ ErrorCode::Blacklisted
+    )]
+    pub blacklist_entry: UncheckedAccount<'info>,
+
     #[account(mut)]
     pub user: Signer<'info>,
 
@@ -46,18 +74,16 @@ pub struct ClaimRewards<'info> {
 }
 
 pub fn handler<'a>(ctx: Context<'_, '_, '_, 'a, ClaimRewards<'a>>) -> Result<()> {
+    ctx.accounts.global_config.require_not_paused(GlobalConfig::PAUSE_CLAIM)?;
+
     let global_config = &ctx.accounts.global_config;
     let user_staked_account = &mut ctx.accounts.user_staked_account;
     let now = Clock::get()?.unix_timestamp;
 
-    if user_staked_account.staked_timestamp + constants::ONE_YEAR_PERIOD < now {
+    if user_staked_account.maturity_timestamp() < now {
         return Err(ErrorCode::StakeExpired.into());
     }
 
-    if constants::BLACKLIST_ADDRESSES.contains(&user_staked_account.user.to_string().as_str()) {
-        return Err(ErrorCode::Blacklisted.into());
-    }
-
     let treasury =
         get_associated_token_address(&ctx.accounts.global_config.treasury, &global_config.mint);
 
@@ -67,7 +93,7 @@ pub fn handler<'a>(ctx: Context<'_, '_, '_, 'a, ClaimRewards<'a>>) -> Result<()>
 
     let last_claim_time = user_staked_account.last_claimed_timestamp;
 
-    if now - last_claim_time < constants::CLAIM_PERIOD_SECONDS {
+    if !global_config.continuous_accrual && now - last_claim_time < constants::CLAIM_PERIOD_SECONDS {
         msg!(
             "Claim too soon: You may claimed at {:?}",
             last_claim_time + constants::CLAIM_PERIOD_SECONDS
@@ -75,7 +101,10 @@ pub fn handler<'a>(ctx: Context<'_, '_, '_, 'a, ClaimRewards<'a>>) -> Result<()>
         return Err(ErrorCode::ClaimTooSoon.into());
     }
 
-    let reward_lamports_u64 = user_staked_account.calculate_rewards(global_config, now)?;
+    let mut tier_registry = ctx.accounts.tier_registry.load_mut()?;
+    let tier = tier_registry.get_tier_mut(user_staked_account.tier_id)?;
+    let (reward_lamports_u64, new_last_claimed_timestamp) =
+        user_staked_account.claimable_rewards(global_config.continuous_accrual, tier, now)?;
 
     if reward_lamports_u64 == 0 {
         msg!("No rewards available to claim.");
@@ -84,30 +113,53 @@ pub fn handler<'a>(ctx: Context<'_, '_, '_, 'a, ClaimRewards<'a>>) -> Result<()>
 
     msg!("Calculated rewards: {:?}", reward_lamports_u64);
 
-    // Mint rewards to the user
-    let manager_bumps = ctx.accounts.global_config.transfer_manager_bumps.clone();
-    let manager_seeds: &[&[&[u8]]] = &[&[b"transfer_manager", &[manager_bumps]]];
+    let mint_supply = ctx.accounts.mint.supply;
+    let granted = ctx
+        .accounts
+        .global_config
+        .reserve_rewards(reward_lamports_u64, mint_supply, now)?;
 
-    let context = CpiContext::new(
-        ctx.accounts.token_program.to_account_info(),
-        MintTo {
-            mint: ctx.accounts.mint.to_account_info(),
-            to: ctx.accounts.user_token_account.to_account_info(),
-            authority: ctx.accounts.transfer_manager.to_account_info(),
-        },
-    )
-    .with_signer(manager_seeds);
+    // Update last claimed timestamp, rewards that did not fit into the budget stay pending on the position
+    user_staked_account.set_last_claimed(tier, new_last_claimed_timestamp)?;
+    user_staked_account.pending_rewards = reward_lamports_u64
+        .checked_sub(granted)
+        .ok_or(ErrorCode::Underflow)?;
 
-    anchor_spl::token::mint_to(context, reward_lamports_u64)?;
-
-    msg!("Minted rewards to user's account.");
-
-    let withdrawal_tax = reward_lamports_u64
-        .checked_mul(global_config.withdrawal_fee_bps as u64)
+    let withdrawal_tax = granted
+        .checked_mul(ctx.accounts.global_config.withdrawal_fee_bps as u64)
         .ok_or(ErrorCode::Overflow)?
         .checked_div(10_000)
         .ok_or(ErrorCode::Overflow)?;
 
+    emit!(RewardsClaimed {
+        user: ctx.accounts.user.key(),
+        positions: vec![user_staked_account.key()],
+        rewards: reward_lamports_u64,
+        paid: granted,
+        deferred: user_staked_account.pending_rewards,
+        withdrawal_fee: withdrawal_tax,
+        from_vault: ctx.accounts.global_config.vault_rewards,
+        timestamp: now,
+    });
+
+    if granted == 0 {
+        msg!("Rewards deferred to the next epoch.");
+        return Ok(());
+    }
+
+    // Mint rewards to the user, or pay them from the reward vault
+    pay_rewards(
+        &ctx.accounts.global_config,
+        &ctx.accounts.mint,
+        &ctx.accounts.transfer_manager,
+        ctx.accounts.reward_vault.as_ref(),
+        ctx.accounts.user_token_account.to_account_info(),
+        &ctx.accounts.token_program,
+        granted,
+    )?;
+
+    msg!("Paid rewards to user's account.");
+
     let cpi_ctx_tax = CpiContext::new(
         ctx.accounts.token_program.to_account_info(),
         Transfer {
@@ -118,19 +170,5 @@ pub fn handler<'a>(ctx: Context<'_, '_, '_, 'a, ClaimRewards<'a>>) -> Result<()>
     );
     token::transfer(cpi_ctx_tax, withdrawal_tax)?;
 
-    // Update last claimed timestamp
-    let init_stake_time = user_staked_account.staked_timestamp;
-    let stake_hours = init_stake_time % constants::CLAIM_PERIOD_SECONDS;
-    let today = now - (now % constants::CLAIM_PERIOD_SECONDS);
-    let yesterday = today - constants::CLAIM_PERIOD_SECONDS;
-
-    let new_last_claimed_timestamp = if now >= today + stake_hours {
-        today + stake_hours
-    } else {
-        yesterday + stake_hours
-    };
-
-    user_staked_account.last_claimed_timestamp = new_last_claimed_timestamp;
-
     Ok(())
 }
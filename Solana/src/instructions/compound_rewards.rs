@@ -0,0 +1,682 @@
+use crate::{
+    constants::constants,
+    errors::ErrorCode,
+    events::RewardsCompounded,
+    instructions::{
+        apply_referral_rewards_for_all_referrer, burn_compounded_rewards, pay_rewards,
+        position_nft_account, update_timestamps, PositionNft,
+    },
+    state::{
+        BlacklistEntry, GlobalConfig, LevelCredits, LockTier, ReferralCredit, ReferralCreditSource,
+        ReferralEntry, StakingAccount, TierRegistry, UserStakedAccount,
+    },
+};
+
+use anchor_lang::prelude::*;
+use anchor_spl::associated_token::{get_associated_token_address, AssociatedToken};
+use anchor_spl::metadata::Metadata;
+use anchor_spl::token::{Mint, Token, TokenAccount};
+
+#[derive(Accounts)]
+pub struct CompoundRewards<'info> {
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = global_config.config_bumps
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"tiers"],
+        bump = tier_registry.load()?.bump
+    )]
+    pub tier_registry: AccountLoader<'info, TierRegistry>,
+
+    #[account(
+        mut,
+        seeds = [b"staking", user.key().as_ref()],
+        bump
+    )]
+    pub staking_account: Account<'info, StakingAccount>,
+
+    #[account(
+        mut,
+        constraint = user_staked_account.is_authorized(&user.key(), position_token_account.as_deref()) @ ErrorCode::InvalidUser
+    )]
+    pub user_staked_account: Account<'info, UserStakedAccount>,
+
+    #[account(
+        init,
+        payer = user,
+        space = UserStakedAccount::LEN,
+        seeds = [b"user-staked", staking_account.key().as_ref(), staking_account.user_staked_counter.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub new_user_staked_account: Account<'info, UserStakedAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"mint"],
+        bump = global_config.mint_bumps
+    )]
+    pub mint: Account<'info, Mint>,
+
+    /// CHECK: empty PDA, manager for token accounts
+    #[account(
+        seeds = [b"transfer_manager"],
+        bump = global_config.transfer_manager_bumps,
+    )]
+    pub transfer_manager: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub treasury_ata: Account<'info, TokenAccount>,
+
+    // Holder's position NFT account, required only for tokenized positions
+    pub position_token_account: Option<Account<'info, TokenAccount>>,
+
+    // Transfer manager ATA holding the rewards, required only in vault mode
+    #[account(mut)]
+    pub reward_vault: Option<Account<'info, TokenAccount>>,
+
+    // Link of the user to its referrer, required when the user has a referrer
+    #[account(
+        mut,
+        seeds = [b"referral", staking_account.referrer.as_ref(), staking_account.key().as_ref()],
+        bump = referral_entry.bump
+    )]
+    pub referral_entry: Option<Account<'info, ReferralEntry>>,
+
+    // Commission credited to the upline for this position, required when some level was paid
+    #[account(
+        init,
+        payer = user,
+        space = ReferralCredit::LEN,
+        seeds = [b"referral-credit", new_user_staked_account.key().as_ref()],
+        bump
+    )]
+    pub referral_credit: Option<Account<'info, ReferralCredit>>,
+
+    /// CHECK: blacklist entry of the user, empty unless the user is blacklisted
+    #[account(
+        seeds = [b"blacklist", user.key().as_ref()],
+        bump,
+        constraint = !BlacklistEntry::is_blacklisted(&blacklist_entry) @ ErrorCode::Blacklisted
+    )]
+    pub blacklist_entry: UncheckedAccount<'info>,
+
+    // NFT of the new position, the position is opened tokenized when it is provided
+    #[account(
+        init,
+        seeds = [b"position-mint", new_user_staked_account.key().as_ref()],
+        bump,
+        payer = user,
+        mint::authority = global_config.transfer_manager,
+        mint::freeze_authority = global_config.transfer_manager,
+        mint::decimals = 0,
+    )]
+    pub new_position_mint: Option<Box<Account<'info, Mint>>>,
+
+    #[account(
+        init,
+        payer = user,
+        associated_token::mint = new_position_mint,
+        associated_token::authority = user,
+    )]
+    pub new_position_token_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    /// CHECK: metadata PDA of new_position_mint, derivation checked by the metadata program
+    #[account(mut)]
+    pub new_metadata_account: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: master edition PDA of new_position_mint, derivation checked by the metadata program
+    #[account(mut)]
+    pub new_master_edition_account: Option<UncheckedAccount<'info>>,
+
+    pub token_metadata_program: Option<Program<'info, Metadata>>,
+    pub associated_token_program: Option<Program<'info, AssociatedToken>>,
+    pub rent: Option<Sysvar<'info, Rent>>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+
+    /*
+    REMAINING_ACCOUNTS: referrer staking accounts (limit 10)
+     */
+}
+
+impl<'info> CompoundRewards<'info> {
+    fn position_nft(&self) -> Result<Option<PositionNft<'info>>> {
+        let Some(position_mint) = &self.new_position_mint else {
+            return Ok(None);
+        };
+
+        Ok(Some(PositionNft {
+            position_mint: position_mint.to_account_info(),
+            position_token_account: position_nft_account(self.new_position_token_account.as_deref())?,
+            metadata_account: position_nft_account(self.new_metadata_account.as_ref())?,
+            master_edition_account: position_nft_account(self.new_master_edition_account.as_ref())?,
+            transfer_manager: self.transfer_manager.to_account_info(),
+            payer: self.user.to_account_info(),
+            token_metadata_program: position_nft_account(self.token_metadata_program.as_ref())?,
+            token_program: self.token_program.to_account_info(),
+            system_program: self.system_program.to_account_info(),
+            rent: position_nft_account(self.rent.as_ref())?,
+        }))
+    }
+}
+
+/*
+ * Restakes the rewards of one position into a new position without minting and burning.
+ * The compound fee is the only part that is minted, straight to treasury.
+ * In vault mode the fee comes from the reward vault and the restaked part is burned from it.
+ */
+pub fn compound_rewards<'a>(
+    ctx: Context<'_, '_, 'a, 'a, CompoundRewards<'a>>,
+    tier_id: u8,
+) -> Result<()> {
+    ctx.accounts.global_config.require_not_paused(GlobalConfig::PAUSE_STAKE | GlobalConfig::PAUSE_CLAIM)?;
+
+    let now = Clock::get()?.unix_timestamp;
+    let continuous_accrual = ctx.accounts.global_config.continuous_accrual;
+
+    let position_nft = ctx.accounts.position_nft()?;
+    let mut tier_registry = ctx.accounts.tier_registry.load_mut()?;
+    require!(tier_registry.get_tier(tier_id)?.is_active(), ErrorCode::InactiveTier);
+
+    let user_staked_account = &mut ctx.accounts.user_staked_account;
+
+    if user_staked_account.maturity_timestamp() < now {
+        return Err(ErrorCode::StakeExpired.into());
+    }
+
+    let treasury = get_associated_token_address(
+        &ctx.accounts.global_config.treasury,
+        &ctx.accounts.global_config.mint,
+    );
+
+    if treasury != ctx.accounts.treasury_ata.key().clone() {
+        return Err(ErrorCode::InvalidTreasury.into());
+    }
+
+    let last_claim_time = user_staked_account.last_claimed_timestamp;
+
+    if !continuous_accrual && now - last_claim_time < constants::CLAIM_PERIOD_SECONDS {
+        msg!(
+            "Claim too soon: You may compound at {:?}",
+            last_claim_time + constants::CLAIM_PERIOD_SECONDS
+        );
+        return Err(ErrorCode::ClaimTooSoon.into());
+    }
+
+    let tier = tier_registry.get_tier_mut(user_staked_account.tier_id)?;
+    let (reward_lamports_u64, new_last_claimed_timestamp) =
+        user_staked_account.claimable_rewards(continuous_accrual, tier, now)?;
+
+    require!(reward_lamports_u64 > 0, ErrorCode::NoRewardsToCompound);
+
+    msg!("Calculated rewards: {:?}", reward_lamports_u64);
+
+    // Compounded rewards are minted on unstake, they count against the budget like a claim
+    let mint_supply = ctx.accounts.mint.supply;
+    let granted = ctx
+        .accounts
+        .global_config
+        .reserve_rewards(reward_lamports_u64, mint_supply, now)?;
+
+    require!(granted > 0, ErrorCode::EmissionsBudgetExhausted);
+
+    user_staked_account.set_last_claimed(tier, new_last_claimed_timestamp)?;
+    user_staked_account.pending_rewards = reward_lamports_u64
+        .checked_sub(granted)
+        .ok_or(ErrorCode::Underflow)?;
+
+    let compound_fee = calculate_compound_fee(&ctx.accounts.global_config, granted)?;
+
+    pay_compound_fee(
+        &ctx.accounts.global_config,
+        &ctx.accounts.mint,
+        &ctx.accounts.transfer_manager,
+        ctx.accounts.reward_vault.as_ref(),
+        &ctx.accounts.treasury_ata,
+        &ctx.accounts.token_program,
+        compound_fee,
+    )?;
+
+    let net_amount = granted
+        .checked_sub(compound_fee)
+        .ok_or(ErrorCode::Underflow)?;
+
+    burn_compounded_rewards(
+        &ctx.accounts.global_config,
+        &ctx.accounts.mint,
+        &ctx.accounts.transfer_manager,
+        ctx.accounts.reward_vault.as_ref(),
+        &ctx.accounts.token_program,
+        net_amount,
+    )?;
+
+    let credits = open_compounded_position(
+        &mut ctx.accounts.global_config,
+        &mut ctx.accounts.staking_account,
+        &mut ctx.accounts.new_user_staked_account,
+        ctx.remaining_accounts,
+        ctx.accounts.referral_entry.as_deref_mut(),
+        tier_id,
+        tier_registry.get_tier_mut(tier_id)?,
+        net_amount,
+        now,
+    )?;
+
+    ReferralCredit::record(
+        ctx.accounts.referral_credit.as_deref_mut(),
+        ctx.bumps.referral_credit,
+        (ctx.accounts.new_user_staked_account.key(), ReferralCreditSource::Position),
+        ctx.accounts.staking_account.key(),
+        &ctx.accounts.staking_account.upline,
+        &credits,
+    )?;
+
+    if let Some(position_nft) = &position_nft {
+        position_nft.mint(
+            ctx.accounts.global_config.transfer_manager_bumps,
+            &mut ctx.accounts.new_user_staked_account,
+            now,
+        )?;
+    }
+
+    emit!(RewardsCompounded {
+        user: ctx.accounts.user.key(),
+        positions: vec![ctx.accounts.user_staked_account.key()],
+        new_position: ctx.accounts.new_user_staked_account.key(),
+        tier_id,
+        rewards: reward_lamports_u64,
+        compounded: granted,
+        deferred: ctx.accounts.user_staked_account.pending_rewards,
+        compound_fee,
+        net_amount,
+        timestamp: now,
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct CompoundAllRewards<'info> {
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = global_config.config_bumps
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"tiers"],
+        bump = tier_registry.load()?.bump
+    )]
+    pub tier_registry: AccountLoader<'info, TierRegistry>,
+
+    #[account(
+        mut,
+        seeds = [b"staking", user.key().as_ref()],
+        bump
+    )]
+    pub staking_account: Account<'info, StakingAccount>,
+
+    #[account(
+        init,
+        payer = user,
+        space = UserStakedAccount::LEN,
+        seeds = [b"user-staked", staking_account.key().as_ref(), staking_account.user_staked_counter.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub new_user_staked_account: Account<'info, UserStakedAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"mint"],
+        bump = global_config.mint_bumps
+    )]
+    pub mint: Account<'info, Mint>,
+
+    /// CHECK: empty PDA, manager for token accounts
+    #[account(
+        seeds = [b"transfer_manager"],
+        bump = global_config.transfer_manager_bumps,
+    )]
+    pub transfer_manager: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub treasury_ata: Account<'info, TokenAccount>,
+
+    // Transfer manager ATA holding the rewards, required only in vault mode
+    #[account(mut)]
+    pub reward_vault: Option<Account<'info, TokenAccount>>,
+
+    // Link of the user to its referrer, required when the user has a referrer
+    #[account(
+        mut,
+        seeds = [b"referral", staking_account.referrer.as_ref(), staking_account.key().as_ref()],
+        bump = referral_entry.bump
+    )]
+    pub referral_entry: Option<Account<'info, ReferralEntry>>,
+
+    // Commission credited to the upline for this position, required when some level was paid
+    #[account(
+        init,
+        payer = user,
+        space = ReferralCredit::LEN,
+        seeds = [b"referral-credit", new_user_staked_account.key().as_ref()],
+        bump
+    )]
+    pub referral_credit: Option<Account<'info, ReferralCredit>>,
+
+    /// CHECK: blacklist entry of the user, empty unless the user is blacklisted
+    #[account(
+        seeds = [b"blacklist", user.key().as_ref()],
+        bump,
+        constraint = !BlacklistEntry::is_blacklisted(&blacklist_entry) @ ErrorCode::Blacklisted
+    )]
+    pub blacklist_entry: UncheckedAccount<'info>,
+
+    // NFT of the new position, the position is opened tokenized when it is provided
+    #[account(
+        init,
+        seeds = [b"position-mint", new_user_staked_account.key().as_ref()],
+        bump,
+        payer = user,
+        mint::authority = global_config.transfer_manager,
+        mint::freeze_authority = global_config.transfer_manager,
+        mint::decimals = 0,
+    )]
+    pub new_position_mint: Option<Box<Account<'info, Mint>>>,
+
+    #[account(
+        init,
+        payer = user,
+        associated_token::mint = new_position_mint,
+        associated_token::authority = user,
+    )]
+    pub new_position_token_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    /// CHECK: metadata PDA of new_position_mint, derivation checked by the metadata program
+    #[account(mut)]
+    pub new_metadata_account: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: master edition PDA of new_position_mint, derivation checked by the metadata program
+    #[account(mut)]
+    pub new_master_edition_account: Option<UncheckedAccount<'info>>,
+
+    pub token_metadata_program: Option<Program<'info, Metadata>>,
+    pub associated_token_program: Option<Program<'info, AssociatedToken>>,
+    pub rent: Option<Sysvar<'info, Rent>>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+
+    /*
+    REMAINING_ACCOUNTS: user_staked_accounts (position_count, limit 24), then referrer staking accounts (limit 10)
+     */
+}
+
+impl<'info> CompoundAllRewards<'info> {
+    fn position_nft(&self) -> Result<Option<PositionNft<'info>>> {
+        let Some(position_mint) = &self.new_position_mint else {
+            return Ok(None);
+        };
+
+        Ok(Some(PositionNft {
+            position_mint: position_mint.to_account_info(),
+            position_token_account: position_nft_account(self.new_position_token_account.as_deref())?,
+            metadata_account: position_nft_account(self.new_metadata_account.as_ref())?,
+            master_edition_account: position_nft_account(self.new_master_edition_account.as_ref())?,
+            transfer_manager: self.transfer_manager.to_account_info(),
+            payer: self.user.to_account_info(),
+            token_metadata_program: position_nft_account(self.token_metadata_program.as_ref())?,
+            token_program: self.token_program.to_account_info(),
+            system_program: self.system_program.to_account_info(),
+            rent: position_nft_account(self.rent.as_ref())?,
+        }))
+    }
+}
+
+pub fn compound_all_rewards<'a>(
+    ctx: Context<'_, '_, 'a, 'a, CompoundAllRewards<'a>>,
+    tier_id: u8,
+    position_count: u8,
+) -> Result<()> {
+    ctx.accounts.global_config.require_not_paused(GlobalConfig::PAUSE_STAKE | GlobalConfig::PAUSE_CLAIM)?;
+
+    let now = Clock::get()?.unix_timestamp;
+    let continuous_accrual = ctx.accounts.global_config.continuous_accrual;
+
+    let position_nft = ctx.accounts.position_nft()?;
+    let mut tier_registry = ctx.accounts.tier_registry.load_mut()?;
+    require!(tier_registry.get_tier(tier_id)?.is_active(), ErrorCode::InactiveTier);
+
+    let remaining_accounts = ctx.remaining_accounts;
+    let position_count = position_count as usize;
+
+    if position_count == 0
+        || position_count > constants::CLAIM_ALL_LIMIT as usize
+        || position_count > remaining_accounts.len()
+    {
+        return Err(ErrorCode::ShouldProvideStakedAccounts.into());
+    }
+
+    let (user_staked_accounts, referrer_accounts) = remaining_accounts.split_at(position_count);
+
+    let treasury = get_associated_token_address(
+        &ctx.accounts.global_config.treasury,
+        &ctx.accounts.global_config.mint,
+    );
+
+    if treasury != ctx.accounts.treasury_ata.key().clone() {
+        return Err(ErrorCode::InvalidTreasury.into());
+    }
+
+    // Rewards deferred by earlier claims are compounded first
+    let reward_lamports_u64 = ctx
+        .accounts
+        .staking_account
+        .calculate_all_rewards(
+            user_staked_accounts,
+            &tier_registry,
+            continuous_accrual,
+            now
+        )?
+        .checked_add(ctx.accounts.staking_account.deferred_rewards)
+        .ok_or(ErrorCode::Overflow)?;
+
+    require!(reward_lamports_u64 > 0, ErrorCode::NoRewardsToCompound);
+
+    msg!("Calculated rewards: {:?}", reward_lamports_u64);
+
+    let mint_supply = ctx.accounts.mint.supply;
+    let granted = ctx
+        .accounts
+        .global_config
+        .reserve_rewards(reward_lamports_u64, mint_supply, now)?;
+
+    require!(granted > 0, ErrorCode::EmissionsBudgetExhausted);
+
+    ctx.accounts.staking_account.deferred_rewards = reward_lamports_u64
+        .checked_sub(granted)
+        .ok_or(ErrorCode::Underflow)?;
+
+    update_timestamps(
+        user_staked_accounts,
+        &mut tier_registry,
+        continuous_accrual,
+        now
+    )?;
+
+    let compound_fee = calculate_compound_fee(&ctx.accounts.global_config, granted)?;
+
+    pay_compound_fee(
+        &ctx.accounts.global_config,
+        &ctx.accounts.mint,
+        &ctx.accounts.transfer_manager,
+        ctx.accounts.reward_vault.as_ref(),
+        &ctx.accounts.treasury_ata,
+        &ctx.accounts.token_program,
+        compound_fee,
+    )?;
+
+    let net_amount = granted
+        .checked_sub(compound_fee)
+        .ok_or(ErrorCode::Underflow)?;
+
+    burn_compounded_rewards(
+        &ctx.accounts.global_config,
+        &ctx.accounts.mint,
+        &ctx.accounts.transfer_manager,
+        ctx.accounts.reward_vault.as_ref(),
+        &ctx.accounts.token_program,
+        net_amount,
+    )?;
+
+    let credits = open_compounded_position(
+        &mut ctx.accounts.global_config,
+        &mut ctx.accounts.staking_account,
+        &mut ctx.accounts.new_user_staked_account,
+        referrer_accounts,
+        ctx.accounts.referral_entry.as_deref_mut(),
+        tier_id,
+        tier_registry.get_tier_mut(tier_id)?,
+        net_amount,
+        now,
+    )?;
+
+    ReferralCredit::record(
+        ctx.accounts.referral_credit.as_deref_mut(),
+        ctx.bumps.referral_credit,
+        (ctx.accounts.new_user_staked_account.key(), ReferralCreditSource::Position),
+        ctx.accounts.staking_account.key(),
+        &ctx.accounts.staking_account.upline,
+        &credits,
+    )?;
+
+    if let Some(position_nft) = &position_nft {
+        position_nft.mint(
+            ctx.accounts.global_config.transfer_manager_bumps,
+            &mut ctx.accounts.new_user_staked_account,
+            now,
+        )?;
+    }
+
+    emit!(RewardsCompounded {
+        user: ctx.accounts.user.key(),
+        positions: user_staked_accounts.iter().map(|account| account.key()).collect(),
+        new_position: ctx.accounts.new_user_staked_account.key(),
+        tier_id,
+        rewards: reward_lamports_u64,
+        compounded: granted,
+        deferred: ctx.accounts.staking_account.deferred_rewards,
+        compound_fee,
+        net_amount,
+        timestamp: now,
+    });
+
+    Ok(())
+}
+
+fn calculate_compound_fee(global_config: &GlobalConfig, rewards: u64) -> Result<u64> {
+    let compound_fee = rewards
+        .checked_mul(global_config.compound_fee_bps as u64)
+        .ok_or(ErrorCode::Overflow)?
+        .checked_div(10_000)
+        .ok_or(ErrorCode::Overflow)?;
+
+    Ok(compound_fee)
+}
+
+fn pay_compound_fee<'info>(
+    global_config: &Account<'info, GlobalConfig>,
+    mint: &Account<'info, Mint>,
+    transfer_manager: &AccountInfo<'info>,
+    reward_vault: Option<&Account<'info, TokenAccount>>,
+    treasury_ata: &Account<'info, TokenAccount>,
+    token_program: &Program<'info, Token>,
+    compound_fee: u64,
+) -> Result<()> {
+    if compound_fee == 0 {
+        return Ok(());
+    }
+
+    pay_rewards(
+        global_config,
+        mint,
+        transfer_manager,
+        reward_vault,
+        treasury_ata.to_account_info(),
+        token_program,
+        compound_fee,
+    )?;
+
+    msg!("Paid compound fee to treasury.");
+
+    Ok(())
+}
+
+fn open_compounded_position<'a>(
+    global_config: &mut Account<'a, GlobalConfig>,
+    staking_account: &mut Account<'a, StakingAccount>,
+    user_staked: &mut Account<'a, UserStakedAccount>,
+    referrer_accounts: &[AccountInfo],
+    referral_entry: Option<&mut ReferralEntry>,
+    tier_id: u8,
+    tier: &mut LockTier,
+    net_amount: u64,
+    now: i64,
+) -> Result<LevelCredits> {
+    require!(net_amount > 0, ErrorCode::NoRewardsToCompound);
+
+    let credits = apply_referral_rewards_for_all_referrer(
+        referrer_accounts,
+        global_config,
+        &staking_account.upline,
+        net_amount,
+    )?;
+
+    ReferralEntry::record_volume(
+        referral_entry,
+        staking_account.referrer != Pubkey::default(),
+        net_amount,
+    )?;
+
+    user_staked.amount_staked = net_amount;
+
+    msg!("Amount compounded: {}", net_amount);
+
+    user_staked.start_accrual(tier, now)?;
+    user_staked.staked_timestamp = now;
+    user_staked.identifier = staking_account.user_staked_counter;
+    user_staked.user = staking_account.user;
+    user_staked.tier_id = tier_id;
+    user_staked.lock_duration = tier.duration;
+
+    staking_account.total_staked = staking_account
+        .total_staked
+        .checked_add(net_amount)
+        .ok_or(ErrorCode::Overflow)?;
+
+    staking_account.user_staked_counter = staking_account
+        .user_staked_counter
+        .checked_add(1)
+        .ok_or(ErrorCode::Overflow)?;
+
+    global_config.total_staked = global_config
+        .total_staked
+        .checked_add(net_amount)
+        .ok_or(ErrorCode::Overflow)?;
+
+    Ok(credits)
+}
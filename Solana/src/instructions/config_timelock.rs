@@ -0,0 +1,238 @@
+use crate::errors::ErrorCode;
+use crate::events::{ConfigChangeCancelled, ConfigChangeExecuted, ConfigChangeQueued};
+use crate::instructions::authorize_privileged;
+use crate::instructions::update_config::{apply_config_change, validate_config_change};
+use crate::state::{
+    ConfigChange, GlobalConfig, Multisig, PendingConfigChange, Proposal, ProposalAction,
+    RoleRegistry, TierRegistry,
+};
+use anchor_lang::prelude::*;
+
+#[derive(Accounts)]
+pub struct QueueConfigChange<'info> {
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = global_config.config_bumps
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = PendingConfigChange::LEN,
+        seeds = [b"config-change", global_config.config_change_counter.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub pending_change: Account<'info, PendingConfigChange>,
+
+    #[account(
+        seeds = [b"roles"],
+        bump = role_registry.bump
+    )]
+    pub role_registry: Account<'info, RoleRegistry>,
+
+    #[account(
+        seeds = [b"multisig"],
+        bump = multisig.bump
+    )]
+    pub multisig: Option<Account<'info, Multisig>>,
+
+    // Approved proposal, replaces the role check of the signer
+    #[account(
+        mut,
+        seeds = [b"proposal", proposal.id.to_le_bytes().as_ref()],
+        bump = proposal.bump
+    )]
+    pub proposal: Option<Account<'info, Proposal>>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+// Once the multisig is required changes are queued through proposals, the timelock runs after the approval
+pub fn queue_config_change(
+    ctx: Context<QueueConfigChange>,
+    change: ConfigChange,
+    effective_at: i64,
+) -> Result<()> {
+    validate_config_change(&change)?;
+
+    authorize_privileged(
+        &ctx.accounts.global_config,
+        &ctx.accounts.role_registry,
+        &ctx.accounts.authority.key(),
+        RoleRegistry::CONFIG_MANAGER,
+        ctx.accounts.multisig.as_deref(),
+        ctx.accounts.proposal.as_deref_mut(),
+        ProposalAction::QueueConfigChange { change },
+    )?;
+
+    let global_config = &mut ctx.accounts.global_config;
+    let pending_change = &mut ctx.accounts.pending_change;
+    let now = Clock::get()?.unix_timestamp;
+
+    require!(
+        effective_at >= now + global_config.min_timelock_delay,
+        ErrorCode::TimelockTooShort
+    );
+
+    pending_change.id = global_config.config_change_counter;
+    pending_change.change = change;
+    pending_change.proposer = ctx.accounts.authority.key();
+    pending_change.queued_at = now;
+    pending_change.effective_at = effective_at;
+    pending_change.bump = ctx.bumps.pending_change;
+
+    global_config.config_change_counter = global_config
+        .config_change_counter
+        .checked_add(1)
+        .ok_or(ErrorCode::Overflow)?;
+
+    emit!(ConfigChangeQueued {
+        id: pending_change.id,
+        change,
+        proposer: pending_change.proposer,
+        queued_at: now,
+        effective_at,
+    });
+
+    msg!("Config change {} queued: {:?}, effective at {}", pending_change.id, change, effective_at);
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct ExecuteConfigChange<'info> {
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = global_config.config_bumps
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        has_one = proposer,
+        close = proposer,
+        seeds = [b"config-change", pending_change.id.to_le_bytes().as_ref()],
+        bump = pending_change.bump
+    )]
+    pub pending_change: Account<'info, PendingConfigChange>,
+
+    // Only needed to execute ConfigChange::LockTier
+    #[account(
+        mut,
+        seeds = [b"tiers"],
+        bump = tier_registry.load()?.bump
+    )]
+    pub tier_registry: Option<AccountLoader<'info, TierRegistry>>,
+
+    /// CHECK: receives the rent of the executed change, checked against pending_change.proposer
+    #[account(mut)]
+    pub proposer: UncheckedAccount<'info>,
+
+    pub executor: Signer<'info>,
+}
+
+pub fn execute_config_change(ctx: Context<ExecuteConfigChange>) -> Result<()> {
+    let global_config = &mut ctx.accounts.global_config;
+    let pending_change = &ctx.accounts.pending_change;
+    let now = Clock::get()?.unix_timestamp;
+
+    if now < pending_change.effective_at {
+        msg!("Config change is due at {:?}", pending_change.effective_at);
+        return Err(ErrorCode::TimelockNotReady.into());
+    }
+
+    let mut tier_registry = match &ctx.accounts.tier_registry {
+        Some(tier_registry) => Some(tier_registry.load_mut()?),
+        None => None,
+    };
+
+    apply_config_change(
+        global_config,
+        tier_registry.as_deref_mut(),
+        pending_change.change,
+        pending_change.proposer,
+    )?;
+
+    emit!(ConfigChangeExecuted {
+        id: pending_change.id,
+        change: pending_change.change,
+        executor: ctx.accounts.executor.key(),
+        timestamp: now,
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct CancelConfigChange<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = global_config.config_bumps
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        close = authority,
+        seeds = [b"config-change", pending_change.id.to_le_bytes().as_ref()],
+        bump = pending_change.bump
+    )]
+    pub pending_change: Account<'info, PendingConfigChange>,
+
+    #[account(
+        seeds = [b"roles"],
+        bump = role_registry.bump
+    )]
+    pub role_registry: Account<'info, RoleRegistry>,
+
+    #[account(
+        seeds = [b"multisig"],
+        bump = multisig.bump
+    )]
+    pub multisig: Option<Account<'info, Multisig>>,
+
+    // Approved proposal, replaces the role check of the signer
+    #[account(
+        mut,
+        seeds = [b"proposal", proposal.id.to_le_bytes().as_ref()],
+        bump = proposal.bump
+    )]
+    pub proposal: Option<Account<'info, Proposal>>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+}
+
+pub fn cancel_config_change(ctx: Context<CancelConfigChange>) -> Result<()> {
+    authorize_privileged(
+        &ctx.accounts.global_config,
+        &ctx.accounts.role_registry,
+        &ctx.accounts.authority.key(),
+        RoleRegistry::CONFIG_MANAGER,
+        ctx.accounts.multisig.as_deref(),
+        ctx.accounts.proposal.as_deref_mut(),
+        ProposalAction::CancelConfigChange {
+            id: ctx.accounts.pending_change.id,
+        },
+    )?;
+
+    let pending_change = &ctx.accounts.pending_change;
+    let now = Clock::get()?.unix_timestamp;
+
+    require!(now < pending_change.effective_at, ErrorCode::TimelockAlreadyDue);
+
+    emit!(ConfigChangeCancelled {
+        id: pending_change.id,
+        change: pending_change.change,
+        authority: ctx.accounts.authority.key(),
+        timestamp: now,
+    });
+
+    msg!("Config change {} cancelled", pending_change.id);
+    Ok(())
+}
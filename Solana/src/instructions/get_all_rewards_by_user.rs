@@ -1,4 +1,5 @@
-use crate::state::{GlobalConfig, StakingAccount};
+use crate::errors::ErrorCode;
+use crate::state::{GlobalConfig, StakingAccount, TierRegistry};
 use anchor_lang::prelude::*;
 
 #[derive(Accounts)]
@@ -9,6 +10,12 @@ pub struct GetAllRewardsByUser<'info> {
     )]
     pub global_config: Account<'info, GlobalConfig>,
 
+    #[account(
+        seeds = [b"tiers"],
+        bump = tier_registry.load()?.bump
+    )]
+    pub tier_registry: AccountLoader<'info, TierRegistry>,
+
     #[account()]
     pub staking_account: Account<'info, StakingAccount>,
 
@@ -21,15 +28,21 @@ pub fn calculate_rewards(
     ctx: Context<GetAllRewardsByUser>,
 ) -> Result<u64> {
     let now = Clock::get()?.unix_timestamp;
-    let global_config = &ctx.accounts.global_config;
+    let tier_registry = ctx.accounts.tier_registry.load()?;
     let staking_account = &ctx.accounts.staking_account;
     let remaining_accounts = ctx.remaining_accounts;
 
     let rewards = staking_account.calculate_all_rewards(
         remaining_accounts,
-        global_config,
+        &tier_registry,
+        ctx.accounts.global_config.continuous_accrual,
         now
     )?;
 
+    // Deferred rewards are paid by the next claim_all_rewards
+    let rewards = rewards
+        .checked_add(staking_account.deferred_rewards)
+        .ok_or(ErrorCode::Overflow)?;
+
     Ok(rewards)
 }
@@ -1,7 +1,6 @@
 use crate::{
-    constants::constants,
     errors::ErrorCode,
-    state::{GlobalConfig, UserStakedAccount},
+    state::{GlobalConfig, TierRegistry, UserStakedAccount},
 };
 
 use anchor_lang::prelude::*;
@@ -14,6 +13,12 @@ pub struct GetCurrentRewardsByUser<'info> {
     )]
     pub global_config: Account<'info, GlobalConfig>,
 
+    #[account(
+        seeds = [b"tiers"],
+        bump = tier_registry.load()?.bump
+    )]
+    pub tier_registry: AccountLoader<'info, TierRegistry>,
+
     #[account()]
     pub user_staked_account: Account<'info, UserStakedAccount>,
 }
@@ -23,23 +28,22 @@ pub fn calculate_current_rewards(
 ) -> Result<u128> {
     let now = Clock::get()?.unix_timestamp;
 
-    let global_config = &ctx.accounts.global_config;
     let user_staked = &ctx.accounts.user_staked_account;
+    let tier_registry = ctx.accounts.tier_registry.load()?;
+    let tier = tier_registry.get_tier(user_staked.tier_id)?;
+
+    // Continuous accrual pays exactly what is accrued, same math as the claim
+    if ctx.accounts.global_config.continuous_accrual {
+        let current_rewards = user_staked.calculate_continuous_rewards(tier, now)?;
 
-    let stake_time = user_staked.staked_timestamp;
+        msg!("Current rewards: {:?}", current_rewards);
 
-    let today = now - (now % constants::CLAIM_PERIOD_SECONDS);
-    let staked_day = stake_time - (stake_time % constants::CLAIM_PERIOD_SECONDS);
-    let time_in_hours = stake_time - staked_day;
-    let yesterday = today - constants::CLAIM_PERIOD_SECONDS;
+        return Ok(current_rewards as u128);
+    }
 
-    let staking_period = if staked_day == today {
-        now - stake_time
-    } else {
-        (now - (yesterday + time_in_hours)) % constants::CLAIM_PERIOD_SECONDS
-    };
+    let staking_period = now - user_staked.aligned_claim_timestamp(now);
 
-    let apy_decimal = global_config.current_apy_bps as u128;
+    let apy_decimal = tier.current_apy_bps as u128;
     let principal = user_staked.amount_staked as u128;
 
     let current_rewards = principal
@@ -0,0 +1,48 @@
+use crate::state::GlobalConfig;
+use anchor_lang::prelude::*;
+use anchor_spl::token::Mint;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug)]
+pub struct EmissionsBudgetInfo {
+    pub enabled: bool,                        // False when no epoch caps are configured
+    pub epoch_end: i64,                       // End of the epoch the remaining amounts belong to
+    pub remaining_rewards: u64,               // Rewards that can still be minted in this epoch
+    pub remaining_admin_mint: u64,            // Tokens the admin can still mint in this epoch
+    pub remaining_supply: u64,                // Room left under MAX_SUPPLY, staked principal included
+    pub total_rewards_emitted: u64,           // Rewards minted since the budget was introduced
+}
+
+#[derive(Accounts)]
+pub struct GetEmissionsBudget<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = global_config.config_bumps
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        seeds = [b"mint"],
+        bump = global_config.mint_bumps
+    )]
+    pub mint: Account<'info, Mint>,
+}
+
+pub fn get_emissions_budget(ctx: Context<GetEmissionsBudget>) -> Result<EmissionsBudgetInfo> {
+    let now = Clock::get()?.unix_timestamp;
+    let global_config = &ctx.accounts.global_config;
+
+    // Same view as after roll(), without writing to the config
+    let mut emissions = global_config.emissions;
+    emissions.roll(now);
+
+    let remaining_supply = global_config.remaining_supply(ctx.accounts.mint.supply);
+
+    Ok(EmissionsBudgetInfo {
+        enabled: emissions.is_enabled(),
+        epoch_end: emissions.epoch_end(),
+        remaining_rewards: emissions.remaining_rewards(now).min(remaining_supply),
+        remaining_admin_mint: emissions.remaining_admin_mint(now).min(remaining_supply),
+        remaining_supply,
+        total_rewards_emitted: emissions.total_rewards_emitted,
+    })
+}
@@ -0,0 +1,34 @@
+use crate::state::{GlobalConfig, StakingAccount};
+use anchor_lang::prelude::*;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug)]
+pub struct ReferralRankInfo {
+    pub rank: Option<u8>,                     // Index in the rank table, None below the first rank
+    pub unlocked_levels: u8,                  // Levels the user is paid commission on
+    pub personal_stake: u64,                  // total_staked of the user
+    pub team_volume: u64,                     // total_referral_staked of the user
+}
+
+#[derive(Accounts)]
+pub struct GetReferralRank<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = global_config.config_bumps
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account()]
+    pub staking_account: Account<'info, StakingAccount>,
+}
+
+pub fn get_referral_rank(ctx: Context<GetReferralRank>) -> Result<ReferralRankInfo> {
+    let global_config = &ctx.accounts.global_config;
+    let staking_account = &ctx.accounts.staking_account;
+
+    Ok(ReferralRankInfo {
+        rank: staking_account.referral_rank(global_config),
+        unlocked_levels: staking_account.unlocked_referral_levels(global_config),
+        personal_stake: staking_account.total_staked,
+        team_volume: staking_account.total_referral_staked,
+    })
+}
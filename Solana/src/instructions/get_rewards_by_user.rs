@@ -1,4 +1,4 @@
-use crate::{constants, state::{GlobalConfig, UserStakedAccount}};
+use crate::{constants, state::{GlobalConfig, TierRegistry, UserStakedAccount}};
 use anchor_lang::prelude::*;
 
 #[derive(Accounts)]
@@ -9,6 +9,12 @@ pub struct GetRewardsByUser<'info> {
     )]
     pub global_config: Account<'info, GlobalConfig>,
 
+    #[account(
+        seeds = [b"tiers"],
+        bump = tier_registry.load()?.bump
+    )]
+    pub tier_registry: AccountLoader<'info, TierRegistry>,
+
     #[account()]
     pub user_staked_account: Account<'info, UserStakedAccount>,
 }
@@ -17,14 +23,17 @@ pub fn calculate_rewards(
     ctx: Context<GetRewardsByUser>,
 ) -> Result<u64> {
     let now = Clock::get()?.unix_timestamp;
-    let global_config = &ctx.accounts.global_config;
     let user_staked = &ctx.accounts.user_staked_account;
+    let tier_registry = ctx.accounts.tier_registry.load()?;
+    let tier = tier_registry.get_tier(user_staked.tier_id)?;
     let last_claim_time = user_staked.last_claimed_timestamp;
 
-    let rewards = if last_claim_time + constants::CLAIM_PERIOD_SECONDS > now {
+    let continuous_accrual = ctx.accounts.global_config.continuous_accrual;
+
+    let rewards = if !continuous_accrual && last_claim_time + constants::CLAIM_PERIOD_SECONDS > now {
         0
     } else {
-        user_staked.calculate_rewards(global_config, now)?
+        user_staked.claimable_rewards(continuous_accrual, tier, now)?.0
     };
 
     Ok(rewards)
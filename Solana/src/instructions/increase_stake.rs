@@ -0,0 +1,239 @@
+use crate::errors::ErrorCode;
+use crate::events::StakeIncreased;
+use crate::instructions::{
+    apply_referral_rewards_for_all_referrer, unwind_referral_rewards_for_all_referrer,
+};
+use crate::state::{BlacklistEntry, GlobalConfig, ReferralCredit, ReferralCreditSource, ReferralEntry, StakingAccount, TierRegistry, UserStakedAccount};
+use anchor_lang::prelude::*;
+use anchor_spl::associated_token::get_associated_token_address;
+use anchor_spl::token::{self, Burn, Mint, Token, TokenAccount, Transfer};
+
+/*
+ * Tops up an existing position instead of opening a new one.
+ *
+ * Policy:
+ * 1. Rewards accrued so far are settled in the configured accrual mode into pending_rewards and paid
+ *    by the next claim. In daily mode that is every whole claim period, the period in progress is not paid
+ * 2. The lock restarts for the whole position: staked_timestamp is reset to now and
+ *    lock_duration is refreshed from the tier, so claim periods stay aligned to the new staking hour
+ * 3. When the upline changed since the position was credited, its credit is unwound from the old
+ *    upline and the whole position is credited to the current one together with the top-up
+ */
+pub fn increase_stake<'a>(
+    ctx: Context<'_, '_, 'a, 'a, IncreaseStake<'a>>,
+    user_amount: u64,
+) -> Result<()> {
+    ctx.accounts.global_config.require_not_paused(GlobalConfig::PAUSE_STAKE)?;
+
+    if user_amount == 0 {
+        return Err(ErrorCode::InvalidAmount.into());
+    }
+
+    let mut tier_registry = ctx.accounts.tier_registry.load_mut()?;
+    let tier = tier_registry.get_tier_mut(ctx.accounts.user_staked_account.tier_id)?;
+    require!(tier.is_active(), ErrorCode::InactiveTier);
+
+    // The holder of a tokenized position may not be the user whose totals are updated here
+    if ctx.accounts.user_staked_account.is_tokenized() {
+        return Err(ErrorCode::PositionTokenized.into());
+    }
+
+    let global_config = &mut ctx.accounts.global_config;
+    let staking_account = &mut ctx.accounts.staking_account;
+    let user_staked = &mut ctx.accounts.user_staked_account;
+
+    let treasury =
+        get_associated_token_address(&global_config.treasury, &global_config.mint);
+
+    if treasury != ctx.accounts.treasury_ata.key().clone() {
+        return Err(ErrorCode::InvalidTreasury.into());
+    }
+
+    let deposit_tax = user_amount
+        .checked_mul(tier.deposit_fee_bps as u64)
+        .ok_or(ErrorCode::Overflow)?
+        .checked_div(10_000)
+        .ok_or(ErrorCode::Overflow)?;
+
+    let net_amount = user_amount
+        .checked_sub(deposit_tax)
+        .ok_or(ErrorCode::Underflow)?;
+
+    // Transfer deposit fee to treasury
+    let cpi_ctx_tax = CpiContext::new(
+        ctx.accounts.token_program.to_account_info(),
+        Transfer {
+            from: ctx.accounts.user_token_account.to_account_info(),
+            to: ctx.accounts.treasury_ata.to_account_info(),
+            authority: ctx.accounts.user.to_account_info(),
+        },
+    );
+    token::transfer(cpi_ctx_tax, deposit_tax)?;
+
+    // Burn the net amount
+    let cpi_ctx_burn = CpiContext::new(
+        ctx.accounts.token_program.to_account_info(),
+        Burn {
+            from: ctx.accounts.user_token_account.to_account_info(),
+            mint: ctx.accounts.mint.to_account_info(),
+            authority: ctx.accounts.user.to_account_info(),
+        },
+    );
+    token::burn(cpi_ctx_burn, net_amount)?;
+
+    let remaining_accounts = ctx.remaining_accounts;
+    let mut referrer_accounts = remaining_accounts;
+    let mut moved_amount = 0;
+
+    if let Some(credit) = ctx.accounts.referral_credit.as_deref_mut() {
+        if credit.source != Pubkey::default() && credit.upline != staking_account.upline {
+            let depth = StakingAccount::upline_depth(&credit.upline);
+
+            require!(remaining_accounts.len() >= depth, ErrorCode::IncompleteReferrerChain);
+
+            let (old_upline_accounts, upline_accounts) = remaining_accounts.split_at(depth);
+
+            unwind_referral_rewards_for_all_referrer(old_upline_accounts, global_config, credit)?;
+            credit.clear();
+
+            referrer_accounts = upline_accounts;
+            moved_amount = user_staked.amount_staked;
+        }
+    }
+
+    let credits = apply_referral_rewards_for_all_referrer(
+        referrer_accounts,
+        global_config,
+        &staking_account.upline,
+        net_amount.checked_add(moved_amount).ok_or(ErrorCode::Overflow)?,
+    )?;
+
+    ReferralEntry::record_volume(
+        ctx.accounts.referral_entry.as_deref_mut(),
+        staking_account.referrer != Pubkey::default(),
+        net_amount,
+    )?;
+
+    ReferralCredit::record(
+        ctx.accounts.referral_credit.as_deref_mut(),
+        ctx.bumps.referral_credit,
+        (user_staked.key(), ReferralCreditSource::Position),
+        staking_account.key(),
+        &staking_account.upline,
+        &credits,
+    )?;
+
+    let now = Clock::get()?.unix_timestamp;
+
+    let settled_rewards = user_staked.restart_lock(tier, global_config.continuous_accrual, now)?;
+
+    msg!("Settled rewards: {}", settled_rewards);
+
+    user_staked.amount_staked = user_staked
+        .amount_staked
+        .checked_add(net_amount)
+        .ok_or(ErrorCode::Overflow)?;
+
+    msg!("Amount added to stake: {}", net_amount);
+
+    staking_account.total_staked = staking_account
+        .total_staked
+        .checked_add(net_amount)
+        .ok_or(ErrorCode::Overflow)?;
+
+    global_config.total_staked = global_config
+        .total_staked
+        .checked_add(net_amount)
+        .ok_or(ErrorCode::Overflow)?;
+
+    emit!(StakeIncreased {
+        user: staking_account.user,
+        user_staked_account: user_staked.key(),
+        amount: user_amount,
+        deposit_fee: deposit_tax,
+        net_amount,
+        amount_staked: user_staked.amount_staked,
+        settled_rewards,
+        lock_duration: user_staked.lock_duration,
+        timestamp: now,
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct IncreaseStake<'info> {
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = global_config.config_bumps
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"tiers"],
+        bump = tier_registry.load()?.bump
+    )]
+    pub tier_registry: AccountLoader<'info, TierRegistry>,
+
+    #[account(
+        mut,
+        seeds = [b"staking", user.key().as_ref()],
+        bump
+    )]
+    pub staking_account: Account<'info, StakingAccount>,
+
+    #[account(mut, has_one = user)]
+    pub user_staked_account: Account<'info, UserStakedAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"mint"],
+        bump = global_config.mint_bumps
+    )]
+    pub mint: Account<'info, Mint>,
+
+    #[account(mut)]
+    pub user_token_account: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub treasury_ata: Account<'info, TokenAccount>,
+
+    // Link of the user to its referrer, required when the user has a referrer
+    #[account(
+        mut,
+        seeds = [b"referral", staking_account.referrer.as_ref(), staking_account.key().as_ref()],
+        bump = referral_entry.bump
+    )]
+    pub referral_entry: Option<Account<'info, ReferralEntry>>,
+
+    // Commission credited to the upline for this position, required when some level was paid
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = ReferralCredit::LEN,
+        seeds = [b"referral-credit", user_staked_account.key().as_ref()],
+        bump
+    )]
+    pub referral_credit: Option<Account<'info, ReferralCredit>>,
+
+    /// CHECK: blacklist entry of the user, empty unless the user is blacklisted
+    #[account(
+        seeds = [b"blacklist", user.key().as_ref()],
+        bump,
+        constraint = !BlacklistEntry::is_blacklisted(&blacklist_entry) @ ErrorCode::Blacklisted
+    )]
+    pub blacklist_entry: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+
+    /*
+    REMAINING_ACCOUNTS: staking accounts of referral_credit.upline, in order, when the upline changed since
+    the credit was made, followed by the staking accounts of the current upline, in order
+     */
+}
@@ -1,6 +1,7 @@
 use crate::constants;
+use crate::events::GlobalConfigInitialized;
 use crate::state::GlobalConfig;
-use crate::{errors::ErrorCode, state::ApyChange};
+use crate::{errors::ErrorCode, state::ApySchedule};
 
 use ::{
     anchor_lang::prelude::*,
@@ -48,11 +49,11 @@ pub fn handler(
     global_config.treasury = ctx.accounts.treasury.key();
     global_config.admin = ctx.accounts.authority.key();
 
+    // Same split as the fixed one OTC purchases used before: 20% to the direct referrer
+    global_config.otc_commission.level_bps[0] = 2_000;
+
     let now = Clock::get()?.unix_timestamp;
-    global_config.apy_history.push(ApyChange {
-        apy_bps: apy,
-        timestamp: now,
-    });
+    global_config.apy_history = ApySchedule::new(apy, now);
 
     let signer_seeds: &[&[&[u8]]] =
         &[&[b"transfer_manager", &[global_config.transfer_manager_bumps]]];
@@ -85,6 +86,17 @@ pub fn handler(
         None,
     )?;
 
+    emit!(GlobalConfigInitialized {
+        admin: ctx.accounts.authority.key(),
+        treasury: ctx.accounts.treasury.key(),
+        mint: ctx.accounts.mint.key(),
+        apy_bps: apy,
+        deposit_fee_bps,
+        withdrawal_fee_bps,
+        lvl_percentages: referral_percentages,
+        timestamp: now,
+    });
+
     Ok(())
 }
 
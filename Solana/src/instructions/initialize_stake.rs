@@ -1,12 +1,21 @@
-use crate::{constants::constants, errors::ErrorCode, state::{GlobalConfig, StakingAccount}};
+use crate::{
+    errors::ErrorCode,
+    events::{ReferralLinkCreated, StakingAccountInitialized},
+    state::{GlobalConfig, ReferralEntry, RoleRegistry, StakingAccount},
+};
 use anchor_lang::prelude::*;
 
 pub fn handler(ctx: Context<Initialize>, referrer: Pubkey) -> Result<()> {
+    ctx.accounts.global_config.require_not_paused(GlobalConfig::PAUSE_STAKE)?;
+
     let user_key = ctx.accounts.user_key.key();
     let signer_key = ctx.accounts.user.key();
 
     if user_key != signer_key {
-     require!(signer_key == ctx.accounts.global_config.admin, ErrorCode::OnlyAdmin);
+        require!(
+            ctx.accounts.role_registry.has_role(&signer_key, RoleRegistry::REFERRAL_OPERATOR),
+            ErrorCode::MissingRole
+        );
     }
 
     let referrer_exist = referrer != Pubkey::default();
@@ -23,20 +32,56 @@ pub fn handler(ctx: Context<Initialize>, referrer: Pubkey) -> Result<()> {
 
     staking_account.user = user_key;
     staking_account.total_staked = 0;
-    staking_account.referral_history = Vec::new();
     staking_account.user_staked_counter = 0;
-    staking_account.last_referral_rewards_claimed = Clock::get()?.unix_timestamp;
+    let now = Clock::get()?.unix_timestamp;
+    staking_account.last_referral_rewards_claimed = now;
 
-    let referrer_staking_account = &mut ctx.accounts.referrer_staking_account;
+    let referrer_staking_account = &ctx.accounts.referrer_staking_account;
 
     if referrer_exist {
         staking_account.referrer = referrer_staking_account.key();
-    }
+        staking_account.upline = StakingAccount::upline_below(referrer_staking_account.key(), referrer_staking_account);
+
+        // The link of the referrer to its own referrer is needed to know the depth of the new one
+        let parent_entry = ctx.accounts.referrer_referral_entry.as_deref();
+
+        if referrer_staking_account.referrer != Pubkey::default() {
+            require!(parent_entry.is_some(), ErrorCode::ShouldProvideReferralEntry);
+        }
+
+        let level = ReferralEntry::level_below(parent_entry);
+        let bump = ctx.bumps.referral_entry.ok_or(ErrorCode::ShouldProvideReferralEntry)?;
 
-    if referrer_staking_account.referral_history.len() < constants::MAX_REFERRAL && referrer_exist {
-        referrer_staking_account.referral_history.push(staking_account.key());
+        let referral_entry = ctx
+            .accounts
+            .referral_entry
+            .as_mut()
+            .ok_or(ErrorCode::ShouldProvideReferralEntry)?;
+
+        referral_entry.referrer = referrer_staking_account.key();
+        referral_entry.referee = staking_account.key();
+        referral_entry.level = level;
+        referral_entry.created_at = now;
+        referral_entry.volume = 0;
+        referral_entry.bump = bump;
+
+        emit!(ReferralLinkCreated {
+            referrer: referral_entry.referrer,
+            referee: referral_entry.referee,
+            referral_entry: referral_entry.key(),
+            level,
+            volume: 0,
+            timestamp: now,
+        });
     }
 
+    emit!(StakingAccountInitialized {
+        user: user_key,
+        staking_account: staking_account.key(),
+        referrer: staking_account.referrer,
+        timestamp: now,
+    });
+
     Ok(())
 }
 
@@ -49,6 +94,12 @@ pub struct Initialize<'info> {
     )]
     pub global_config: Account<'info, GlobalConfig>,
 
+    #[account(
+        seeds = [b"roles"],
+        bump = role_registry.bump
+    )]
+    pub role_registry: Account<'info, RoleRegistry>,
+
     #[account(
         init,
         payer = user,
@@ -67,6 +118,23 @@ pub struct Initialize<'info> {
     )]
     pub referrer_staking_account: Account<'info, StakingAccount>,
 
+    // Required when a referrer is set
+    #[account(
+        init,
+        payer = user,
+        space = ReferralEntry::LEN,
+        seeds = [b"referral", referrer_staking_account.key().as_ref(), staking_account.key().as_ref()],
+        bump
+    )]
+    pub referral_entry: Option<Account<'info, ReferralEntry>>,
+
+    // Link of the referrer to its own referrer, required when the referrer has one
+    #[account(
+        seeds = [b"referral", referrer_staking_account.referrer.as_ref(), referrer_staking_account.key().as_ref()],
+        bump = referrer_referral_entry.bump
+    )]
+    pub referrer_referral_entry: Option<Account<'info, ReferralEntry>>,
+
     /// CHECK: user account
     pub user_key: UncheckedAccount<'info>,
     #[account(mut)]
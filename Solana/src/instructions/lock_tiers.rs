@@ -0,0 +1,204 @@
+use crate::constants::constants;
+use crate::errors::ErrorCode;
+use crate::instructions::{authorize_privileged, update_config::apply_config_change};
+use crate::state::{
+    ApySchedule, ConfigChange, GlobalConfig, LockTier, Multisig, Proposal, ProposalAction,
+    TierRegistry, APY_HISTORY_LEN, LEGACY_TIER_ID, MAX_LOCK_TIERS, RoleRegistry,
+};
+use anchor_lang::prelude::*;
+
+#[derive(Accounts)]
+pub struct InitializeTierRegistry<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = global_config.config_bumps
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = TierRegistry::LEN,
+        seeds = [b"tiers"],
+        bump
+    )]
+    pub tier_registry: AccountLoader<'info, TierRegistry>,
+
+    #[account(
+        seeds = [b"roles"],
+        bump = role_registry.bump
+    )]
+    pub role_registry: Account<'info, RoleRegistry>,
+
+    #[account(
+        mut,
+        constraint = role_registry.has_role(&authority.key(), RoleRegistry::CONFIG_MANAGER) @ ErrorCode::MissingRole
+    )]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/*
+ * The legacy tier starts as the one-year lock of the first layout with a copy of the global
+ * APY history, so migrated positions are paid the global rates up to now and the tier's own after.
+ * Pins of the global history belong to referral checkpoints and are not copied.
+ */
+pub fn initialize_tier_registry(ctx: Context<InitializeTierRegistry>) -> Result<()> {
+    let global_config = &ctx.accounts.global_config;
+    let mut tier_registry = ctx.accounts.tier_registry.load_init()?;
+
+    let mut apy_history = global_config.apy_history;
+    apy_history.pins = [0; APY_HISTORY_LEN];
+
+    tier_registry.tiers[LEGACY_TIER_ID as usize] = LockTier {
+        apy_history,
+        duration: constants::ONE_YEAR_PERIOD,
+        current_apy_bps: global_config.current_apy_bps,
+        deposit_fee_bps: global_config.deposit_fee_bps,
+        active: 1,
+        ..LockTier::default()
+    };
+    tier_registry.tier_count = LEGACY_TIER_ID + 1;
+    tier_registry.bump = ctx.bumps.tier_registry;
+
+    msg!("Tier registry initialized");
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct UpdateTierRegistry<'info> {
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = global_config.config_bumps
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"tiers"],
+        bump = tier_registry.load()?.bump
+    )]
+    pub tier_registry: AccountLoader<'info, TierRegistry>,
+
+    #[account(
+        seeds = [b"roles"],
+        bump = role_registry.bump
+    )]
+    pub role_registry: Account<'info, RoleRegistry>,
+
+    #[account(
+        seeds = [b"multisig"],
+        bump = multisig.bump
+    )]
+    pub multisig: Option<Account<'info, Multisig>>,
+
+    // Approved proposal, replaces the role check of the signer
+    #[account(
+        mut,
+        seeds = [b"proposal", proposal.id.to_le_bytes().as_ref()],
+        bump = proposal.bump
+    )]
+    pub proposal: Option<Account<'info, Proposal>>,
+
+    pub authority: Signer<'info>,
+}
+
+// Only while the timelock is off, otherwise queue ConfigChange::LockTier
+pub fn set_lock_tier(
+    ctx: Context<UpdateTierRegistry>,
+    tier_id: u8,
+    duration: i64,
+    apy: u32,
+    deposit_fee_bps: u16,
+    active: bool,
+) -> Result<()> {
+    authorize_privileged(
+        &ctx.accounts.global_config,
+        &ctx.accounts.role_registry,
+        &ctx.accounts.authority.key(),
+        RoleRegistry::CONFIG_MANAGER,
+        ctx.accounts.multisig.as_deref(),
+        ctx.accounts.proposal.as_deref_mut(),
+        ProposalAction::SetLockTier {
+            tier_id,
+            duration,
+            apy_bps: apy,
+            deposit_fee_bps,
+            active,
+        },
+    )?;
+
+    let global_config = &mut ctx.accounts.global_config;
+    let mut tier_registry = ctx.accounts.tier_registry.load_mut()?;
+
+    require!(global_config.min_timelock_delay == 0, ErrorCode::TimelockRequired);
+
+    apply_config_change(
+        global_config,
+        Some(&mut *tier_registry),
+        ConfigChange::LockTier {
+            tier_id,
+            duration,
+            apy_bps: apy,
+            deposit_fee_bps,
+            active,
+        },
+        ctx.accounts.authority.key(),
+    )
+}
+
+/*
+ * Adds a new tier when tier_id equals the current tier count, otherwise updates an existing one.
+ * Tiers are never removed so tier ids stored in positions stay valid, deactivate them instead.
+ */
+pub(crate) fn apply_lock_tier(
+    tier_registry: &mut TierRegistry,
+    tier_id: u8,
+    duration: i64,
+    apy: u32,
+    deposit_fee_bps: u16,
+    active: bool,
+    now: i64,
+) -> Result<()> {
+    let tier_count = tier_registry.tier_count;
+
+    if tier_id == tier_count {
+        require!((tier_count as usize) < MAX_LOCK_TIERS, ErrorCode::InvalidTier);
+
+        tier_registry.tiers[tier_id as usize] = LockTier {
+            duration,
+            current_apy_bps: apy,
+            deposit_fee_bps,
+            active: active as u8,
+            apy_history: ApySchedule::new(apy, now),
+            ..LockTier::default()
+        };
+        tier_registry.tier_count = tier_count + 1;
+
+        msg!("Tier {} added", tier_id);
+        return Ok(());
+    }
+
+    require!(tier_id < tier_count, ErrorCode::InvalidTier);
+
+    let tier = &mut tier_registry.tiers[tier_id as usize];
+
+    require!(
+        tier_id != LEGACY_TIER_ID || duration == tier.duration,
+        ErrorCode::LegacyTierDurationFixed
+    );
+
+    if tier.current_apy_bps != apy {
+        tier.apy_history.record(apy, now)?;
+        tier.current_apy_bps = apy;
+    }
+
+    tier.duration = duration;
+    tier.deposit_fee_bps = deposit_fee_bps;
+    tier.active = active as u8;
+
+    msg!("Tier {} updated: {:?}", tier_id, tier);
+    Ok(())
+}
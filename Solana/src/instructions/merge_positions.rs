@@ -0,0 +1,213 @@
+use crate::{
+    constants::constants,
+    errors::ErrorCode,
+    events::PositionsMerged,
+    state::{
+        BlacklistEntry, GlobalConfig, ReferralCredit, ReferralCreditSource, StakingAccount, TierRegistry,
+        UserStakedAccount,
+    },
+};
+
+use anchor_lang::prelude::*;
+
+#[derive(Accounts)]
+pub struct MergePositions<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = global_config.config_bumps
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"tiers"],
+        bump = tier_registry.load()?.bump
+    )]
+    pub tier_registry: AccountLoader<'info, TierRegistry>,
+
+    #[account(
+        mut,
+        seeds = [b"staking", user.key().as_ref()],
+        bump
+    )]
+    pub staking_account: Account<'info, StakingAccount>,
+
+    #[account(mut, has_one = user)]
+    pub user_staked_account: Account<'info, UserStakedAccount>,
+
+    // Credits of the merged positions move here, required when one of them has a credit
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = ReferralCredit::LEN,
+        seeds = [b"referral-credit", user_staked_account.key().as_ref()],
+        bump
+    )]
+    pub referral_credit: Option<Account<'info, ReferralCredit>>,
+
+    /// CHECK: blacklist entry of the user, empty unless the user is blacklisted
+    #[account(
+        seeds = [b"blacklist", user.key().as_ref()],
+        bump,
+        constraint = !BlacklistEntry::is_blacklisted(&blacklist_entry) @ ErrorCode::Blacklisted
+    )]
+    pub blacklist_entry: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    /*
+    REMAINING_ACCOUNTS: pairs of a user_staked_account merged into user_staked_account (limit 24)
+    and its referral credit PDA, which may be empty
+     */
+}
+
+/*
+ * Consolidates positions of the same tier into user_staked_account:
+ * 1. Rewards of every position are settled in the configured accrual mode into pending_rewards
+ *    of the kept position. In daily mode the period in progress of a merged position is not paid,
+ *    the kept position's period in progress goes on with the merged amount
+ * 2. The kept position takes the latest maturity among the merged ones, its lock is extended
+ *    so claim periods stay aligned to its own staking hour
+ * 3. Referral credits of the merged positions move to the kept position, their commission stays
+ *    with the upline until the merged stake is unstaked. A credit made for another upline cannot
+ *    be added and is left to the unwind crank
+ * 4. Merged positions and their credits are closed and their rent goes back to the user
+ */
+pub fn merge_positions<'a>(ctx: Context<'_, '_, 'a, 'a, MergePositions<'a>>) -> Result<()> {
+    ctx.accounts.global_config.require_not_paused(GlobalConfig::PAUSE_STAKE)?;
+
+    let now = Clock::get()?.unix_timestamp;
+    let remaining_accounts = ctx.remaining_accounts;
+    let merged_count = remaining_accounts.len() / 2;
+
+    if remaining_accounts.is_empty() {
+        return Err(ErrorCode::NothingToMerge.into());
+    }
+
+    if remaining_accounts.len() % 2 != 0 || merged_count > constants::CLAIM_ALL_LIMIT as usize {
+        return Err(ErrorCode::ShouldProvideStakedAccounts.into());
+    }
+
+    StakingAccount::require_unique_accounts(remaining_accounts)?;
+
+    let staking_account = &mut ctx.accounts.staking_account;
+    let user_staked = &mut ctx.accounts.user_staked_account;
+    let user_staked_key = user_staked.key();
+
+    if user_staked.is_tokenized() {
+        return Err(ErrorCode::PositionTokenized.into());
+    }
+
+    if user_staked.maturity_timestamp() < now {
+        return Err(ErrorCode::StakeExpired.into());
+    }
+
+    let mut tier_registry = ctx.accounts.tier_registry.load_mut()?;
+    let tier = tier_registry.get_tier_mut(user_staked.tier_id)?;
+
+    let continuous_accrual = ctx.accounts.global_config.continuous_accrual;
+
+    user_staked.settle_rewards(tier, continuous_accrual, now)?;
+
+    for accounts in remaining_accounts.chunks(2) {
+        let (account_info, credit_info) = (&accounts[0], &accounts[1]);
+
+        if account_info.key() == user_staked_key {
+            return Err(ErrorCode::DuplicateStakedAccount.into());
+        }
+
+        let mut merged: Account<'a, UserStakedAccount> = Account::try_from(account_info)
+            .map_err(|_| ErrorCode::InvalidStakedAccount)?;
+
+        if merged.user != staking_account.user {
+            return Err(ErrorCode::InvalidUser.into());
+        }
+
+        if merged.is_tokenized() {
+            return Err(ErrorCode::PositionTokenized.into());
+        }
+
+        if merged.tier_id != user_staked.tier_id {
+            return Err(ErrorCode::TierMismatch.into());
+        }
+
+        // Expired positions stopped earning, they are unstaked instead
+        if merged.maturity_timestamp() < now {
+            return Err(ErrorCode::StakeExpired.into());
+        }
+
+        merged.settle_rewards(tier, continuous_accrual, now)?;
+        merged.stop_accrual(tier)?;
+
+        user_staked.pending_rewards = user_staked
+            .pending_rewards
+            .checked_add(merged.pending_rewards)
+            .ok_or(ErrorCode::Overflow)?;
+
+        user_staked.amount_staked = user_staked
+            .amount_staked
+            .checked_add(merged.amount_staked)
+            .ok_or(ErrorCode::Overflow)?;
+
+        if merged.maturity_timestamp() > user_staked.maturity_timestamp() {
+            user_staked.lock_duration = merged
+                .maturity_timestamp()
+                .checked_sub(user_staked.staked_timestamp)
+                .ok_or(ErrorCode::Underflow)?;
+        }
+
+        let (credit_key, _) = Pubkey::find_program_address(
+            &[b"referral-credit", account_info.key().as_ref()],
+            &crate::ID,
+        );
+
+        require_keys_eq!(credit_info.key(), credit_key, ErrorCode::InvalidReferralCredit);
+
+        if credit_info.owner == &crate::ID && !credit_info.data_is_empty() {
+            let merged_credit: Account<'a, ReferralCredit> = Account::try_from(credit_info)?;
+            let kept_credit = ctx.accounts.referral_credit.as_deref();
+
+            let same_upline = kept_credit.map_or(true, |credit| {
+                credit.source == Pubkey::default() || credit.upline == merged_credit.upline
+            });
+
+            if same_upline {
+                ReferralCredit::record(
+                    ctx.accounts.referral_credit.as_deref_mut(),
+                    ctx.bumps.referral_credit,
+                    (user_staked_key, ReferralCreditSource::Position),
+                    staking_account.key(),
+                    &merged_credit.upline,
+                    &merged_credit.level_credits(),
+                )?;
+
+                merged_credit.close(ctx.accounts.user.to_account_info())?;
+            }
+        }
+
+        merged.close(ctx.accounts.user.to_account_info())?;
+
+        msg!("Merged position: {:?}", account_info.key());
+    }
+
+    staking_account.closed_stakes_counter = staking_account
+        .closed_stakes_counter
+        .checked_add(merged_count as u64)
+        .ok_or(ErrorCode::Overflow)?;
+
+    emit!(PositionsMerged {
+        user: staking_account.user,
+        target: user_staked_key,
+        merged: remaining_accounts.iter().step_by(2).map(|account| account.key()).collect(),
+        amount_staked: user_staked.amount_staked,
+        maturity: user_staked.maturity_timestamp(),
+        timestamp: now,
+    });
+
+    msg!("Amount staked after merge: {}", user_staked.amount_staked);
+
+    Ok(())
+}
@@ -0,0 +1,360 @@
+use crate::{
+    constants::constants,
+    errors::ErrorCode,
+    events::{AccountMigrated, ReferralLinkCreated},
+    state::{
+        ApySchedule, GlobalConfig, ReferralEntry, StakingAccount, TierRegistry, UserStakedAccount,
+        LEGACY_TIER_ID,
+    },
+};
+
+use anchor_lang::{prelude::*, system_program};
+
+/*
+ * Accounts created before a layout grew are shorter than the current LEN. New fields are only ever
+ * appended, so such an account is grown in place and the appended fields read as zeroes until the
+ * migration fills them in.
+ */
+#[derive(Accounts)]
+pub struct MigrateGlobalConfig<'info> {
+    /// CHECK: config in the first layout, deserialized by the handler once it is grown
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump,
+        owner = crate::ID
+    )]
+    pub global_config: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+pub fn migrate_global_config(ctx: Context<MigrateGlobalConfig>) -> Result<()> {
+    let account_info = ctx.accounts.global_config.to_account_info();
+    let old_len = account_info.data_len();
+    let now = Clock::get()?.unix_timestamp;
+
+    require!(old_len < GlobalConfig::LEN, ErrorCode::AccountAlreadyMigrated);
+
+    grow_account(
+        &account_info,
+        &ctx.accounts.admin,
+        &ctx.accounts.system_program,
+        GlobalConfig::LEN,
+    )?;
+
+    let mut global_config = {
+        let data = account_info.try_borrow_data()?;
+        GlobalConfig::try_deserialize(&mut &data[..]).map_err(|_| ErrorCode::DeserializationError)?
+    };
+
+    require_keys_eq!(global_config.admin, ctx.accounts.admin.key(), ErrorCode::OnlyAdmin);
+
+    /*
+     * The first layout only kept the rate that worked before the last change and the time
+     * the current rate started. The previous rate stays in effect for everything before that.
+     */
+    let current_apy = global_config.current_apy_bps;
+
+    global_config.apy_history = match global_config.legacy_apy_history.first() {
+        Some(change) if change.apy_bps != current_apy => {
+            let mut apy_history = ApySchedule::new(change.apy_bps, change.timestamp - 1);
+            apy_history.record(current_apy, change.timestamp)?;
+            apy_history
+        }
+        Some(change) => ApySchedule::new(current_apy, change.timestamp),
+        None => ApySchedule::new(current_apy, now),
+    };
+    global_config.legacy_apy_history.clear();
+
+    {
+        let mut data = account_info.try_borrow_mut_data()?;
+        let mut cursor = std::io::Cursor::new(&mut data[..]);
+
+        GlobalConfig::try_serialize(&global_config, &mut cursor)
+            .map_err(|_| ErrorCode::SerializationError)?;
+    }
+
+    emit!(AccountMigrated {
+        account: account_info.key(),
+        old_len: old_len as u32,
+        new_len: GlobalConfig::LEN as u32,
+        payer: ctx.accounts.admin.key(),
+        timestamp: now,
+    });
+
+    msg!("Global config migrated to the current layout.");
+
+    Ok(())
+}
+
+// Anyone may pay for the migration, it only grows the account and never changes balances
+#[derive(Accounts)]
+pub struct MigrateStakingAccount<'info> {
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = global_config.config_bumps
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    /// CHECK: staking account in the first layout, deserialized by the handler once it is grown
+    #[account(
+        mut,
+        seeds = [b"staking", user.key().as_ref()],
+        bump,
+        owner = crate::ID
+    )]
+    pub staking_account: UncheckedAccount<'info>,
+
+    /// CHECK: owner of the staking account, only used to derive its address
+    pub user: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+pub fn migrate_staking_account(ctx: Context<MigrateStakingAccount>) -> Result<()> {
+    let account_info = ctx.accounts.staking_account.to_account_info();
+    let old_len = account_info.data_len();
+    let now = Clock::get()?.unix_timestamp;
+
+    if old_len < StakingAccount::LEN {
+        grow_account(
+            &account_info,
+            &ctx.accounts.payer,
+            &ctx.accounts.system_program,
+            StakingAccount::LEN,
+        )?;
+    }
+
+    let mut staking_account = {
+        let data = account_info.try_borrow_data()?;
+        StakingAccount::try_deserialize(&mut &data[..]).map_err(|_| ErrorCode::DeserializationError)?
+    };
+
+    // Referral rewards of the first layout were never pinned in the APY history
+    let needs_referral_pin =
+        !staking_account.referral_apy_pinned && staking_account.daily_referral_rewards > 0;
+
+    require!(
+        old_len < StakingAccount::LEN
+            || !staking_account.referral_history.is_empty()
+            || needs_referral_pin,
+        ErrorCode::AccountAlreadyMigrated
+    );
+
+    // Referral links live in ReferralEntry PDAs, the referees are still known from their own referrer
+    staking_account.referral_history.clear();
+
+    if needs_referral_pin {
+        staking_account.repin_referral_checkpoint(&mut ctx.accounts.global_config.apy_history, None)?;
+    }
+
+    {
+        let mut data = account_info.try_borrow_mut_data()?;
+        let mut cursor = std::io::Cursor::new(&mut data[..]);
+
+        StakingAccount::try_serialize(&staking_account, &mut cursor)
+            .map_err(|_| ErrorCode::SerializationError)?;
+    }
+
+    emit!(AccountMigrated {
+        account: account_info.key(),
+        old_len: old_len as u32,
+        new_len: account_info.data_len() as u32,
+        payer: ctx.accounts.payer.key(),
+        timestamp: now,
+    });
+
+    msg!("Staking account migrated to the current layout.");
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct MigrateUserStakedAccount<'info> {
+    #[account(
+        mut,
+        seeds = [b"tiers"],
+        bump = tier_registry.load()?.bump
+    )]
+    pub tier_registry: AccountLoader<'info, TierRegistry>,
+
+    /// CHECK: position in the first layout, deserialized by the handler once it is grown
+    #[account(
+        mut,
+        owner = crate::ID
+    )]
+    pub user_staked_account: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/*
+ * Positions of the first layout had no tier, they matured one year after staking and were paid
+ * at the global APY. They move to the legacy tier with their original one-year lock, a zero lock
+ * would make them mature immediately. Rewards since the last claim are paid at the legacy tier's rates,
+ * which start with the global APY history copied by initialize_tier_registry.
+ */
+pub fn migrate_user_staked_account(ctx: Context<MigrateUserStakedAccount>) -> Result<()> {
+    let account_info = ctx.accounts.user_staked_account.to_account_info();
+    let old_len = account_info.data_len();
+    let now = Clock::get()?.unix_timestamp;
+
+    require!(old_len < UserStakedAccount::LEN, ErrorCode::AccountAlreadyMigrated);
+
+    grow_account(
+        &account_info,
+        &ctx.accounts.payer,
+        &ctx.accounts.system_program,
+        UserStakedAccount::LEN,
+    )?;
+
+    let mut user_staked_account = {
+        let data = account_info.try_borrow_data()?;
+        UserStakedAccount::try_deserialize(&mut &data[..])
+            .map_err(|_| ErrorCode::DeserializationError)?
+    };
+
+    user_staked_account.tier_id = LEGACY_TIER_ID;
+    user_staked_account.lock_duration = constants::ONE_YEAR_PERIOD;
+    user_staked_account.position_mint = Pubkey::default();
+
+    let mut tier_registry = ctx.accounts.tier_registry.load_mut()?;
+    let tier = tier_registry.get_tier_mut(LEGACY_TIER_ID)?;
+    let last_claimed_timestamp = user_staked_account.last_claimed_timestamp;
+
+    user_staked_account.start_accrual(tier, last_claimed_timestamp)?;
+
+    {
+        let mut data = account_info.try_borrow_mut_data()?;
+        let mut cursor = std::io::Cursor::new(&mut data[..]);
+
+        UserStakedAccount::try_serialize(&user_staked_account, &mut cursor)
+            .map_err(|_| ErrorCode::SerializationError)?;
+    }
+
+    emit!(AccountMigrated {
+        account: account_info.key(),
+        old_len: old_len as u32,
+        new_len: UserStakedAccount::LEN as u32,
+        payer: ctx.accounts.payer.key(),
+        timestamp: now,
+    });
+
+    msg!("Position migrated to the legacy tier.");
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct BackfillReferralEntry<'info> {
+    #[account(
+        seeds = [b"staking", user.key().as_ref()],
+        bump,
+        constraint = staking_account.referrer != Pubkey::default() @ ErrorCode::InvalidReferrer
+    )]
+    pub staking_account: Account<'info, StakingAccount>,
+
+    #[account(address = staking_account.referrer @ ErrorCode::ReferrerMismatch)]
+    pub referrer_staking_account: Account<'info, StakingAccount>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = ReferralEntry::LEN,
+        seeds = [b"referral", referrer_staking_account.key().as_ref(), staking_account.key().as_ref()],
+        bump
+    )]
+    pub referral_entry: Account<'info, ReferralEntry>,
+
+    // Link of the referrer to its own referrer, required when the referrer has one
+    #[account(
+        seeds = [b"referral", referrer_staking_account.referrer.as_ref(), referrer_staking_account.key().as_ref()],
+        bump = referrer_referral_entry.bump
+    )]
+    pub referrer_referral_entry: Option<Account<'info, ReferralEntry>>,
+
+    /// CHECK: owner of the staking account, only used to derive its address
+    pub user: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/*
+ * Links made before referral entries existed only live in the referee's referrer field, and every
+ * instruction recording volume requires the entry. Permissionless, the entry only restates the link
+ * already stored on chain. The stake already held counts as the volume of the link, as in add_referrer.
+ * Levels come from the parent link, so a tree is backfilled from the top down.
+ */
+pub fn backfill_referral_entry(ctx: Context<BackfillReferralEntry>) -> Result<()> {
+    let staking_account = &ctx.accounts.staking_account;
+    let referrer_account = &ctx.accounts.referrer_staking_account;
+    let parent_entry = ctx.accounts.referrer_referral_entry.as_deref();
+
+    if referrer_account.referrer != Pubkey::default() {
+        require!(parent_entry.is_some(), ErrorCode::ShouldProvideReferralEntry);
+    }
+
+    let now = Clock::get()?.unix_timestamp;
+    let referral_entry = &mut ctx.accounts.referral_entry;
+
+    referral_entry.referrer = referrer_account.key();
+    referral_entry.referee = staking_account.key();
+    referral_entry.level = ReferralEntry::level_below(parent_entry);
+    referral_entry.created_at = now;
+    referral_entry.volume = staking_account.total_staked;
+    referral_entry.bump = ctx.bumps.referral_entry;
+
+    emit!(ReferralLinkCreated {
+        referrer: referral_entry.referrer,
+        referee: referral_entry.referee,
+        referral_entry: referral_entry.key(),
+        level: referral_entry.level,
+        volume: referral_entry.volume,
+        timestamp: now,
+    });
+
+    msg!("Referral entry backfilled for {}", staking_account.key());
+
+    Ok(())
+}
+
+// Tops the account up to the rent-exempt minimum of the new length before growing it
+pub(crate) fn grow_account<'info>(
+    account_info: &AccountInfo<'info>,
+    payer: &Signer<'info>,
+    system_program: &Program<'info, System>,
+    len: usize,
+) -> Result<()> {
+    let rent = Rent::get()?
+        .minimum_balance(len)
+        .saturating_sub(account_info.lamports());
+
+    if rent > 0 {
+        let cpi_context = CpiContext::new(
+            system_program.to_account_info(),
+            system_program::Transfer {
+                from: payer.to_account_info(),
+                to: account_info.clone(),
+            },
+        );
+        system_program::transfer(cpi_context, rent)?;
+    }
+
+    account_info.realloc(len, true)?;
+
+    Ok(())
+}
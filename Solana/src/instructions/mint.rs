@@ -1,15 +1,35 @@
 use anchor_lang::prelude::*;
 use anchor_spl::token::{Mint, MintTo, Token, TokenAccount};
 
-use crate::{constants, errors::ErrorCode, state::GlobalConfig};
+use crate::{
+    errors::ErrorCode,
+    events::TokensMinted,
+    instructions::authorize_privileged,
+    state::{GlobalConfig, Multisig, Proposal, ProposalAction, RoleRegistry},
+};
 
 pub fn mint_tokens(ctx: Context<MintToken>, amount: u64) -> Result<()> {
+    ctx.accounts.global_config.require_not_paused(GlobalConfig::PAUSE_MINT)?;
+
     require!(amount > 0, ErrorCode::InvalidMintAmount);
 
-    require!(
-        amount + ctx.accounts.mint.supply <= constants::MAX_SUPPLY,
-        ErrorCode::MaxSupplyReached
-    );
+    authorize_privileged(
+        &ctx.accounts.global_config,
+        &ctx.accounts.role_registry,
+        &ctx.accounts.authority.key(),
+        RoleRegistry::MINTER,
+        ctx.accounts.multisig.as_deref(),
+        ctx.accounts.proposal.as_deref_mut(),
+        ProposalAction::MintTokens {
+            amount,
+            destination: ctx.accounts.associated_token_account.key(),
+        },
+    )?;
+
+    let now = Clock::get()?.unix_timestamp;
+    let mint_supply = ctx.accounts.mint.supply;
+
+    ctx.accounts.global_config.reserve_admin_mint(amount, mint_supply, now)?;
 
     let manager_bumps = ctx.accounts.global_config.transfer_manager_bumps.clone();
     let manager_seeds: &[&[&[u8]]] = &[&[b"transfer_manager", &[manager_bumps]]];
@@ -25,6 +45,16 @@ pub fn mint_tokens(ctx: Context<MintToken>, amount: u64) -> Result<()> {
     );
     anchor_spl::token::mint_to(context, amount)?;
 
+    emit!(TokensMinted {
+        authority: ctx.accounts.authority.key(),
+        destination: ctx.accounts.associated_token_account.key(),
+        amount,
+        supply: mint_supply
+            .checked_add(amount)
+            .ok_or(ErrorCode::Overflow)?,
+        timestamp: now,
+    });
+
     msg!("Minted {} tokens.", amount);
 
     Ok(())
@@ -33,7 +63,7 @@ pub fn mint_tokens(ctx: Context<MintToken>, amount: u64) -> Result<()> {
 #[derive(Accounts)]
 pub struct MintToken<'info> {
     #[account(
-        has_one = admin,
+        mut,
         seeds = [b"config"],
         bump = global_config.config_bumps
     )]
@@ -53,8 +83,28 @@ pub struct MintToken<'info> {
     )]
     pub transfer_manager: AccountInfo<'info>,
 
+    #[account(
+        seeds = [b"roles"],
+        bump = role_registry.bump
+    )]
+    pub role_registry: Account<'info, RoleRegistry>,
+
+    #[account(
+        seeds = [b"multisig"],
+        bump = multisig.bump
+    )]
+    pub multisig: Option<Account<'info, Multisig>>,
+
+    // Approved proposal, replaces the role check of the signer
+    #[account(
+        mut,
+        seeds = [b"proposal", proposal.id.to_le_bytes().as_ref()],
+        bump = proposal.bump
+    )]
+    pub proposal: Option<Account<'info, Proposal>>,
+
     #[account(mut)]
-    pub admin: Signer<'info>,
+    pub authority: Signer<'info>,
     #[account(mut)]
     pub associated_token_account: Account<'info, TokenAccount>,
     /// CHECK: The SPL Token Program.
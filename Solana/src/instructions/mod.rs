@@ -6,6 +6,7 @@ pub mod get_rewards_by_user;
 pub mod get_all_rewards_by_user;
 pub mod get_current_rewards_by_user;
 pub mod get_referral_rewards;
+pub mod get_referral_rank;
 pub mod remove_freeze_authority;
 pub mod add_referrer;
 
@@ -15,9 +16,27 @@ pub mod stake;
 pub mod update_config;
 
 pub mod ownable;
-pub mod set_referrals_info;
+pub mod adjust_referral_info;
 
 pub mod otc_buy;
+pub mod otc_desk;
+pub mod unstake;
+pub mod lock_tiers;
+pub mod config_timelock;
+pub mod compound_rewards;
+pub mod increase_stake;
+pub mod merge_positions;
+pub mod split_position;
+pub mod tokenize_position;
+pub mod blacklist;
+pub mod pause;
+pub mod multisig;
+pub mod get_emissions_budget;
+pub mod reward_vault;
+pub mod referral;
+pub mod unwind_referral_credit;
+pub mod migrate_accounts;
+pub mod checkpoint_rewards;
 
 pub use claim_rewards::*;
 pub use initialize_config::*;
@@ -29,10 +48,29 @@ pub use get_rewards_by_user::*;
 pub use get_current_rewards_by_user::*;
 pub use claim_referral_rewards::*;
 pub use get_referral_rewards::*;
+pub use get_referral_rank::*;
 pub use ownable::*;
 pub use remove_freeze_authority::*;
 pub use add_referrer::*;
-pub use set_referrals_info::*;
+pub use adjust_referral_info::*;
 pub use claim_all_rewards::*;
 pub use get_all_rewards_by_user::*;
 pub use otc_buy::*;
+pub use otc_desk::*;
+pub use unstake::*;
+pub use lock_tiers::*;
+pub use config_timelock::*;
+pub use compound_rewards::*;
+pub use increase_stake::*;
+pub use merge_positions::*;
+pub use split_position::*;
+pub use tokenize_position::*;
+pub use blacklist::*;
+pub use pause::*;
+pub use multisig::*;
+pub use get_emissions_budget::*;
+pub use reward_vault::*;
+pub use referral::*;
+pub use unwind_referral_credit::*;
+pub use migrate_accounts::*;
+pub use checkpoint_rewards::*;
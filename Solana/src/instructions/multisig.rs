@@ -0,0 +1,342 @@
+use crate::errors::ErrorCode;
+use crate::events::{MultisigUpdated, ProposalApproved, ProposalCreated, ProposalExecuted};
+use crate::instructions::{validate_config_change, validate_referral_adjustment};
+use crate::state::{ConfigChange, GlobalConfig, Multisig, Proposal, ProposalAction, RoleRegistry};
+use anchor_lang::prelude::*;
+
+#[derive(Accounts)]
+pub struct InitializeMultisig<'info> {
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [b"config"],
+        bump = global_config.config_bumps
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = Multisig::LEN,
+        seeds = [b"multisig"],
+        bump
+    )]
+    pub multisig: Account<'info, Multisig>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/*
+ * From here on the instructions checked by authorize_privileged and authorize_admin only run
+ * through approved proposals and the signer set itself can only be changed by a proposal.
+ */
+pub fn initialize_multisig(
+    ctx: Context<InitializeMultisig>,
+    signers: Vec<Pubkey>,
+    threshold: u8,
+) -> Result<()> {
+    let multisig = &mut ctx.accounts.multisig;
+
+    multisig.set_signers(&signers, threshold)?;
+    multisig.proposal_counter = 0;
+    multisig.bump = ctx.bumps.multisig;
+
+    ctx.accounts.global_config.multisig_required = true;
+
+    emit!(MultisigUpdated {
+        signers,
+        threshold,
+        nonce: multisig.nonce,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    msg!("Multisig initialized with threshold {}", threshold);
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct CreateProposal<'info> {
+    #[account(
+        mut,
+        seeds = [b"multisig"],
+        bump = multisig.bump
+    )]
+    pub multisig: Account<'info, Multisig>,
+
+    #[account(
+        init,
+        payer = proposer,
+        space = Proposal::LEN,
+        seeds = [b"proposal", multisig.proposal_counter.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub proposal: Account<'info, Proposal>,
+
+    #[account(mut)]
+    pub proposer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+// The proposer approves its own proposal
+pub fn create_proposal(ctx: Context<CreateProposal>, action: ProposalAction) -> Result<()> {
+    let multisig = &mut ctx.accounts.multisig;
+    let proposal = &mut ctx.accounts.proposal;
+    let proposer = ctx.accounts.proposer.key();
+    let now = Clock::get()?.unix_timestamp;
+
+    validate_action(&action)?;
+
+    proposal.id = multisig.proposal_counter;
+    proposal.action = action;
+    proposal.proposer = proposer;
+    proposal.approvals = 0;
+    proposal.multisig_nonce = multisig.nonce;
+    proposal.created_at = now;
+    proposal.executed = false;
+    proposal.bump = ctx.bumps.proposal;
+
+    proposal.approve(multisig, &proposer)?;
+
+    multisig.proposal_counter = multisig
+        .proposal_counter
+        .checked_add(1)
+        .ok_or(ErrorCode::Overflow)?;
+
+    emit!(ProposalCreated {
+        id: proposal.id,
+        action,
+        proposer,
+        timestamp: now,
+    });
+
+    msg!("Proposal {} created: {:?}", proposal.id, action);
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct ApproveProposal<'info> {
+    #[account(
+        seeds = [b"multisig"],
+        bump = multisig.bump
+    )]
+    pub multisig: Account<'info, Multisig>,
+
+    #[account(
+        mut,
+        seeds = [b"proposal", proposal.id.to_le_bytes().as_ref()],
+        bump = proposal.bump
+    )]
+    pub proposal: Account<'info, Proposal>,
+
+    pub signer: Signer<'info>,
+}
+
+pub fn approve_proposal(ctx: Context<ApproveProposal>) -> Result<()> {
+    let proposal = &mut ctx.accounts.proposal;
+    let signer = ctx.accounts.signer.key();
+
+    proposal.approve(&ctx.accounts.multisig, &signer)?;
+
+    emit!(ProposalApproved {
+        id: proposal.id,
+        signer,
+        approvals: proposal.approval_count(),
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    msg!(
+        "Proposal {} approved by {}, {}/{} approvals",
+        proposal.id,
+        signer,
+        proposal.approval_count(),
+        ctx.accounts.multisig.threshold
+    );
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct UpdateMultisig<'info> {
+    #[account(
+        mut,
+        seeds = [b"multisig"],
+        bump = multisig.bump
+    )]
+    pub multisig: Account<'info, Multisig>,
+
+    #[account(
+        mut,
+        seeds = [b"proposal", proposal.id.to_le_bytes().as_ref()],
+        bump = proposal.bump
+    )]
+    pub proposal: Account<'info, Proposal>,
+
+    pub executor: Signer<'info>,
+}
+
+// Permissionless once the proposal reached the threshold
+pub fn update_multisig(ctx: Context<UpdateMultisig>) -> Result<()> {
+    let multisig = &mut ctx.accounts.multisig;
+    let proposal = &mut ctx.accounts.proposal;
+    let action = proposal.action;
+
+    let ProposalAction::UpdateMultisig {
+        signers,
+        signer_count,
+        threshold,
+    } = action
+    else {
+        return Err(ErrorCode::ProposalMismatch.into());
+    };
+
+    proposal.execute(multisig, &action)?;
+
+    let signers = &signers[..signer_count as usize];
+    multisig.set_signers(signers, threshold)?;
+
+    let now = Clock::get()?.unix_timestamp;
+
+    emit!(ProposalExecuted {
+        id: proposal.id,
+        action,
+        executor: ctx.accounts.executor.key(),
+        timestamp: now,
+    });
+
+    emit!(MultisigUpdated {
+        signers: signers.to_vec(),
+        threshold,
+        nonce: multisig.nonce,
+        timestamp: now,
+    });
+
+    msg!("Multisig updated with threshold {}", threshold);
+    Ok(())
+}
+
+fn validate_action(action: &ProposalAction) -> Result<()> {
+    match *action {
+        ProposalAction::MintTokens { amount, .. } => {
+            require!(amount > 0, ErrorCode::InvalidMintAmount);
+        }
+        ProposalAction::AdjustReferralInfo {
+            total_referral_staked_delta,
+            daily_referral_rewards_delta,
+            reason,
+            memo_hash,
+            ..
+        } => {
+            validate_referral_adjustment(
+                total_referral_staked_delta,
+                daily_referral_rewards_delta,
+                reason,
+                &memo_hash,
+            )?;
+        }
+        ProposalAction::UpdateMultisig {
+            signer_count,
+            threshold,
+            ..
+        } => {
+            require!(
+                threshold > 0 && threshold <= signer_count,
+                ErrorCode::InvalidMultisig
+            );
+        }
+        ProposalAction::AirdropStake { amount, .. } => {
+            require!(amount > 0, ErrorCode::InvalidAmount);
+        }
+        ProposalAction::GrantRoles { roles, .. } | ProposalAction::RevokeRoles { roles, .. } => {
+            require!(
+                roles != 0 && roles & !RoleRegistry::ALL_ROLES == 0,
+                ErrorCode::InvalidRoles
+            );
+        }
+        ProposalAction::SetLockTier {
+            tier_id,
+            duration,
+            apy_bps,
+            deposit_fee_bps,
+            active,
+        } => {
+            validate_config_change(&ConfigChange::LockTier {
+                tier_id,
+                duration,
+                apy_bps,
+                deposit_fee_bps,
+                active,
+            })?;
+        }
+        ProposalAction::QueueConfigChange { change } | ProposalAction::UpdateConfig { change } => {
+            validate_config_change(&change)?;
+        }
+        ProposalAction::CancelConfigChange { .. } | ProposalAction::TransferOwnership { .. } => {}
+    }
+
+    Ok(())
+}
+
+/*
+ * Privileged handlers run either for a holder of the role or for an approved proposal
+ * carrying exactly the same action. Once the multisig is required only the latter works.
+ */
+pub(crate) fn authorize_privileged(
+    global_config: &GlobalConfig,
+    role_registry: &RoleRegistry,
+    authority: &Pubkey,
+    role: u8,
+    multisig: Option<&Multisig>,
+    proposal: Option<&mut Proposal>,
+    action: ProposalAction,
+) -> Result<()> {
+    let Some(proposal) = proposal else {
+        require!(!global_config.multisig_required, ErrorCode::MultisigRequired);
+        require!(role_registry.has_role(authority, role), ErrorCode::MissingRole);
+
+        return Ok(());
+    };
+
+    execute_proposal(authority, multisig, proposal, action)
+}
+
+// Same as authorize_privileged for instructions that belong to the admin rather than to a role
+pub(crate) fn authorize_admin(
+    global_config: &GlobalConfig,
+    authority: &Pubkey,
+    multisig: Option<&Multisig>,
+    proposal: Option<&mut Proposal>,
+    action: ProposalAction,
+) -> Result<()> {
+    let Some(proposal) = proposal else {
+        require!(!global_config.multisig_required, ErrorCode::MultisigRequired);
+        require_keys_eq!(*authority, global_config.admin, ErrorCode::OnlyAdmin);
+
+        return Ok(());
+    };
+
+    execute_proposal(authority, multisig, proposal, action)
+}
+
+fn execute_proposal(
+    authority: &Pubkey,
+    multisig: Option<&Multisig>,
+    proposal: &mut Proposal,
+    action: ProposalAction,
+) -> Result<()> {
+    let multisig = multisig.ok_or(ErrorCode::MultisigRequired)?;
+
+    proposal.execute(multisig, &action)?;
+
+    emit!(ProposalExecuted {
+        id: proposal.id,
+        action,
+        executor: *authority,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    msg!("Executed through proposal {}", proposal.id);
+    Ok(())
+}
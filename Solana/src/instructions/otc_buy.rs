@@ -1,145 +1,237 @@
 use crate::errors::ErrorCode;
-use crate::state::{GlobalConfig, StakingAccount, UserStakedAccount};
+use crate::events::{OtcCommissionPaid, OtcPurchase};
+use crate::instructions::{open_position, position_nft_account, NewPosition, PositionNft};
+use crate::state::{BlacklistEntry, GlobalConfig, OtcDesk, OtcWalletLimit, PriceOracle, ReferralCredit, ReferralEntry, RoleRegistry, StakingAccount, TierRegistry, UserStakedAccount};
 use anchor_lang::{prelude::*, system_program};
-use anchor_spl::token::Token;
+use anchor_spl::associated_token::AssociatedToken;
+use anchor_spl::metadata::Metadata;
+use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer};
 
 pub fn handler<'a>(
     ctx: Context<'_, '_, 'a, 'a, OtcBuy<'a>>,
     sol_amount: u64,
-    toon_amount: u64,
+    max_price: u64,
+    tier_id: u8,
 ) -> Result<()> {
-    if sol_amount == 0 || toon_amount == 0 {
+    ctx.accounts.global_config.require_not_paused(GlobalConfig::PAUSE_OTC)?;
+
+    if sol_amount == 0 {
         return Err(ErrorCode::InvalidAmount.into());
     }
 
-    let global_config = &mut ctx.accounts.global_config;
-    let staking_account = &mut ctx.accounts.staking_account;
-    let user_staked = &mut ctx.accounts.user_staked_account;
-    let buyer = ctx.accounts.user.to_account_info();
-    let treasury = ctx.accounts.treasury.to_account_info();
-    let referrer = ctx.accounts.referrer.to_account_info();
-    let sys_program = ctx.accounts.system_program.to_account_info();
+    let position_nft = ctx.accounts.position_nft()?;
+    let mut tier_registry = ctx.accounts.tier_registry.load_mut()?;
+    let tier = tier_registry.get_tier_mut(tier_id)?;
+    require!(tier.is_active(), ErrorCode::InactiveTier);
 
-    let referrer_key = referrer.key();
-
-    if treasury.key() != global_config.treasury.key() {
-        return Err(ErrorCode::InvalidTreasury.into());
-    }
-
-    let treasury_amount = sol_amount * 80 / 100;
-    let referrer_amount = sol_amount - treasury_amount;
+    let now = Clock::get()?.unix_timestamp;
+    let otc_desk = &ctx.accounts.otc_desk;
 
-    msg!("Sending proceeds to treasury and referrer");
+    // The amount is priced on-chain, the buyer only bounds the price it accepts
+    let price = otc_desk.price(&ctx.accounts.price_oracle, now)?;
+    require!(price <= max_price, ErrorCode::OtcPriceAboveMax);
 
-    send_sol(buyer.clone(), treasury, treasury_amount, sys_program.clone())?;
-    msg!("Sent {} SOL to treasury", treasury_amount);
+    let toon_amount = OtcDesk::tokens_for(sol_amount, price, ctx.accounts.mint.decimals)?;
+    otc_desk.check_purchase_size(toon_amount)?;
 
-    send_sol(buyer, referrer, referrer_amount, sys_program)?;
-    msg!("Sent {} SOL to referrer", referrer_amount);
+    let otc_wallet_limit = &mut ctx.accounts.otc_wallet_limit;
 
-    let remaining_accounts = ctx.remaining_accounts;
+    otc_wallet_limit.user = ctx.accounts.user.key();
+    otc_wallet_limit.bump = ctx.bumps.otc_wallet_limit;
+    otc_wallet_limit.record(toon_amount, otc_desk.settings.daily_wallet_cap, now)?;
 
-    if remaining_accounts.len() > 0 {
-        if remaining_accounts.len() > global_config.lvl_percentages.len() {
-            return Err(ErrorCode::OnlyTenReferralsLevel.into());
-        }
+    let global_config = &mut ctx.accounts.global_config;
+    let staking_account = &mut ctx.accounts.staking_account;
+    let user_staked = &mut ctx.accounts.user_staked_account;
+    let commission = global_config.otc_commission;
 
-        apply_referral_rewards_for_all_referrer(
-            remaining_accounts,
-            global_config,
-            toon_amount,
-            staking_account.referrer,
-            referrer_key,
-        )?;
-    } else if staking_account.referrer != Pubkey::default() {
-        return Err(ErrorCode::ShouldProvideReferrer.into());
+    if ctx.accounts.treasury.key() != global_config.treasury.key() {
+        return Err(ErrorCode::InvalidTreasury.into());
     }
 
-    user_staked.amount_staked = toon_amount;
-
-    msg!("Amount staked: {}", toon_amount);
+    let (quote_account, treasury) = if commission.pays_in_sol() {
+        (None, ctx.accounts.treasury.to_account_info())
+    } else {
+        let (Some(buyer_quote_account), Some(treasury_quote_account)) = (
+            ctx.accounts.buyer_quote_account.as_ref(),
+            ctx.accounts.treasury_quote_account.as_ref(),
+        ) else {
+            return Err(ErrorCode::ShouldProvideQuoteAccounts.into());
+        };
+
+        (
+            Some(buyer_quote_account.to_account_info()),
+            treasury_quote_account.to_account_info(),
+        )
+    };
+
+    let payer = OtcPayer {
+        buyer: ctx.accounts.user.to_account_info(),
+        quote_account,
+        system_program: ctx.accounts.system_program.to_account_info(),
+        token_program: ctx.accounts.token_program.to_account_info(),
+    };
+
+    // Remaining accounts: the upline staking accounts, then one commission account per level
+    let remaining_accounts = ctx.remaining_accounts;
+    let depth = StakingAccount::upline_depth(&staking_account.upline);
+    let (referrer_accounts, commission_accounts) =
+        remaining_accounts.split_at(remaining_accounts.len().min(depth));
+
+    msg!("Sending proceeds to upline and treasury");
+
+    let commission_amount = pay_upline_commissions(
+        &payer,
+        global_config,
+        &staking_account.upline,
+        referrer_accounts,
+        commission_accounts,
+        sol_amount,
+        now,
+    )?;
+
+    let treasury_amount = sol_amount
+        .checked_sub(commission_amount)
+        .ok_or(ErrorCode::Underflow)?;
+
+    payer.pay(treasury, treasury_amount)?;
+    msg!("Sent {} to treasury, {} to upline", treasury_amount, commission_amount);
+
+    open_position(
+        global_config,
+        tier,
+        tier_id,
+        NewPosition {
+            staking_account,
+            user_staked,
+            referral_entry: ctx.accounts.referral_entry.as_deref_mut(),
+            referral_credit: ctx.accounts.referral_credit.as_deref_mut(),
+            referral_credit_bump: ctx.bumps.referral_credit,
+            position_nft,
+        },
+        referrer_accounts,
+        toon_amount,
+        now,
+    )?;
+
+    emit!(OtcPurchase {
+        buyer: staking_account.user,
+        user_staked_account: user_staked.key(),
+        tier_id,
+        sol_amount,
+        toon_amount,
+        price,
+        treasury_amount,
+        commission_amount,
+        quote_mint: commission.quote_mint,
+        operator: ctx.accounts.authority.key(),
+        timestamp: now,
+    });
 
-    let now = Clock::get()?.unix_timestamp;
+    Ok(())
+}
 
-    user_staked.last_claimed_timestamp = now;
-    user_staked.staked_timestamp = now;
-    user_staked.identifier = staking_account.user_staked_counter;
-    user_staked.user = staking_account.user;
+// Source of an OTC payment: SOL from the buyer's wallet, or the buyer's token account of the quote mint
+struct OtcPayer<'info> {
+    buyer: AccountInfo<'info>,
+    quote_account: Option<AccountInfo<'info>>,
+    system_program: AccountInfo<'info>,
+    token_program: AccountInfo<'info>,
+}
 
-    staking_account.total_staked = staking_account
-        .total_staked
-        .checked_add(toon_amount)
-        .ok_or(ErrorCode::Overflow)?;
+impl<'info> OtcPayer<'info> {
+    fn pay(&self, to: AccountInfo<'info>, amount: u64) -> Result<()> {
+        if amount == 0 {
+            return Ok(());
+        }
 
-    staking_account.user_staked_counter = staking_account
-        .user_staked_counter
-        .checked_add(1)
-        .ok_or(ErrorCode::Overflow)?;
+        let Some(quote_account) = &self.quote_account else {
+            return send_sol(self.buyer.clone(), to, amount, self.system_program.clone());
+        };
 
-    global_config.total_staked = global_config
-        .total_staked
-        .checked_add(toon_amount)
-        .ok_or(ErrorCode::Overflow)?;
+        let cpi_context = CpiContext::new(
+            self.token_program.clone(),
+            Transfer {
+                from: quote_account.clone(),
+                to,
+                authority: self.buyer.clone(),
+            },
+        );
 
-    Ok(())
+        token::transfer(cpi_context, amount)
+    }
 }
 
-fn apply_referral_rewards_for_all_referrer(
-    remaining_accounts: &[AccountInfo],
-    global_config: &Account<GlobalConfig>,
-    stake_amount: u64,
-    first_referrer: Pubkey,
-    provided_referrer: Pubkey,
-) -> Result<()> {
-    let mut next_referrer = first_referrer;
+/*
+ * Pays every upline level its share of the payment, straight to the referrer's wallet or to its
+ * token account of the quote mint. Levels locked by the referrer's rank are not paid.
+ * Commission accounts are only checked for levels that are paid. Returns the total paid.
+ */
+fn pay_upline_commissions<'info>(
+    payer: &OtcPayer<'info>,
+    global_config: &GlobalConfig,
+    upline: &[Pubkey],
+    referrer_accounts: &[AccountInfo<'info>],
+    commission_accounts: &[AccountInfo<'info>],
+    payment: u64,
+    now: i64,
+) -> Result<u64> {
+    StakingAccount::verify_referrer_accounts(upline, referrer_accounts)?;
+
+    require!(
+        commission_accounts.len() == referrer_accounts.len(),
+        ErrorCode::ShouldProvideCommissionAccounts
+    );
 
-    for (index, account_info) in remaining_accounts.iter().enumerate() {
-        if next_referrer != account_info.key() {
-            return Err(ErrorCode::ReferrerMismatch.into());
-        }
+    let commission = &global_config.otc_commission;
+    let mut paid: u64 = 0;
 
-        let commission_percentage = global_config.lvl_percentages.get(index).copied().unwrap_or(0);
+    for (index, (referrer_info, recipient)) in referrer_accounts.iter().zip(commission_accounts).enumerate() {
+        let amount = commission.level_amount(payment, index)?;
 
-        let commission = stake_amount
-            .checked_mul(commission_percentage as u64)
-            .ok_or(ErrorCode::Overflow)?
-            .checked_div(100)
-            .ok_or(ErrorCode::Overflow)?;
+        if amount == 0 {
+            continue;
+        }
 
-        if commission > 0 {
-            let referrer_account = &mut account_info.try_borrow_mut_data()?[..];
+        require_keys_eq!(*referrer_info.owner, crate::ID, ErrorCode::InvalidReferrer);
 
-            let mut referrer_data: StakingAccount = StakingAccount::try_deserialize(&mut &referrer_account[..])
-                .map_err(|_| ErrorCode::DeserializationError)?;
+        let referrer = StakingAccount::try_deserialize(&mut &referrer_info.try_borrow_data()?[..])
+            .map_err(|_| ErrorCode::DeserializationError)?;
 
-            if index == 0 && referrer_data.user.key() != provided_referrer {
-                return Err(ErrorCode::InvalidReferrer.into());
-            }
+        if index >= referrer.unlocked_referral_levels(global_config) as usize {
+            continue;
+        }
 
-            next_referrer = referrer_data.referrer;
+        if commission.pays_in_sol() {
+            require_keys_eq!(recipient.key(), referrer.user, ErrorCode::InvalidCommissionAccount);
+        } else {
+            require_keys_eq!(*recipient.owner, token::ID, ErrorCode::InvalidCommissionAccount);
 
-            if referrer_data.total_referral_staked == 0 {
-                referrer_data.last_referral_rewards_claimed = Clock::get()?.unix_timestamp;
-            }
+            let token_account = TokenAccount::try_deserialize(&mut &recipient.try_borrow_data()?[..])
+                .map_err(|_| ErrorCode::InvalidCommissionAccount)?;
 
-            referrer_data.total_referral_staked = referrer_data
-                .total_referral_staked
-                .checked_add(stake_amount)
-                .ok_or(ErrorCode::Overflow)?;
+            require!(
+                token_account.mint == commission.quote_mint && token_account.owner == referrer.user,
+                ErrorCode::InvalidCommissionAccount
+            );
+        }
 
-            referrer_data.daily_referral_rewards = referrer_data
-                .daily_referral_rewards
-                .checked_add(commission)
-                .ok_or(ErrorCode::Overflow)?;
+        payer.pay(recipient.clone(), amount)?;
 
-            let mut cursor = std::io::Cursor::new(referrer_account);
+        paid = paid.checked_add(amount).ok_or(ErrorCode::Overflow)?;
 
-            StakingAccount::try_serialize(&referrer_data, &mut cursor)
-                .map_err(|_| ErrorCode::SerializationError)?;
-        }
+        emit!(OtcCommissionPaid {
+            buyer: payer.buyer.key(),
+            referrer: referrer_info.key(),
+            recipient: recipient.key(),
+            level: index as u8,
+            amount,
+            quote_mint: commission.quote_mint,
+            timestamp: now,
+        });
     }
 
-    Ok(())
+    Ok(paid)
 }
 
 fn send_sol<'info>(
@@ -167,11 +259,17 @@ pub struct OtcBuy<'info> {
     #[account(
         mut,
         seeds = [b"config"],
-        bump = global_config.config_bumps,
-        has_one = admin
+        bump = global_config.config_bumps
     )]
     pub global_config: Account<'info, GlobalConfig>,
 
+    #[account(
+        mut,
+        seeds = [b"tiers"],
+        bump = tier_registry.load()?.bump
+    )]
+    pub tier_registry: AccountLoader<'info, TierRegistry>,
+
     #[account(
         mut,
         seeds = [b"staking", user.key().as_ref()],
@@ -182,14 +280,115 @@ pub struct OtcBuy<'info> {
     #[account(
         init,
         payer = user,
-        space = 8 + 8 + 8 + 8 + 8 + 32,
+        space = UserStakedAccount::LEN,
         seeds = [b"user-staked", staking_account.key().as_ref(), staking_account.user_staked_counter.to_le_bytes().as_ref()],
         bump
     )]
     pub user_staked_account: Account<'info, UserStakedAccount>,
 
+    #[account(
+        seeds = [b"otc-desk"],
+        bump = otc_desk.bump
+    )]
+    pub otc_desk: Account<'info, OtcDesk>,
+
+    #[account(
+        seeds = [b"price-oracle"],
+        bump = price_oracle.bump
+    )]
+    pub price_oracle: Account<'info, PriceOracle>,
+
+    #[account(
+        seeds = [b"mint"],
+        bump = global_config.mint_bumps
+    )]
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = OtcWalletLimit::LEN,
+        seeds = [b"otc-limit", user.key().as_ref()],
+        bump
+    )]
+    pub otc_wallet_limit: Account<'info, OtcWalletLimit>,
+
+    #[account(
+        seeds = [b"roles"],
+        bump = role_registry.bump
+    )]
+    pub role_registry: Account<'info, RoleRegistry>,
+
+    #[account(
+        mut,
+        constraint = role_registry.has_role(&authority.key(), RoleRegistry::OTC_OPERATOR) @ ErrorCode::MissingRole
+    )]
+    pub authority: Signer<'info>,
+
+    // Link of the user to its referrer, required when the user has a referrer
+    #[account(
+        mut,
+        seeds = [b"referral", staking_account.referrer.as_ref(), staking_account.key().as_ref()],
+        bump = referral_entry.bump
+    )]
+    pub referral_entry: Option<Account<'info, ReferralEntry>>,
+
+    // Commission credited to the upline for this position, required when some level was paid
+    #[account(
+        init,
+        payer = user,
+        space = ReferralCredit::LEN,
+        seeds = [b"referral-credit", user_staked_account.key().as_ref()],
+        bump
+    )]
+    pub referral_credit: Option<Account<'info, ReferralCredit>>,
+
+    /// CHECK: blacklist entry of the user, empty unless the user is blacklisted
+    #[account(
+        seeds = [b"blacklist", user.key().as_ref()],
+        bump,
+        constraint = !BlacklistEntry::is_blacklisted(&blacklist_entry) @ ErrorCode::Blacklisted
+    )]
+    pub blacklist_entry: UncheckedAccount<'info>,
+
+    /// CHECK: empty PDA, mint authority of the position NFT, required with position_mint
+    #[account(
+        seeds = [b"transfer_manager"],
+        bump = global_config.transfer_manager_bumps,
+    )]
+    pub transfer_manager: Option<UncheckedAccount<'info>>,
+
+    // NFT of the new position, the position is opened tokenized when it is provided
+    #[account(
+        init,
+        seeds = [b"position-mint", user_staked_account.key().as_ref()],
+        bump,
+        payer = user,
+        mint::authority = global_config.transfer_manager,
+        mint::freeze_authority = global_config.transfer_manager,
+        mint::decimals = 0,
+    )]
+    pub position_mint: Option<Box<Account<'info, Mint>>>,
+
+    #[account(
+        init,
+        payer = user,
+        associated_token::mint = position_mint,
+        associated_token::authority = user,
+    )]
+    pub position_token_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    /// CHECK: metadata PDA of position_mint, derivation checked by the metadata program
     #[account(mut)]
-    pub admin: Signer<'info>,
+    pub metadata_account: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: master edition PDA of position_mint, derivation checked by the metadata program
+    #[account(mut)]
+    pub master_edition_account: Option<UncheckedAccount<'info>>,
+
+    pub token_metadata_program: Option<Program<'info, Metadata>>,
+    pub associated_token_program: Option<Program<'info, AssociatedToken>>,
+    pub rent: Option<Sysvar<'info, Rent>>,
 
     #[account(mut)]
     pub user: Signer<'info>,
@@ -198,10 +397,42 @@ pub struct OtcBuy<'info> {
     #[account(mut)]
     pub treasury: UncheckedAccount<'info>,
 
-    /// CHECK: we do not read or write the data of this account
-    #[account(mut)]
-    pub referrer: UncheckedAccount<'info>,
+    // Token accounts of the quote mint, required when OTC purchases are paid in a token
+    #[account(
+        mut,
+        token::mint = global_config.otc_commission.quote_mint,
+        token::authority = user
+    )]
+    pub buyer_quote_account: Option<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        token::mint = global_config.otc_commission.quote_mint,
+        token::authority = global_config.treasury
+    )]
+    pub treasury_quote_account: Option<Account<'info, TokenAccount>>,
 
     pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
 }
+
+impl<'info> OtcBuy<'info> {
+    fn position_nft(&self) -> Result<Option<PositionNft<'info>>> {
+        let Some(position_mint) = &self.position_mint else {
+            return Ok(None);
+        };
+
+        Ok(Some(PositionNft {
+            position_mint: position_mint.to_account_info(),
+            position_token_account: position_nft_account(self.position_token_account.as_deref())?,
+            metadata_account: position_nft_account(self.metadata_account.as_ref())?,
+            master_edition_account: position_nft_account(self.master_edition_account.as_ref())?,
+            transfer_manager: position_nft_account(self.transfer_manager.as_ref())?,
+            payer: self.user.to_account_info(),
+            token_metadata_program: position_nft_account(self.token_metadata_program.as_ref())?,
+            token_program: self.token_program.to_account_info(),
+            system_program: self.system_program.to_account_info(),
+            rent: position_nft_account(self.rent.as_ref())?,
+        }))
+    }
+}
@@ -0,0 +1,194 @@
+use crate::errors::ErrorCode;
+use crate::events::{OtcDeskUpdated, OtcPricePushed};
+use crate::state::{GlobalConfig, OtcDesk, OtcDeskSettings, OtcWalletLimit, PriceOracle, RoleRegistry};
+use anchor_lang::prelude::*;
+use anchor_spl::token::Mint;
+
+#[derive(Accounts)]
+pub struct InitializeOtcDesk<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = global_config.config_bumps
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = OtcDesk::LEN,
+        seeds = [b"otc-desk"],
+        bump
+    )]
+    pub otc_desk: Account<'info, OtcDesk>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = PriceOracle::LEN,
+        seeds = [b"price-oracle"],
+        bump
+    )]
+    pub price_oracle: Account<'info, PriceOracle>,
+
+    #[account(
+        seeds = [b"roles"],
+        bump = role_registry.bump
+    )]
+    pub role_registry: Account<'info, RoleRegistry>,
+
+    #[account(
+        mut,
+        constraint = role_registry.has_role(&authority.key(), RoleRegistry::CONFIG_MANAGER) @ ErrorCode::MissingRole
+    )]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+// otc_buy stays unusable until update_otc_desk sets a price
+pub fn initialize_otc_desk(ctx: Context<InitializeOtcDesk>) -> Result<()> {
+    ctx.accounts.otc_desk.bump = ctx.bumps.otc_desk;
+    ctx.accounts.price_oracle.bump = ctx.bumps.price_oracle;
+
+    msg!("OTC desk initialized");
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct UpdateOtcDesk<'info> {
+    #[account(
+        mut,
+        seeds = [b"otc-desk"],
+        bump = otc_desk.bump
+    )]
+    pub otc_desk: Account<'info, OtcDesk>,
+
+    #[account(
+        seeds = [b"roles"],
+        bump = role_registry.bump
+    )]
+    pub role_registry: Account<'info, RoleRegistry>,
+
+    #[account(
+        constraint = role_registry.has_role(&authority.key(), RoleRegistry::CONFIG_MANAGER) @ ErrorCode::MissingRole
+    )]
+    pub authority: Signer<'info>,
+}
+
+pub fn update_otc_desk(ctx: Context<UpdateOtcDesk>, new_settings: OtcDeskSettings) -> Result<()> {
+    let otc_desk = &mut ctx.accounts.otc_desk;
+
+    require!(new_settings.is_valid(), ErrorCode::InvalidOtcDesk);
+
+    emit!(OtcDeskUpdated {
+        old_settings: otc_desk.settings,
+        new_settings,
+        authority: ctx.accounts.authority.key(),
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    otc_desk.settings = new_settings;
+
+    msg!("OTC desk updated to {:?}", new_settings);
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct PushOtcPrice<'info> {
+    #[account(
+        mut,
+        seeds = [b"price-oracle"],
+        bump = price_oracle.bump
+    )]
+    pub price_oracle: Account<'info, PriceOracle>,
+
+    #[account(
+        seeds = [b"roles"],
+        bump = role_registry.bump
+    )]
+    pub role_registry: Account<'info, RoleRegistry>,
+
+    #[account(
+        constraint = role_registry.has_role(&feeder.key(), RoleRegistry::PRICE_FEEDER) @ ErrorCode::MissingRole
+    )]
+    pub feeder: Signer<'info>,
+}
+
+// Price in quote units per whole token, it holds until the next push
+pub fn push_otc_price(ctx: Context<PushOtcPrice>, price: u64) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+
+    ctx.accounts.price_oracle.push(price, now)?;
+
+    emit!(OtcPricePushed {
+        price,
+        feeder: ctx.accounts.feeder.key(),
+        timestamp: now,
+    });
+
+    msg!("OTC price pushed: {}", price);
+    Ok(())
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug)]
+pub struct OtcQuote {
+    pub price: u64,                           // Quote units per whole token, pass as max_price to otc_buy
+    pub toon_amount: u64,                     // Tokens otc_buy would stake for the payment
+    pub remaining_daily_cap: u64,             // Tokens the wallet may still buy today, u64::MAX without a cap
+}
+
+#[derive(Accounts)]
+#[instruction(payment: u64, user: Pubkey)]
+pub struct GetOtcQuote<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = global_config.config_bumps
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        seeds = [b"otc-desk"],
+        bump = otc_desk.bump
+    )]
+    pub otc_desk: Account<'info, OtcDesk>,
+
+    #[account(
+        seeds = [b"price-oracle"],
+        bump = price_oracle.bump
+    )]
+    pub price_oracle: Account<'info, PriceOracle>,
+
+    #[account(
+        seeds = [b"mint"],
+        bump = global_config.mint_bumps
+    )]
+    pub mint: Account<'info, Mint>,
+
+    // Purchases of the wallet, missing until its first OTC purchase
+    #[account(
+        seeds = [b"otc-limit", user.as_ref()],
+        bump = otc_wallet_limit.bump
+    )]
+    pub otc_wallet_limit: Option<Account<'info, OtcWalletLimit>>,
+}
+
+pub fn get_otc_quote(ctx: Context<GetOtcQuote>, payment: u64, _user: Pubkey) -> Result<OtcQuote> {
+    let now = Clock::get()?.unix_timestamp;
+    let otc_desk = &ctx.accounts.otc_desk;
+    let daily_cap = otc_desk.settings.daily_wallet_cap;
+
+    let price = otc_desk.price(&ctx.accounts.price_oracle, now)?;
+    let toon_amount = OtcDesk::tokens_for(payment, price, ctx.accounts.mint.decimals)?;
+
+    let remaining_daily_cap = match ctx.accounts.otc_wallet_limit.as_deref() {
+        Some(limit) => limit.remaining(daily_cap, now),
+        None if daily_cap == 0 => u64::MAX,
+        None => daily_cap,
+    };
+
+    Ok(OtcQuote {
+        price,
+        toon_amount,
+        remaining_daily_cap,
+    })
+}
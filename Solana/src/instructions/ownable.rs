@@ -1,26 +1,57 @@
 use anchor_lang::prelude::*;
 
-use crate::state::GlobalConfig;
+use crate::events::{
+    OwnershipTransferAccepted, OwnershipTransferStarted, RoleGrantProposed, RoleGranted, RoleRevoked,
+};
+use crate::instructions::authorize_admin;
+use crate::state::{GlobalConfig, Multisig, Proposal, ProposalAction, RoleRegistry};
 
 #[derive(Accounts)]
 pub struct TransferOwnership<'info> {
     #[account(
         mut,
-        has_one = admin,
         seeds = [b"config"],
         bump = global_config.config_bumps
     )]
     pub global_config: Account<'info, GlobalConfig>,
 
-    pub admin: Signer<'info>,
+    #[account(
+        seeds = [b"multisig"],
+        bump = multisig.bump
+    )]
+    pub multisig: Option<Account<'info, Multisig>>,
+
+    // Approved proposal, replaces the admin signature
+    #[account(
+        mut,
+        seeds = [b"proposal", proposal.id.to_le_bytes().as_ref()],
+        bump = proposal.bump
+    )]
+    pub proposal: Option<Account<'info, Proposal>>,
+
+    pub authority: Signer<'info>,
 }
 
 pub fn transfer_ownership(ctx: Context<TransferOwnership>, new_admin: Pubkey) -> Result<()> {
+    authorize_admin(
+        &ctx.accounts.global_config,
+        &ctx.accounts.authority.key(),
+        ctx.accounts.multisig.as_deref(),
+        ctx.accounts.proposal.as_deref_mut(),
+        ProposalAction::TransferOwnership { new_admin },
+    )?;
+
     let global_config = &mut ctx.accounts.global_config;
 
     global_config.pending_admin = new_admin;
 
-    msg!("Ownership transfer started {} {}", ctx.accounts.admin.key(), new_admin);
+    emit!(OwnershipTransferStarted {
+        admin: global_config.admin,
+        pending_admin: new_admin,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    msg!("Ownership transfer started {} {}", global_config.admin, new_admin);
     Ok(())
 }
 
@@ -40,10 +71,168 @@ pub struct AcceptOwnership<'info> {
 pub fn accept_ownership(ctx: Context<AcceptOwnership>) -> Result<()> {
     let global_config = &mut ctx.accounts.global_config;
     let pending_admin = global_config.pending_admin.key();
+    let previous_admin = global_config.admin;
 
     global_config.admin = pending_admin;
     global_config.pending_admin = Pubkey::default();
 
+    emit!(OwnershipTransferAccepted {
+        previous_admin,
+        new_admin: pending_admin,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
     msg!("Ownership transferred to {}", pending_admin);
     Ok(())
 }
+
+#[derive(Accounts)]
+pub struct InitializeRoles<'info> {
+    #[account(
+        has_one = admin,
+        seeds = [b"config"],
+        bump = global_config.config_bumps
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = RoleRegistry::LEN,
+        seeds = [b"roles"],
+        bump
+    )]
+    pub role_registry: Account<'info, RoleRegistry>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+// The admin starts with every role so nothing stops working until roles are handed out
+pub fn initialize_roles(ctx: Context<InitializeRoles>) -> Result<()> {
+    let role_registry = &mut ctx.accounts.role_registry;
+    let admin = ctx.accounts.admin.key();
+
+    role_registry.bump = ctx.bumps.role_registry;
+    role_registry.grant(admin, RoleRegistry::ALL_ROLES)?;
+    role_registry.accept(&admin)?;
+
+    emit!(RoleGranted {
+        member: admin,
+        roles: RoleRegistry::ALL_ROLES,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    msg!("Role registry initialized");
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct ManageRoles<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = global_config.config_bumps
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"roles"],
+        bump = role_registry.bump
+    )]
+    pub role_registry: Account<'info, RoleRegistry>,
+
+    #[account(
+        seeds = [b"multisig"],
+        bump = multisig.bump
+    )]
+    pub multisig: Option<Account<'info, Multisig>>,
+
+    // Approved proposal, replaces the admin signature
+    #[account(
+        mut,
+        seeds = [b"proposal", proposal.id.to_le_bytes().as_ref()],
+        bump = proposal.bump
+    )]
+    pub proposal: Option<Account<'info, Proposal>>,
+
+    pub authority: Signer<'info>,
+}
+
+pub fn grant_roles(ctx: Context<ManageRoles>, member: Pubkey, roles: u8) -> Result<()> {
+    authorize_admin(
+        &ctx.accounts.global_config,
+        &ctx.accounts.authority.key(),
+        ctx.accounts.multisig.as_deref(),
+        ctx.accounts.proposal.as_deref_mut(),
+        ProposalAction::GrantRoles { member, roles },
+    )?;
+
+    let role_registry = &mut ctx.accounts.role_registry;
+
+    role_registry.grant(member, roles)?;
+
+    emit!(RoleGrantProposed {
+        member,
+        roles,
+        authority: ctx.accounts.authority.key(),
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    msg!("Roles {:#08b} granted to {}, waiting for acceptance", roles, member);
+    Ok(())
+}
+
+pub fn revoke_roles(ctx: Context<ManageRoles>, member: Pubkey, roles: u8) -> Result<()> {
+    authorize_admin(
+        &ctx.accounts.global_config,
+        &ctx.accounts.authority.key(),
+        ctx.accounts.multisig.as_deref(),
+        ctx.accounts.proposal.as_deref_mut(),
+        ProposalAction::RevokeRoles { member, roles },
+    )?;
+
+    let role_registry = &mut ctx.accounts.role_registry;
+
+    role_registry.revoke(&member, roles)?;
+
+    emit!(RoleRevoked {
+        member,
+        roles,
+        authority: ctx.accounts.authority.key(),
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    msg!("Roles {:#08b} revoked from {}", roles, member);
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct AcceptRoles<'info> {
+    #[account(
+        mut,
+        seeds = [b"roles"],
+        bump = role_registry.bump
+    )]
+    pub role_registry: Account<'info, RoleRegistry>,
+
+    pub member: Signer<'info>,
+}
+
+pub fn accept_roles(ctx: Context<AcceptRoles>) -> Result<()> {
+    let role_registry = &mut ctx.accounts.role_registry;
+    let member = ctx.accounts.member.key();
+
+    let roles = role_registry.accept(&member)?;
+
+    emit!(RoleGranted {
+        member,
+        roles,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    msg!("Roles {:#08b} accepted by {}", roles, member);
+    Ok(())
+}
@@ -0,0 +1,57 @@
+use crate::errors::ErrorCode;
+use crate::events::PauseUpdated;
+use crate::state::{GlobalConfig, RoleRegistry};
+use anchor_lang::prelude::*;
+
+#[derive(Accounts)]
+pub struct SetPause<'info> {
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = global_config.config_bumps
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        seeds = [b"roles"],
+        bump = role_registry.bump
+    )]
+    pub role_registry: Account<'info, RoleRegistry>,
+
+    pub authority: Signer<'info>,
+}
+
+/*
+ * Sets the paused instruction groups:
+ * admin - any combination of flags
+ * pauser role - can only add flags, lifting a pause is left to the admin
+ *
+ * Admin instructions and views are never paused, so the admin can always react to an incident.
+ */
+pub fn set_pause(ctx: Context<SetPause>, paused_flags: u8) -> Result<()> {
+    let global_config = &mut ctx.accounts.global_config;
+    let authority = ctx.accounts.authority.key();
+    let old_flags = global_config.paused_flags;
+
+    require!(paused_flags & !GlobalConfig::PAUSE_ALL == 0, ErrorCode::InvalidPauseFlags);
+
+    if authority != global_config.admin {
+        require!(
+            ctx.accounts.role_registry.has_role(&authority, RoleRegistry::PAUSER),
+            ErrorCode::MissingRole
+        );
+        require!(paused_flags & old_flags == old_flags, ErrorCode::OnlyAdmin);
+    }
+
+    global_config.paused_flags = paused_flags;
+
+    emit!(PauseUpdated {
+        old_flags,
+        new_flags: paused_flags,
+        authority,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    msg!("Paused flags updated from {:#07b} to {:#07b}", old_flags, paused_flags);
+    Ok(())
+}
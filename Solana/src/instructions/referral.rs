@@ -0,0 +1,497 @@
+use crate::errors::ErrorCode;
+use crate::events::{ReferralCommissionCredited, ReferralCommissionUnwound};
+use crate::state::{GlobalConfig, LevelCredits, ReferralCredit, StakingAccount};
+use anchor_lang::prelude::*;
+
+// Commission of one level, percentage is taken from lvl_percentages
+pub fn level_commission(stake_amount: u64, percentage: u8) -> Result<u64> {
+    let commission = stake_amount
+        .checked_mul(percentage as u64)
+        .ok_or(ErrorCode::Overflow)?
+        .checked_div(100)
+        .ok_or(ErrorCode::Overflow)?;
+
+    Ok(commission)
+}
+
+// The claim period of a referrer starts with its first credited volume
+pub fn credit_referrer(
+    referrer: &mut StakingAccount,
+    stake_amount: u64,
+    commission: u64,
+    now: i64,
+) -> Result<()> {
+    if referrer.total_referral_staked == 0 {
+        referrer.last_referral_rewards_claimed = now;
+    }
+
+    referrer.total_referral_staked = referrer
+        .total_referral_staked
+        .checked_add(stake_amount)
+        .ok_or(ErrorCode::Overflow)?;
+
+    referrer.daily_referral_rewards = referrer
+        .daily_referral_rewards
+        .checked_add(commission)
+        .ok_or(ErrorCode::Overflow)?;
+
+    Ok(())
+}
+
+/*
+ * Credits stake_amount to every paying level of the upline and returns what each level got,
+ * to be recorded in the ReferralCredit of the position. A referrer whose rank does not unlock
+ * its level still gets the volume towards its next rank, but no commission.
+ * Rewards accrued at the old rate are settled first, the new commission only accrues from now on.
+ * referrer_accounts are the writable staking accounts of the upline, in order, upline[0] first.
+ */
+pub(crate) fn apply_referral_rewards_for_all_referrer(
+    referrer_accounts: &[AccountInfo],
+    global_config: &mut GlobalConfig,
+    upline: &[Pubkey],
+    stake_amount: u64,
+) -> Result<LevelCredits> {
+    let now = Clock::get()?.unix_timestamp;
+
+    credit_referrer_accounts(referrer_accounts, global_config, upline, stake_amount, now)
+}
+
+fn credit_referrer_accounts(
+    referrer_accounts: &[AccountInfo],
+    global_config: &mut GlobalConfig,
+    upline: &[Pubkey],
+    stake_amount: u64,
+    now: i64,
+) -> Result<LevelCredits> {
+    StakingAccount::verify_referrer_accounts(upline, referrer_accounts)?;
+
+    let mut credits = LevelCredits::default();
+    let mut next_referrer = upline.first().copied().unwrap_or_default();
+
+    for (index, account_info) in referrer_accounts.iter().enumerate() {
+        require_keys_eq!(account_info.key(), next_referrer, ErrorCode::ReferrerMismatch);
+        require_keys_eq!(*account_info.owner, crate::ID, ErrorCode::InvalidReferrer);
+
+        let referrer_account = &mut account_info.try_borrow_mut_data()?[..];
+
+        let mut referrer_data: StakingAccount = StakingAccount::try_deserialize(&mut &referrer_account[..])
+            .map_err(|_| ErrorCode::DeserializationError)?;
+
+        next_referrer = referrer_data.referrer;
+
+        let commission_percentage = global_config.lvl_percentages.get(index).copied().unwrap_or(0);
+        let mut commission = level_commission(stake_amount, commission_percentage)?;
+
+        if commission == 0 {
+            continue;
+        }
+
+        if index >= referrer_data.unlocked_referral_levels(global_config) as usize {
+            commission = 0;
+        }
+
+        let checkpoint = referrer_data.referral_checkpoint();
+
+        // Volume alone does not change the rate, the claim period is left alone
+        let settled_rewards = if commission > 0 {
+            referrer_data.settle_referral_rewards(global_config, now)?
+        } else {
+            0
+        };
+
+        credit_referrer(&mut referrer_data, stake_amount, commission, now)?;
+        referrer_data.repin_referral_checkpoint(&mut global_config.apy_history, checkpoint)?;
+
+        credits.volumes[index] = stake_amount;
+        credits.commissions[index] = commission;
+
+        emit!(ReferralCommissionCredited {
+            referrer: account_info.key(),
+            level: index as u8,
+            staked_amount: stake_amount,
+            commission,
+            settled_rewards,
+            total_referral_staked: referrer_data.total_referral_staked,
+            daily_referral_rewards: referrer_data.daily_referral_rewards,
+            timestamp: now,
+        });
+
+        let mut cursor = std::io::Cursor::new(referrer_account);
+
+        StakingAccount::try_serialize(&referrer_data, &mut cursor)
+            .map_err(|_| ErrorCode::SerializationError)?;
+    }
+
+    Ok(credits)
+}
+
+// Balances may have been corrected by an operator since the credit, they never go below zero
+pub fn debit_referrer(referrer: &mut StakingAccount, volume: u64, commission: u64) {
+    referrer.total_referral_staked = referrer.total_referral_staked.saturating_sub(volume);
+    referrer.daily_referral_rewards = referrer.daily_referral_rewards.saturating_sub(commission);
+}
+
+/*
+ * Takes a recorded credit back from every level it was paid to.
+ * Rewards accrued at the old rate are settled first so the referrer keeps what was already earned.
+ * referrer_accounts are the staking accounts of credit.upline, in order.
+ */
+pub(crate) fn unwind_referral_rewards_for_all_referrer(
+    referrer_accounts: &[AccountInfo],
+    global_config: &mut GlobalConfig,
+    credit: &ReferralCredit,
+) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+
+    debit_referrer_accounts(referrer_accounts, global_config, credit, now)
+}
+
+fn debit_referrer_accounts(
+    referrer_accounts: &[AccountInfo],
+    global_config: &mut GlobalConfig,
+    credit: &ReferralCredit,
+    now: i64,
+) -> Result<()> {
+    StakingAccount::verify_referrer_accounts(&credit.upline, referrer_accounts)?;
+
+    for (index, account_info) in referrer_accounts.iter().enumerate() {
+        let volume = credit.volumes[index];
+        let commission = credit.commissions[index];
+
+        if volume == 0 && commission == 0 {
+            continue;
+        }
+
+        require_keys_eq!(*account_info.owner, crate::ID, ErrorCode::InvalidReferrer);
+
+        let referrer_account = &mut account_info.try_borrow_mut_data()?[..];
+
+        let mut referrer_data: StakingAccount = StakingAccount::try_deserialize(&mut &referrer_account[..])
+            .map_err(|_| ErrorCode::DeserializationError)?;
+
+        let checkpoint = referrer_data.referral_checkpoint();
+        let settled_rewards = referrer_data.settle_referral_rewards(global_config, now)?;
+
+        debit_referrer(&mut referrer_data, volume, commission);
+        referrer_data.repin_referral_checkpoint(&mut global_config.apy_history, checkpoint)?;
+
+        emit!(ReferralCommissionUnwound {
+            referrer: account_info.key(),
+            level: index as u8,
+            source: credit.source,
+            volume,
+            commission,
+            settled_rewards,
+            total_referral_staked: referrer_data.total_referral_staked,
+            daily_referral_rewards: referrer_data.daily_referral_rewards,
+            timestamp: now,
+        });
+
+        let mut cursor = std::io::Cursor::new(referrer_account);
+
+        StakingAccount::try_serialize(&referrer_data, &mut cursor)
+            .map_err(|_| ErrorCode::SerializationError)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::constants;
+    use crate::state::{
+        ApySchedule, EarlyExitPenalty, EmissionsBudget, OtcCommission, ReferralCreditSource, ReferralRank,
+        ReferralRanks, MAX_UPLINE_LEVELS,
+    };
+
+    const PERIOD: i64 = constants::CLAIM_PERIOD_SECONDS;
+    const START: i64 = 1_700_000_000;
+    const LEVELS: [u8; 10] = [10, 5, 3, 2, 1, 1, 1, 1, 1, 1];
+
+    // Staking account of a referrer, stored the way the loops read it from remaining accounts
+    struct ReferrerAccount {
+        key: Pubkey,
+        lamports: u64,
+        data: Vec<u8>,
+    }
+
+    impl ReferrerAccount {
+        fn new(referrer: Pubkey, total_staked: u64) -> Self {
+            let mut account = Self {
+                key: Pubkey::new_unique(),
+                lamports: 0,
+                data: Vec::new(),
+            };
+
+            account.store(&StakingAccount {
+                user: Pubkey::new_unique(),
+                claim_airdrop: false,
+                total_staked,
+                total_referral_staked: 0,
+                daily_referral_rewards: 0,
+                user_staked_counter: 0,
+                last_referral_rewards_claimed: 0,
+                referrer,
+                referral_history: Vec::new(),
+                closed_stakes_counter: 0,
+                deferred_rewards: 0,
+                upline: [Pubkey::default(); MAX_UPLINE_LEVELS],
+                referral_apy_pinned: false,
+            });
+
+            account
+        }
+
+        fn load(&self) -> StakingAccount {
+            StakingAccount::try_deserialize(&mut &self.data[..]).unwrap()
+        }
+
+        fn store(&mut self, staking_account: &StakingAccount) {
+            self.data.clear();
+            staking_account.try_serialize(&mut self.data).unwrap();
+            self.data.resize(StakingAccount::LEN, 0);
+        }
+
+        fn info(&mut self) -> AccountInfo<'_> {
+            AccountInfo::new(&self.key, false, true, &mut self.lamports, &mut self.data, &crate::ID, false, 0)
+        }
+    }
+
+    // Upline of `depth` referrers, the top one has a referrer of its own when `above_top` is set
+    fn upline_chain(depth: usize, above_top: bool) -> (Vec<ReferrerAccount>, [Pubkey; MAX_UPLINE_LEVELS]) {
+        let mut accounts: Vec<ReferrerAccount> = Vec::with_capacity(depth);
+        let mut referrer = if above_top { Pubkey::new_unique() } else { Pubkey::default() };
+
+        for _ in 0..depth {
+            let account = ReferrerAccount::new(referrer, 0);
+            referrer = account.key;
+            accounts.insert(0, account);
+        }
+
+        let mut upline = [Pubkey::default(); MAX_UPLINE_LEVELS];
+
+        for (level, account) in accounts.iter().enumerate().take(MAX_UPLINE_LEVELS) {
+            upline[level] = account.key;
+        }
+
+        (accounts, upline)
+    }
+
+    fn global_config() -> GlobalConfig {
+        GlobalConfig {
+            admin: Pubkey::default(),
+            pending_admin: Pubkey::default(),
+            treasury: Pubkey::default(),
+            mint: Pubkey::default(),
+            transfer_manager: Pubkey::default(),
+            current_apy_bps: 1_000,
+            deposit_fee_bps: 0,
+            withdrawal_fee_bps: 0,
+            lvl_percentages: LEVELS,
+            total_staked: 0,
+            config_bumps: 0,
+            mint_bumps: 0,
+            transfer_manager_bumps: 0,
+            legacy_apy_history: Vec::new(),
+            early_exit_penalty: EarlyExitPenalty::default(),
+            min_timelock_delay: 0,
+            config_change_counter: 0,
+            continuous_accrual: false,
+            compound_fee_bps: 0,
+            paused_flags: 0,
+            multisig_required: false,
+            emissions: EmissionsBudget::default(),
+            vault_rewards: false,
+            referral_ranks: ReferralRanks::default(),
+            otc_commission: OtcCommission::default(),
+            apy_history: ApySchedule::new(1_000, START - 10 * PERIOD),
+        }
+    }
+
+    fn credit(
+        accounts: &mut [ReferrerAccount],
+        global_config: &mut GlobalConfig,
+        upline: &[Pubkey],
+        stake_amount: u64,
+        now: i64,
+    ) -> Result<LevelCredits> {
+        let infos: Vec<AccountInfo> = accounts.iter_mut().map(ReferrerAccount::info).collect();
+
+        credit_referrer_accounts(&infos, global_config, upline, stake_amount, now)
+    }
+
+    fn unwind(
+        accounts: &mut [ReferrerAccount],
+        global_config: &mut GlobalConfig,
+        referral_credit: &ReferralCredit,
+        now: i64,
+    ) -> Result<()> {
+        let infos: Vec<AccountInfo> = accounts.iter_mut().map(ReferrerAccount::info).collect();
+
+        debit_referrer_accounts(&infos, global_config, referral_credit, now)
+    }
+
+    fn referral_credit(upline: [Pubkey; MAX_UPLINE_LEVELS], credits: &LevelCredits) -> ReferralCredit {
+        ReferralCredit {
+            source: Pubkey::new_unique(),
+            kind: ReferralCreditSource::Position,
+            staking_account: Pubkey::new_unique(),
+            upline,
+            volumes: credits.volumes,
+            commissions: credits.commissions,
+            bump: 0,
+        }
+    }
+
+    #[test]
+    fn credits_every_level_of_a_short_upline() {
+        let mut global_config = global_config();
+        let (mut accounts, upline) = upline_chain(3, false);
+
+        let credits = credit(&mut accounts, &mut global_config, &upline, 1_000, START).unwrap();
+
+        assert_eq!(credits.volumes[..3], [1_000; 3]);
+        assert_eq!(credits.commissions[..3], [100, 50, 30]);
+        assert!(credits.volumes[3..].iter().all(|volume| *volume == 0));
+
+        for (level, account) in accounts.iter().enumerate() {
+            let referrer = account.load();
+
+            assert_eq!(referrer.total_referral_staked, 1_000);
+            assert_eq!(referrer.daily_referral_rewards, credits.commissions[level]);
+            assert_eq!(referrer.last_referral_rewards_claimed, START);
+            assert!(referrer.referral_apy_pinned);
+        }
+    }
+
+    #[test]
+    fn pays_ten_levels_of_a_deeper_tree() {
+        let mut global_config = global_config();
+        let (mut accounts, upline) = upline_chain(MAX_UPLINE_LEVELS, true);
+
+        let credits = credit(&mut accounts, &mut global_config, &upline, 1_000, START).unwrap();
+
+        assert_eq!(credits.volumes, [1_000; MAX_UPLINE_LEVELS]);
+        assert_eq!(credits.commissions, LEVELS.map(|percentage| 10 * percentage as u64));
+    }
+
+    #[test]
+    fn new_commission_does_not_accrue_before_it_was_credited() {
+        let mut global_config = global_config();
+        let (mut accounts, upline) = upline_chain(1, false);
+
+        credit(&mut accounts, &mut global_config, &upline, 1_000_000, START).unwrap();
+
+        let later = START + 30 * PERIOD;
+        let earned = accounts[0].load().calculate_referral_rewards(&global_config, later).unwrap();
+
+        credit(&mut accounts, &mut global_config, &upline, 1_000_000, later).unwrap();
+
+        let referrer = accounts[0].load();
+        let today = later - later % PERIOD;
+
+        assert!(earned > 0);
+        assert_eq!(referrer.deferred_rewards, earned);
+        assert_eq!(referrer.daily_referral_rewards, 200_000);
+        assert_eq!(referrer.calculate_referral_rewards(&global_config, later).unwrap(), 0);
+        assert_eq!(
+            referrer.calculate_referral_rewards(&global_config, today + PERIOD).unwrap() as u128,
+            global_config.apy_history.accrue(200_000, today, 1).unwrap()
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_and_overlong_chains() {
+        let mut global_config = global_config();
+        let (mut accounts, upline) = upline_chain(3, false);
+
+        let truncated = credit(&mut accounts[..2], &mut global_config, &upline, 1_000, START);
+        assert_eq!(truncated.unwrap_err(), ErrorCode::IncompleteReferrerChain.into());
+
+        let (mut overlong, _) = upline_chain(MAX_UPLINE_LEVELS + 1, false);
+        let result = credit(&mut overlong, &mut global_config, &upline, 1_000, START);
+        assert_eq!(result.unwrap_err(), ErrorCode::OnlyTenReferralsLevel.into());
+
+        // Nothing was credited by the failed calls
+        assert!(accounts.iter().all(|account| account.load().total_referral_staked == 0));
+    }
+
+    #[test]
+    fn rank_locked_levels_get_volume_without_commission() {
+        let mut global_config = global_config();
+        let (mut accounts, upline) = upline_chain(3, false);
+
+        global_config.referral_ranks.set(&[
+            ReferralRank { min_personal_stake: 0, min_team_volume: 0, unlocked_levels: 1 },
+            ReferralRank { min_personal_stake: 5_000, min_team_volume: 0, unlocked_levels: 3 },
+        ]);
+
+        // Only the top referrer holds enough stake for the second rank
+        let mut top = accounts[2].load();
+        top.total_staked = 5_000;
+        accounts[2].store(&top);
+
+        let credits = credit(&mut accounts, &mut global_config, &upline, 1_000, START).unwrap();
+
+        assert_eq!(credits.volumes[..3], [1_000; 3]);
+        assert_eq!(credits.commissions[..3], [100, 0, 30]);
+
+        let locked = accounts[1].load();
+
+        assert_eq!(locked.total_referral_staked, 1_000);
+        assert_eq!(locked.daily_referral_rewards, 0);
+        assert!(!locked.referral_apy_pinned);
+    }
+
+    #[test]
+    fn unwind_settles_what_was_earned_before_debiting() {
+        let mut global_config = global_config();
+        let (mut accounts, upline) = upline_chain(3, false);
+
+        let credits = credit(&mut accounts, &mut global_config, &upline, 1_000_000, START).unwrap();
+        let referral_credit = referral_credit(upline, &credits);
+
+        let later = START + 3 * PERIOD;
+        let earned: Vec<u64> = accounts
+            .iter()
+            .map(|account| account.load().calculate_referral_rewards(&global_config, later).unwrap())
+            .collect();
+
+        unwind(&mut accounts, &mut global_config, &referral_credit, later).unwrap();
+
+        for (level, account) in accounts.iter().enumerate() {
+            let referrer = account.load();
+
+            assert!(earned[level] > 0);
+            assert_eq!(referrer.deferred_rewards, earned[level]);
+            assert_eq!(referrer.total_referral_staked, 0);
+            assert_eq!(referrer.daily_referral_rewards, 0);
+            assert!(!referrer.referral_apy_pinned);
+        }
+
+        assert!(global_config.apy_history.pins.iter().all(|pins| *pins == 0));
+    }
+
+    #[test]
+    fn unwind_saturates_balances_corrected_below_the_credit() {
+        let mut global_config = global_config();
+        let (mut accounts, upline) = upline_chain(2, false);
+
+        let credits = credit(&mut accounts, &mut global_config, &upline, 1_000, START).unwrap();
+        let referral_credit = referral_credit(upline, &credits);
+
+        // An operator lowered the first referrer below what the credit added
+        let mut corrected = accounts[0].load();
+        corrected.total_referral_staked = 400;
+        corrected.daily_referral_rewards = 40;
+        accounts[0].store(&corrected);
+
+        unwind(&mut accounts, &mut global_config, &referral_credit, START).unwrap();
+
+        let referrer = accounts[0].load();
+
+        assert_eq!(referrer.total_referral_staked, 0);
+        assert_eq!(referrer.daily_referral_rewards, 0);
+    }
+}
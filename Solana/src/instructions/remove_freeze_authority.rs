@@ -3,12 +3,11 @@ use anchor_spl::token::{
     spl_token::instruction::AuthorityType, Mint, SetAuthority, Token,
 };
 
-use crate::state::GlobalConfig;
+use crate::{errors::ErrorCode, events::FreezeAuthorityRevoked, state::{GlobalConfig, RoleRegistry}};
 
 #[derive(Accounts)]
 pub struct RevokeFreezeAuth<'info> {
     #[account(
-        has_one = admin,
         seeds = [b"config"],
         bump = global_config.config_bumps
     )]
@@ -28,8 +27,17 @@ pub struct RevokeFreezeAuth<'info> {
     )]
     pub transfer_manager: AccountInfo<'info>,
 
-    #[account(mut)]
-    pub admin: Signer<'info>,
+    #[account(
+        seeds = [b"roles"],
+        bump = role_registry.bump
+    )]
+    pub role_registry: Account<'info, RoleRegistry>,
+
+    #[account(
+        mut,
+        constraint = role_registry.has_role(&authority.key(), RoleRegistry::MINTER) @ ErrorCode::MissingRole
+    )]
+    pub authority: Signer<'info>,
 
     pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
@@ -49,5 +57,11 @@ pub struct RevokeFreezeAuth<'info> {
     );
     anchor_spl::token::set_authority(context, AuthorityType::FreezeAccount, None)?;
 
+    emit!(FreezeAuthorityRevoked {
+        mint: ctx.accounts.mint.key(),
+        authority: ctx.accounts.authority.key(),
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
     Ok(())
 }
@@ -0,0 +1,260 @@
+use crate::errors::ErrorCode;
+use crate::events::RewardsFunded;
+use crate::state::{GlobalConfig, TierRegistry};
+use anchor_lang::prelude::*;
+use anchor_spl::associated_token::{get_associated_token_address, AssociatedToken};
+use anchor_spl::token::{self, Burn, Mint, MintTo, Token, TokenAccount, Transfer};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+#[derive(Accounts)]
+pub struct FundRewards<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = global_config.config_bumps
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        seeds = [b"mint"],
+        bump = global_config.mint_bumps
+    )]
+    pub mint: Account<'info, Mint>,
+
+    /// CHECK: empty PDA, manager for token accounts
+    #[account(
+        seeds = [b"transfer_manager"],
+        bump = global_config.transfer_manager_bumps,
+    )]
+    pub transfer_manager: AccountInfo<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = funder,
+        associated_token::mint = mint,
+        associated_token::authority = transfer_manager,
+    )]
+    pub reward_vault: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub funder_token_account: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub funder: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+// Anyone may top up the vault, tokens sent here can only leave as rewards
+pub fn fund_rewards(ctx: Context<FundRewards>, amount: u64) -> Result<()> {
+    require!(amount > 0, ErrorCode::InvalidAmount);
+
+    let cpi_ctx = CpiContext::new(
+        ctx.accounts.token_program.to_account_info(),
+        Transfer {
+            from: ctx.accounts.funder_token_account.to_account_info(),
+            to: ctx.accounts.reward_vault.to_account_info(),
+            authority: ctx.accounts.funder.to_account_info(),
+        },
+    );
+    token::transfer(cpi_ctx, amount)?;
+
+    ctx.accounts.reward_vault.reload()?;
+
+    emit!(RewardsFunded {
+        funder: ctx.accounts.funder.key(),
+        amount,
+        vault_balance: ctx.accounts.reward_vault.amount,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    msg!("Funded reward vault with {} tokens", amount);
+    Ok(())
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug)]
+pub struct RewardRunway {
+    pub vault_balance: u64,                   // Tokens available for rewards
+    pub daily_emission: u64,                  // Estimated rewards paid per day
+    pub runway_seconds: i64,                  // Time until the vault runs dry at that rate, i64::MAX if nothing is emitted
+}
+
+#[derive(Accounts)]
+pub struct GetRewardRunway<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = global_config.config_bumps
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        seeds = [b"tiers"],
+        bump = tier_registry.load()?.bump
+    )]
+    pub tier_registry: AccountLoader<'info, TierRegistry>,
+
+    /// CHECK: empty PDA, manager for token accounts
+    #[account(
+        seeds = [b"transfer_manager"],
+        bump = global_config.transfer_manager_bumps,
+    )]
+    pub transfer_manager: AccountInfo<'info>,
+
+    #[account(
+        address = get_associated_token_address(&transfer_manager.key(), &global_config.mint) @ ErrorCode::InvalidRewardVault
+    )]
+    pub reward_vault: Account<'info, TokenAccount>,
+}
+
+/*
+ * Emission rate is estimated as all staked tokens earning the highest active tier APY,
+ * capped by the emissions budget when one is set. Referral rewards are not included.
+ */
+pub fn get_reward_runway(ctx: Context<GetRewardRunway>) -> Result<RewardRunway> {
+    let global_config = &ctx.accounts.global_config;
+    let tier_registry = ctx.accounts.tier_registry.load()?;
+
+    let max_apy_bps = tier_registry.tiers[..tier_registry.tier_count as usize]
+        .iter()
+        .filter(|tier| tier.is_active())
+        .map(|tier| tier.current_apy_bps)
+        .max()
+        .unwrap_or(global_config.current_apy_bps);
+
+    let mut daily_emission = (global_config.total_staked as u128)
+        .checked_mul(max_apy_bps as u128)
+        .ok_or(ErrorCode::Overflow)?
+        .checked_div(365 * 10_000)
+        .ok_or(ErrorCode::Overflow)?;
+
+    let emissions = &global_config.emissions;
+
+    if emissions.is_enabled() {
+        let budget_per_day = (emissions.reward_cap as u128)
+            .checked_mul(SECONDS_PER_DAY as u128)
+            .ok_or(ErrorCode::Overflow)?
+            .checked_div(emissions.epoch_duration as u128)
+            .ok_or(ErrorCode::Overflow)?;
+
+        daily_emission = daily_emission.min(budget_per_day);
+    }
+
+    let daily_emission = u64::try_from(daily_emission).map_err(|_| ErrorCode::Overflow)?;
+    let vault_balance = ctx.accounts.reward_vault.amount;
+
+    let runway_seconds = if daily_emission == 0 {
+        i64::MAX
+    } else {
+        let runway = (vault_balance as u128)
+            .checked_mul(SECONDS_PER_DAY as u128)
+            .ok_or(ErrorCode::Overflow)?
+            / daily_emission as u128;
+
+        i64::try_from(runway).unwrap_or(i64::MAX)
+    };
+
+    Ok(RewardRunway {
+        vault_balance,
+        daily_emission,
+        runway_seconds,
+    })
+}
+
+/*
+ * Pays rewards either by minting or, in vault mode, out of the reward vault.
+ * The vault is the transfer_manager ATA, it has to hold the full amount.
+ */
+pub(crate) fn pay_rewards<'info>(
+    global_config: &Account<'info, GlobalConfig>,
+    mint: &Account<'info, Mint>,
+    transfer_manager: &AccountInfo<'info>,
+    reward_vault: Option<&Account<'info, TokenAccount>>,
+    to: AccountInfo<'info>,
+    token_program: &Program<'info, Token>,
+    amount: u64,
+) -> Result<()> {
+    let manager_bumps = global_config.transfer_manager_bumps;
+    let manager_seeds: &[&[&[u8]]] = &[&[b"transfer_manager", &[manager_bumps]]];
+
+    if !global_config.vault_rewards {
+        let context = CpiContext::new(
+            token_program.to_account_info(),
+            MintTo {
+                mint: mint.to_account_info(),
+                to,
+                authority: transfer_manager.to_account_info(),
+            },
+        )
+        .with_signer(manager_seeds);
+
+        return token::mint_to(context, amount);
+    }
+
+    let reward_vault = load_reward_vault(global_config, transfer_manager, reward_vault, amount)?;
+
+    let context = CpiContext::new(
+        token_program.to_account_info(),
+        Transfer {
+            from: reward_vault.to_account_info(),
+            to,
+            authority: transfer_manager.to_account_info(),
+        },
+    )
+    .with_signer(manager_seeds);
+
+    token::transfer(context, amount)
+}
+
+/*
+ * Compounded rewards turn into principal that is minted again on unstake.
+ * In vault mode the same amount is burned from the vault so the supply does not grow.
+ */
+pub(crate) fn burn_compounded_rewards<'info>(
+    global_config: &Account<'info, GlobalConfig>,
+    mint: &Account<'info, Mint>,
+    transfer_manager: &AccountInfo<'info>,
+    reward_vault: Option<&Account<'info, TokenAccount>>,
+    token_program: &Program<'info, Token>,
+    amount: u64,
+) -> Result<()> {
+    if !global_config.vault_rewards || amount == 0 {
+        return Ok(());
+    }
+
+    let reward_vault = load_reward_vault(global_config, transfer_manager, reward_vault, amount)?;
+
+    let manager_bumps = global_config.transfer_manager_bumps;
+    let manager_seeds: &[&[&[u8]]] = &[&[b"transfer_manager", &[manager_bumps]]];
+
+    let context = CpiContext::new(
+        token_program.to_account_info(),
+        Burn {
+            mint: mint.to_account_info(),
+            from: reward_vault.to_account_info(),
+            authority: transfer_manager.to_account_info(),
+        },
+    )
+    .with_signer(manager_seeds);
+
+    token::burn(context, amount)
+}
+
+fn load_reward_vault<'a, 'info>(
+    global_config: &GlobalConfig,
+    transfer_manager: &AccountInfo<'info>,
+    reward_vault: Option<&'a Account<'info, TokenAccount>>,
+    amount: u64,
+) -> Result<&'a Account<'info, TokenAccount>> {
+    let reward_vault = reward_vault.ok_or(ErrorCode::InvalidRewardVault)?;
+
+    require!(
+        reward_vault.key()
+            == get_associated_token_address(&transfer_manager.key(), &global_config.mint),
+        ErrorCode::InvalidRewardVault
+    );
+    require!(reward_vault.amount >= amount, ErrorCode::RewardVaultEmpty);
+
+    Ok(reward_vault)
+}
@@ -0,0 +1,243 @@
+use crate::{
+    errors::ErrorCode,
+    events::PositionSplit,
+    instructions::{position_nft_account, PositionNft},
+    state::{
+        BlacklistEntry, GlobalConfig, ReferralCredit, ReferralCreditSource, StakingAccount, TierRegistry,
+        UserStakedAccount,
+    },
+};
+
+use anchor_lang::prelude::*;
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    metadata::Metadata,
+    token::{Mint, Token, TokenAccount},
+};
+
+#[derive(Accounts)]
+pub struct SplitPosition<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = global_config.config_bumps
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"tiers"],
+        bump = tier_registry.load()?.bump
+    )]
+    pub tier_registry: AccountLoader<'info, TierRegistry>,
+
+    #[account(
+        mut,
+        seeds = [b"staking", user.key().as_ref()],
+        bump
+    )]
+    pub staking_account: Account<'info, StakingAccount>,
+
+    #[account(mut, has_one = user)]
+    pub user_staked_account: Account<'info, UserStakedAccount>,
+
+    #[account(
+        init,
+        payer = user,
+        space = UserStakedAccount::LEN,
+        seeds = [b"user-staked", staking_account.key().as_ref(), staking_account.user_staked_counter.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub new_user_staked_account: Account<'info, UserStakedAccount>,
+
+    /// CHECK: commission credited to the upline for the position, empty when none was credited
+    #[account(
+        mut,
+        seeds = [b"referral-credit", user_staked_account.key().as_ref()],
+        bump
+    )]
+    pub referral_credit: UncheckedAccount<'info>,
+
+    // Share of the credit moved to the new position, required when the position has a credit
+    #[account(
+        init,
+        payer = user,
+        space = ReferralCredit::LEN,
+        seeds = [b"referral-credit", new_user_staked_account.key().as_ref()],
+        bump
+    )]
+    pub new_referral_credit: Option<Account<'info, ReferralCredit>>,
+
+    /// CHECK: blacklist entry of the user, empty unless the user is blacklisted
+    #[account(
+        seeds = [b"blacklist", user.key().as_ref()],
+        bump,
+        constraint = !BlacklistEntry::is_blacklisted(&blacklist_entry) @ ErrorCode::Blacklisted
+    )]
+    pub blacklist_entry: UncheckedAccount<'info>,
+
+    /// CHECK: empty PDA, mint authority of the position NFT, required with position_mint
+    #[account(
+        seeds = [b"transfer_manager"],
+        bump = global_config.transfer_manager_bumps,
+    )]
+    pub transfer_manager: Option<UncheckedAccount<'info>>,
+
+    // NFT of the new position, the position is opened tokenized when it is provided
+    #[account(
+        init,
+        seeds = [b"position-mint", new_user_staked_account.key().as_ref()],
+        bump,
+        payer = user,
+        mint::authority = global_config.transfer_manager,
+        mint::freeze_authority = global_config.transfer_manager,
+        mint::decimals = 0,
+    )]
+    pub position_mint: Option<Box<Account<'info, Mint>>>,
+
+    #[account(
+        init,
+        payer = user,
+        associated_token::mint = position_mint,
+        associated_token::authority = user,
+    )]
+    pub position_token_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    /// CHECK: metadata PDA of position_mint, derivation checked by the metadata program
+    #[account(mut)]
+    pub metadata_account: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: master edition PDA of position_mint, derivation checked by the metadata program
+    #[account(mut)]
+    pub master_edition_account: Option<UncheckedAccount<'info>>,
+
+    pub token_metadata_program: Option<Program<'info, Metadata>>,
+    pub associated_token_program: Option<Program<'info, AssociatedToken>>,
+    pub rent: Option<Sysvar<'info, Rent>>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> SplitPosition<'info> {
+    fn position_nft(&self) -> Result<Option<PositionNft<'info>>> {
+        let Some(position_mint) = &self.position_mint else {
+            return Ok(None);
+        };
+
+        Ok(Some(PositionNft {
+            position_mint: position_mint.to_account_info(),
+            position_token_account: position_nft_account(self.position_token_account.as_deref())?,
+            metadata_account: position_nft_account(self.metadata_account.as_ref())?,
+            master_edition_account: position_nft_account(self.master_edition_account.as_ref())?,
+            transfer_manager: position_nft_account(self.transfer_manager.as_ref())?,
+            payer: self.user.to_account_info(),
+            token_metadata_program: position_nft_account(self.token_metadata_program.as_ref())?,
+            token_program: self.token_program.to_account_info(),
+            system_program: self.system_program.to_account_info(),
+            rent: position_nft_account(self.rent.as_ref())?,
+        }))
+    }
+}
+
+/*
+ * Carves `amount` out of a position into a new one with the same tier and maturity.
+ * Rewards accrued so far are settled in the configured accrual mode and stay with the original position.
+ * The new position continues from the same checkpoint, so in daily mode both share the period in progress.
+ * The referral credit of the position is split in the same proportion, so each part is unwound with its stake.
+ */
+pub fn split_position(ctx: Context<SplitPosition>, amount: u64) -> Result<()> {
+    ctx.accounts.global_config.require_not_paused(GlobalConfig::PAUSE_STAKE)?;
+
+    let now = Clock::get()?.unix_timestamp;
+    let position_nft = ctx.accounts.position_nft()?;
+
+    let staking_account = &mut ctx.accounts.staking_account;
+    let user_staked = &mut ctx.accounts.user_staked_account;
+    let new_user_staked = &mut ctx.accounts.new_user_staked_account;
+
+    if user_staked.is_tokenized() {
+        return Err(ErrorCode::PositionTokenized.into());
+    }
+
+    if amount == 0 || amount >= user_staked.amount_staked {
+        return Err(ErrorCode::InvalidSplitAmount.into());
+    }
+
+    let total_staked = user_staked.amount_staked;
+
+    if user_staked.maturity_timestamp() < now {
+        return Err(ErrorCode::StakeExpired.into());
+    }
+
+    let mut tier_registry = ctx.accounts.tier_registry.load_mut()?;
+    let tier = tier_registry.get_tier_mut(user_staked.tier_id)?;
+
+    user_staked.settle_rewards(tier, ctx.accounts.global_config.continuous_accrual, now)?;
+
+    user_staked.amount_staked = user_staked
+        .amount_staked
+        .checked_sub(amount)
+        .ok_or(ErrorCode::Underflow)?;
+
+    new_user_staked.amount_staked = amount;
+    new_user_staked.start_accrual(tier, user_staked.last_claimed_timestamp)?;
+    new_user_staked.staked_timestamp = user_staked.staked_timestamp;
+    new_user_staked.identifier = staking_account.user_staked_counter;
+    new_user_staked.user = staking_account.user;
+    new_user_staked.tier_id = user_staked.tier_id;
+    new_user_staked.lock_duration = user_staked.lock_duration;
+
+    staking_account.user_staked_counter = staking_account
+        .user_staked_counter
+        .checked_add(1)
+        .ok_or(ErrorCode::Overflow)?;
+
+    let credit_info = ctx.accounts.referral_credit.to_account_info();
+
+    if credit_info.owner == &crate::ID && !credit_info.data_is_empty() {
+        let credit_data = &mut credit_info.try_borrow_mut_data()?[..];
+
+        let mut referral_credit = ReferralCredit::try_deserialize(&mut &credit_data[..])
+            .map_err(|_| ErrorCode::DeserializationError)?;
+
+        let share = referral_credit.split_off(amount, total_staked)?;
+
+        ReferralCredit::record(
+            ctx.accounts.new_referral_credit.as_deref_mut(),
+            ctx.bumps.new_referral_credit,
+            (new_user_staked.key(), ReferralCreditSource::Position),
+            staking_account.key(),
+            &referral_credit.upline,
+            &share,
+        )?;
+
+        let mut cursor = std::io::Cursor::new(credit_data);
+
+        ReferralCredit::try_serialize(&referral_credit, &mut cursor)
+            .map_err(|_| ErrorCode::SerializationError)?;
+    }
+
+    if let Some(position_nft) = &position_nft {
+        position_nft.mint(
+            ctx.accounts.global_config.transfer_manager_bumps,
+            new_user_staked,
+            now,
+        )?;
+    }
+
+    emit!(PositionSplit {
+        user: staking_account.user,
+        source: user_staked.key(),
+        new_position: new_user_staked.key(),
+        amount,
+        remaining: user_staked.amount_staked,
+        timestamp: now,
+    });
+
+    msg!("Amount split into a new position: {}", amount);
+
+    Ok(())
+}
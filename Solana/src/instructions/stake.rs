@@ -1,17 +1,30 @@
 use crate::errors::ErrorCode;
-use crate::state::{GlobalConfig, StakingAccount, UserStakedAccount};
+use crate::events::{AirdropStaked, Staked};
+use crate::instructions::{
+    apply_referral_rewards_for_all_referrer, authorize_privileged, position_nft_account, PositionNft,
+};
+use crate::state::{BlacklistEntry, GlobalConfig, LockTier, Multisig, Proposal, ProposalAction, ReferralCredit, ReferralCreditSource, ReferralEntry, RoleRegistry, StakingAccount, TierRegistry, UserStakedAccount};
 use anchor_lang::prelude::*;
-use anchor_spl::associated_token::get_associated_token_address;
+use anchor_spl::associated_token::{get_associated_token_address, AssociatedToken};
+use anchor_spl::metadata::Metadata;
 use anchor_spl::token::{self, Burn, Mint, Token, TokenAccount, Transfer};
 
 pub fn handler<'a>(
     ctx: Context<'_, '_, 'a, 'a, Stake<'a>>,
     user_amount: u64,
+    tier_id: u8,
 ) -> Result<()> {
+    ctx.accounts.global_config.require_not_paused(GlobalConfig::PAUSE_STAKE)?;
+
     if user_amount == 0 {
         return Err(ErrorCode::InvalidAmount.into());
     }
 
+    let position_nft = ctx.accounts.position_nft()?;
+    let mut tier_registry = ctx.accounts.tier_registry.load_mut()?;
+    let tier = tier_registry.get_tier_mut(tier_id)?;
+    require!(tier.is_active(), ErrorCode::InactiveTier);
+
     let global_config = &mut ctx.accounts.global_config;
     let staking_account = &mut ctx.accounts.staking_account;
     let user_staked = &mut ctx.accounts.user_staked_account;
@@ -23,13 +36,8 @@ pub fn handler<'a>(
         return Err(ErrorCode::InvalidTreasury.into());
     }
 
-    msg!(
-        "Current APY from GlobalConfig: {}",
-        global_config.current_apy_bps
-    );
-
     let deposit_tax = user_amount
-        .checked_mul(global_config.deposit_fee_bps as u64)
+        .checked_mul(tier.deposit_fee_bps as u64)
         .ok_or(ErrorCode::Overflow)?
         .checked_div(10_000)
         .ok_or(ErrorCode::Overflow)?;
@@ -60,48 +68,37 @@ pub fn handler<'a>(
     );
     token::burn(cpi_ctx_burn, net_amount)?;
 
-    let remaining_accounts = ctx.remaining_accounts;
-
-    if remaining_accounts.len() > 0 {
-        if remaining_accounts.len() > global_config.lvl_percentages.len() {
-            return Err(ErrorCode::OnlyTenReferralsLevel.into());
-        }
-
-        apply_referral_rewards_for_all_referrer(
-            remaining_accounts,
-            global_config,
-            net_amount,
-            staking_account.referrer
-        )?;
-    } else if staking_account.referrer != Pubkey::default() {
-        return Err(ErrorCode::ShouldProvideReferrer.into());
-    }
-
-    user_staked.amount_staked = net_amount;
-
-    msg!("Amount staked: {}", net_amount);
-
     let now = Clock::get()?.unix_timestamp;
 
-    user_staked.last_claimed_timestamp = now;
-    user_staked.staked_timestamp = now;
-    user_staked.identifier = staking_account.user_staked_counter;
-    user_staked.user = staking_account.user;
-
-    staking_account.total_staked = staking_account
-        .total_staked
-        .checked_add(net_amount)
-        .ok_or(ErrorCode::Overflow)?;
-
-    staking_account.user_staked_counter = staking_account
-        .user_staked_counter
-        .checked_add(1)
-        .ok_or(ErrorCode::Overflow)?;
-
-    global_config.total_staked = global_config
-        .total_staked
-        .checked_add(net_amount)
-        .ok_or(ErrorCode::Overflow)?;
+    open_position(
+        global_config,
+        tier,
+        tier_id,
+        NewPosition {
+            staking_account,
+            user_staked,
+            referral_entry: ctx.accounts.referral_entry.as_deref_mut(),
+            referral_credit: ctx.accounts.referral_credit.as_deref_mut(),
+            referral_credit_bump: ctx.bumps.referral_credit,
+            position_nft,
+        },
+        ctx.remaining_accounts,
+        net_amount,
+        now,
+    )?;
+
+    emit!(Staked {
+        user: staking_account.user,
+        user_staked_account: user_staked.key(),
+        identifier: user_staked.identifier,
+        tier_id,
+        amount: user_amount,
+        deposit_fee: deposit_tax,
+        net_amount,
+        lock_duration: tier.duration,
+        referrer: staking_account.referrer,
+        timestamp: now,
+    });
 
     Ok(())
 }
@@ -109,70 +106,75 @@ pub fn handler<'a>(
 pub fn stake_airdrop<'a>(
     ctx: Context<'_, '_, 'a, 'a, Stake<'a>>,
     user_amount: u64,
+    tier_id: u8,
 ) -> Result<()> {
+    ctx.accounts.global_config.require_not_paused(GlobalConfig::PAUSE_STAKE)?;
+
     if user_amount == 0 {
         return Err(ErrorCode::InvalidAmount.into());
     }
 
+    authorize_privileged(
+        &ctx.accounts.global_config,
+        &ctx.accounts.role_registry,
+        &ctx.accounts.user.key(),
+        RoleRegistry::AIRDROP_OPERATOR,
+        ctx.accounts.multisig.as_deref(),
+        ctx.accounts.proposal.as_deref_mut(),
+        ProposalAction::AirdropStake {
+            staking_account: ctx.accounts.staking_account.key(),
+            amount: user_amount,
+            tier_id,
+            by_admin: false,
+        },
+    )?;
+
+    let position_nft = ctx.accounts.position_nft()?;
+    let mut tier_registry = ctx.accounts.tier_registry.load_mut()?;
+    let tier = tier_registry.get_tier_mut(tier_id)?;
+    require!(tier.is_active(), ErrorCode::InactiveTier);
+
     let global_config = &mut ctx.accounts.global_config;
     let staking_account = &mut ctx.accounts.staking_account;
     let user_staked = &mut ctx.accounts.user_staked_account;
     let signer_key = ctx.accounts.user.key();
 
-    require!(signer_key == global_config.admin, ErrorCode::OnlyAdmin);
     require!(!staking_account.claim_airdrop, ErrorCode::AirdropAlreadyClaimed);
 
-    msg!(
-        "Current APY from GlobalConfig: {}",
-        global_config.current_apy_bps
-    );
-
     let net_amount = user_amount;
 
-    let remaining_accounts = ctx.remaining_accounts;
-
-    if remaining_accounts.len() > 0 {
-        if remaining_accounts.len() > global_config.lvl_percentages.len() {
-            return Err(ErrorCode::OnlyTenReferralsLevel.into());
-        }
-
-        apply_referral_rewards_for_all_referrer(
-            remaining_accounts,
-            global_config,
-            net_amount,
-            staking_account.referrer
-        )?;
-    } else if staking_account.referrer != Pubkey::default() {
-        return Err(ErrorCode::ShouldProvideReferrer.into());
-    }
-
-    user_staked.amount_staked = net_amount;
-
-    msg!("Amount staked: {}", net_amount);
+    staking_account.claim_airdrop = true;
 
     let now = Clock::get()?.unix_timestamp;
 
-    user_staked.last_claimed_timestamp = now;
-    user_staked.staked_timestamp = now;
-    user_staked.identifier = staking_account.user_staked_counter;
-    user_staked.user = staking_account.user;
-
-    staking_account.total_staked = staking_account
-        .total_staked
-        .checked_add(net_amount)
-        .ok_or(ErrorCode::Overflow)?;
-
-    staking_account.user_staked_counter = staking_account
-        .user_staked_counter
-        .checked_add(1)
-        .ok_or(ErrorCode::Overflow)?;
-
-    global_config.total_staked = global_config
-        .total_staked
-        .checked_add(net_amount)
-        .ok_or(ErrorCode::Overflow)?;
-
-    staking_account.claim_airdrop = true;
+    open_position(
+        global_config,
+        tier,
+        tier_id,
+        NewPosition {
+            staking_account,
+            user_staked,
+            referral_entry: ctx.accounts.referral_entry.as_deref_mut(),
+            referral_credit: ctx.accounts.referral_credit.as_deref_mut(),
+            referral_credit_bump: ctx.bumps.referral_credit,
+            position_nft,
+        },
+        ctx.remaining_accounts,
+        net_amount,
+        now,
+    )?;
+
+    emit!(AirdropStaked {
+        user: staking_account.user,
+        user_staked_account: user_staked.key(),
+        identifier: user_staked.identifier,
+        tier_id,
+        amount: net_amount,
+        lock_duration: tier.duration,
+        operator: signer_key,
+        by_admin: false,
+        timestamp: now,
+    });
 
     Ok(())
 }
@@ -180,56 +182,153 @@ pub fn stake_airdrop<'a>(
 pub fn stake_by_admin<'a>(
     ctx: Context<'_, '_, 'a, 'a, Stake<'a>>,
     user_amount: u64,
+    tier_id: u8,
 ) -> Result<()> {
+    ctx.accounts.global_config.require_not_paused(GlobalConfig::PAUSE_STAKE)?;
+
     if user_amount == 0 {
         return Err(ErrorCode::InvalidAmount.into());
     }
 
+    authorize_privileged(
+        &ctx.accounts.global_config,
+        &ctx.accounts.role_registry,
+        &ctx.accounts.user.key(),
+        RoleRegistry::AIRDROP_OPERATOR,
+        ctx.accounts.multisig.as_deref(),
+        ctx.accounts.proposal.as_deref_mut(),
+        ProposalAction::AirdropStake {
+            staking_account: ctx.accounts.staking_account.key(),
+            amount: user_amount,
+            tier_id,
+            by_admin: true,
+        },
+    )?;
+
+    let position_nft = ctx.accounts.position_nft()?;
+    let mut tier_registry = ctx.accounts.tier_registry.load_mut()?;
+    let tier = tier_registry.get_tier_mut(tier_id)?;
+    require!(tier.is_active(), ErrorCode::InactiveTier);
+
     let global_config = &mut ctx.accounts.global_config;
     let staking_account = &mut ctx.accounts.staking_account;
     let user_staked = &mut ctx.accounts.user_staked_account;
     let signer_key = ctx.accounts.user.key();
 
-    require!(signer_key == global_config.admin, ErrorCode::OnlyAdmin);
+    let net_amount = user_amount;
 
-    msg!(
-        "Current APY from GlobalConfig: {}",
-        global_config.current_apy_bps
-    );
+    staking_account.claim_airdrop = true;
 
-    let net_amount = user_amount;
+    let now = Clock::get()?.unix_timestamp;
 
-    let remaining_accounts = ctx.remaining_accounts;
-
-    if remaining_accounts.len() > 0 {
-        if remaining_accounts.len() > global_config.lvl_percentages.len() {
-            return Err(ErrorCode::OnlyTenReferralsLevel.into());
-        }
-
-        apply_referral_rewards_for_all_referrer(
-            remaining_accounts,
-            global_config,
-            net_amount,
-            staking_account.referrer
-        )?;
-    } else if staking_account.referrer != Pubkey::default() {
-        return Err(ErrorCode::ShouldProvideReferrer.into());
-    }
+    open_position(
+        global_config,
+        tier,
+        tier_id,
+        NewPosition {
+            staking_account,
+            user_staked,
+            referral_entry: ctx.accounts.referral_entry.as_deref_mut(),
+            referral_credit: ctx.accounts.referral_credit.as_deref_mut(),
+            referral_credit_bump: ctx.bumps.referral_credit,
+            position_nft,
+        },
+        ctx.remaining_accounts,
+        net_amount,
+        now,
+    )?;
+
+    emit!(AirdropStaked {
+        user: staking_account.user,
+        user_staked_account: user_staked.key(),
+        identifier: user_staked.identifier,
+        tier_id,
+        amount: net_amount,
+        lock_duration: tier.duration,
+        operator: signer_key,
+        by_admin: true,
+        timestamp: now,
+    });
+
+    Ok(())
+}
 
-    user_staked.amount_staked = net_amount;
+// Accounts of the position opened by open_position
+pub(crate) struct NewPosition<'a, 'info> {
+    pub staking_account: &'a mut Account<'info, StakingAccount>,
+    pub user_staked: &'a mut Account<'info, UserStakedAccount>,
+    pub referral_entry: Option<&'a mut ReferralEntry>,
+    pub referral_credit: Option<&'a mut ReferralCredit>,
+    pub referral_credit_bump: Option<u8>,
+    pub position_nft: Option<PositionNft<'info>>,
+}
 
-    msg!("Amount staked: {}", net_amount);
+/*
+ * Opens a position of `amount` under the tier, once the stake has been paid for:
+ * credits the upline, records the volume of the referral link and the credit of the position,
+ * starts accrual, updates the totals and mints the position NFT when its accounts are provided.
+ * referrer_accounts are the writable staking accounts of the upline, in order.
+ */
+pub(crate) fn open_position<'info>(
+    global_config: &mut GlobalConfig,
+    tier: &mut LockTier,
+    tier_id: u8,
+    position: NewPosition<'_, 'info>,
+    referrer_accounts: &[AccountInfo<'info>],
+    amount: u64,
+    now: i64,
+) -> Result<()> {
+    let NewPosition {
+        staking_account,
+        user_staked,
+        referral_entry,
+        referral_credit,
+        referral_credit_bump,
+        position_nft,
+    } = position;
 
-    let now = Clock::get()?.unix_timestamp;
+    msg!(
+        "Current APY from tier {}: {}",
+        tier_id,
+        tier.current_apy_bps
+    );
 
-    user_staked.last_claimed_timestamp = now;
+    let credits = apply_referral_rewards_for_all_referrer(
+        referrer_accounts,
+        global_config,
+        &staking_account.upline,
+        amount,
+    )?;
+
+    ReferralEntry::record_volume(
+        referral_entry,
+        staking_account.referrer != Pubkey::default(),
+        amount,
+    )?;
+
+    ReferralCredit::record(
+        referral_credit,
+        referral_credit_bump,
+        (user_staked.key(), ReferralCreditSource::Position),
+        staking_account.key(),
+        &staking_account.upline,
+        &credits,
+    )?;
+
+    user_staked.amount_staked = amount;
+
+    msg!("Amount staked: {}", amount);
+
+    user_staked.start_accrual(tier, now)?;
     user_staked.staked_timestamp = now;
     user_staked.identifier = staking_account.user_staked_counter;
     user_staked.user = staking_account.user;
+    user_staked.tier_id = tier_id;
+    user_staked.lock_duration = tier.duration;
 
     staking_account.total_staked = staking_account
         .total_staked
-        .checked_add(net_amount)
+        .checked_add(amount)
         .ok_or(ErrorCode::Overflow)?;
 
     staking_account.user_staked_counter = staking_account
@@ -239,62 +338,11 @@ pub fn stake_by_admin<'a>(
 
     global_config.total_staked = global_config
         .total_staked
-        .checked_add(net_amount)
+        .checked_add(amount)
         .ok_or(ErrorCode::Overflow)?;
 
-    staking_account.claim_airdrop = true;
-
-    Ok(())
-}
-
-fn apply_referral_rewards_for_all_referrer(
-    remaining_accounts: &[AccountInfo],
-    global_config: &Account<GlobalConfig>,
-    stake_amount: u64,
-    first_referrer: Pubkey
-) -> Result<()> {
-    let mut next_referrer = first_referrer;
-
-    for (index, account_info) in remaining_accounts.iter().enumerate() {
-        if next_referrer != account_info.key() {
-            return Err(ErrorCode::ReferrerMismatch.into());
-        }
-
-        let commission_percentage = global_config.lvl_percentages.get(index).copied().unwrap_or(0);
-
-        let commission = stake_amount
-            .checked_mul(commission_percentage as u64)
-            .ok_or(ErrorCode::Overflow)?
-            .checked_div(100)
-            .ok_or(ErrorCode::Overflow)?;
-
-        if commission > 0 {
-            let referrer_account = &mut account_info.try_borrow_mut_data()?[..];
-
-            let mut referrer_data: StakingAccount = StakingAccount::try_deserialize(&mut &referrer_account[..])
-                .map_err(|_| ErrorCode::DeserializationError)?;
-
-            next_referrer = referrer_data.referrer;
-
-            if referrer_data.total_referral_staked == 0 {
-                referrer_data.last_referral_rewards_claimed = Clock::get()?.unix_timestamp;
-            }
-
-            referrer_data.total_referral_staked = referrer_data
-                .total_referral_staked
-                .checked_add(stake_amount)
-                .ok_or(ErrorCode::Overflow)?;
-
-            referrer_data.daily_referral_rewards = referrer_data
-                .daily_referral_rewards
-                .checked_add(commission)
-                .ok_or(ErrorCode::Overflow)?;
-
-            let mut cursor = std::io::Cursor::new(referrer_account);
-
-            StakingAccount::try_serialize(&referrer_data, &mut cursor)
-                .map_err(|_| ErrorCode::SerializationError)?;
-        }
+    if let Some(position_nft) = &position_nft {
+        position_nft.mint(global_config.transfer_manager_bumps, user_staked, now)?;
     }
 
     Ok(())
@@ -309,13 +357,40 @@ pub struct Stake<'info> {
     )]
     pub global_config: Account<'info, GlobalConfig>,
 
+    #[account(
+        mut,
+        seeds = [b"tiers"],
+        bump = tier_registry.load()?.bump
+    )]
+    pub tier_registry: AccountLoader<'info, TierRegistry>,
+
+    #[account(
+        seeds = [b"roles"],
+        bump = role_registry.bump
+    )]
+    pub role_registry: Account<'info, RoleRegistry>,
+
+    #[account(
+        seeds = [b"multisig"],
+        bump = multisig.bump
+    )]
+    pub multisig: Option<Account<'info, Multisig>>,
+
+    // Approved proposal, replaces the role check of stake_airdrop and stake_by_admin
+    #[account(
+        mut,
+        seeds = [b"proposal", proposal.id.to_le_bytes().as_ref()],
+        bump = proposal.bump
+    )]
+    pub proposal: Option<Account<'info, Proposal>>,
+
     #[account(mut)]
     pub staking_account: Account<'info, StakingAccount>,
 
     #[account(
         init,
         payer = user,
-        space = 8 + 8 + 8 + 8 + 8 + 32,
+        space = UserStakedAccount::LEN,
         seeds = [b"user-staked", staking_account.key().as_ref(), staking_account.user_staked_counter.to_le_bytes().as_ref()],
         bump
     )]
@@ -334,9 +409,99 @@ pub struct Stake<'info> {
     #[account(mut)]
     pub treasury_ata: Account<'info, TokenAccount>,
 
+    // Link of the user to its referrer, required when the user has a referrer
+    #[account(
+        mut,
+        seeds = [b"referral", staking_account.referrer.as_ref(), staking_account.key().as_ref()],
+        bump = referral_entry.bump
+    )]
+    pub referral_entry: Option<Account<'info, ReferralEntry>>,
+
+    // Commission credited to the upline for this position, required when some level was paid
+    #[account(
+        init,
+        payer = user,
+        space = ReferralCredit::LEN,
+        seeds = [b"referral-credit", user_staked_account.key().as_ref()],
+        bump
+    )]
+    pub referral_credit: Option<Account<'info, ReferralCredit>>,
+
+    /// CHECK: blacklist entry of the user, empty unless the user is blacklisted
+    #[account(
+        seeds = [b"blacklist", staking_account.user.as_ref()],
+        bump,
+        constraint = !BlacklistEntry::is_blacklisted(&blacklist_entry) @ ErrorCode::Blacklisted
+    )]
+    pub blacklist_entry: UncheckedAccount<'info>,
+
+    /// CHECK: empty PDA, mint authority of the position NFT, required with position_mint
+    #[account(
+        seeds = [b"transfer_manager"],
+        bump = global_config.transfer_manager_bumps,
+    )]
+    pub transfer_manager: Option<UncheckedAccount<'info>>,
+
+    // NFT of the new position, the position is opened tokenized when it is provided
+    #[account(
+        init,
+        seeds = [b"position-mint", user_staked_account.key().as_ref()],
+        bump,
+        payer = user,
+        mint::authority = global_config.transfer_manager,
+        mint::freeze_authority = global_config.transfer_manager,
+        mint::decimals = 0,
+    )]
+    pub position_mint: Option<Box<Account<'info, Mint>>>,
+
+    /// CHECK: owner of the staking account, receives the position NFT, required with position_mint
+    #[account(address = staking_account.user @ ErrorCode::InvalidUser)]
+    pub position_owner: Option<UncheckedAccount<'info>>,
+
+    #[account(
+        init,
+        payer = user,
+        associated_token::mint = position_mint,
+        associated_token::authority = position_owner,
+    )]
+    pub position_token_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    /// CHECK: metadata PDA of position_mint, derivation checked by the metadata program
+    #[account(mut)]
+    pub metadata_account: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: master edition PDA of position_mint, derivation checked by the metadata program
+    #[account(mut)]
+    pub master_edition_account: Option<UncheckedAccount<'info>>,
+
+    pub token_metadata_program: Option<Program<'info, Metadata>>,
+    pub associated_token_program: Option<Program<'info, AssociatedToken>>,
+    pub rent: Option<Sysvar<'info, Rent>>,
+
     #[account(mut)]
     pub user: Signer<'info>,
 
     pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
 }
+
+impl<'info> Stake<'info> {
+    fn position_nft(&self) -> Result<Option<PositionNft<'info>>> {
+        let Some(position_mint) = &self.position_mint else {
+            return Ok(None);
+        };
+
+        Ok(Some(PositionNft {
+            position_mint: position_mint.to_account_info(),
+            position_token_account: position_nft_account(self.position_token_account.as_deref())?,
+            metadata_account: position_nft_account(self.metadata_account.as_ref())?,
+            master_edition_account: position_nft_account(self.master_edition_account.as_ref())?,
+            transfer_manager: position_nft_account(self.transfer_manager.as_ref())?,
+            payer: self.user.to_account_info(),
+            token_metadata_program: position_nft_account(self.token_metadata_program.as_ref())?,
+            token_program: self.token_program.to_account_info(),
+            system_program: self.system_program.to_account_info(),
+            rent: position_nft_account(self.rent.as_ref())?,
+        }))
+    }
+}
@@ -0,0 +1,254 @@
+use crate::{
+    constants::constants,
+    errors::ErrorCode,
+    events::PositionTokenized,
+    state::{BlacklistEntry, GlobalConfig, UserStakedAccount},
+};
+
+use ::{
+    anchor_lang::prelude::*,
+    anchor_spl::{
+        associated_token::AssociatedToken,
+        metadata::{
+            create_master_edition_v3, create_metadata_accounts_v3,
+            mpl_token_metadata::types::DataV2, CreateMasterEditionV3, CreateMetadataAccountsV3,
+            Metadata,
+        },
+        token::{mint_to, Mint, MintTo, Token, TokenAccount},
+    },
+};
+
+pub const POSITION_NAME: &str = "Staked Position";
+pub const POSITION_SYMBOL: &str = "STAKE";
+
+/*
+ * Mints a Metaplex NFT for an open position that was opened without one. From then on claims and
+ * unstakes are authorised by whoever holds the NFT, so the position can be traded while it is locked.
+ */
+pub fn tokenize_position(ctx: Context<TokenizePosition>) -> Result<()> {
+    ctx.accounts.global_config.require_not_paused(GlobalConfig::PAUSE_STAKE)?;
+
+    let now = Clock::get()?.unix_timestamp;
+
+    if ctx.accounts.user_staked_account.is_tokenized() {
+        return Err(ErrorCode::PositionAlreadyTokenized.into());
+    }
+
+    if ctx.accounts.user_staked_account.maturity_timestamp() < now {
+        return Err(ErrorCode::StakeExpired.into());
+    }
+
+    let position_nft = PositionNft {
+        position_mint: ctx.accounts.position_mint.to_account_info(),
+        position_token_account: ctx.accounts.position_token_account.to_account_info(),
+        metadata_account: ctx.accounts.metadata_account.to_account_info(),
+        master_edition_account: ctx.accounts.master_edition_account.to_account_info(),
+        transfer_manager: ctx.accounts.transfer_manager.to_account_info(),
+        payer: ctx.accounts.user.to_account_info(),
+        token_metadata_program: ctx.accounts.token_metadata_program.to_account_info(),
+        token_program: ctx.accounts.token_program.to_account_info(),
+        system_program: ctx.accounts.system_program.to_account_info(),
+        rent: ctx.accounts.rent.to_account_info(),
+    };
+
+    position_nft.mint(
+        ctx.accounts.global_config.transfer_manager_bumps,
+        &mut ctx.accounts.user_staked_account,
+        now,
+    )
+}
+
+/*
+ * Accounts of a position NFT. Instructions that open a position take them as optional accounts
+ * and mint the NFT in the same instruction when position_mint is provided, instead of a separate
+ * tokenize_position.
+ */
+pub(crate) struct PositionNft<'info> {
+    pub position_mint: AccountInfo<'info>,
+    pub position_token_account: AccountInfo<'info>,
+    pub metadata_account: AccountInfo<'info>,
+    pub master_edition_account: AccountInfo<'info>,
+    pub transfer_manager: AccountInfo<'info>,
+    pub payer: AccountInfo<'info>,
+    pub token_metadata_program: AccountInfo<'info>,
+    pub token_program: AccountInfo<'info>,
+    pub system_program: AccountInfo<'info>,
+    pub rent: AccountInfo<'info>,
+}
+
+// Position NFT accounts are optional when opening a position, but all of them come with position_mint
+pub(crate) fn position_nft_account<'info, T: ToAccountInfo<'info>>(
+    account: Option<&T>,
+) -> Result<AccountInfo<'info>> {
+    Ok(account
+        .ok_or(ErrorCode::ShouldProvidePositionToken)?
+        .to_account_info())
+}
+
+impl<'info> PositionNft<'info> {
+    // Mints the NFT into position_token_account, from then on its holder controls the position
+    pub fn mint(
+        &self,
+        transfer_manager_bumps: u8,
+        user_staked: &mut Account<'info, UserStakedAccount>,
+        now: i64,
+    ) -> Result<()> {
+        let signer_seeds: &[&[&[u8]]] = &[&[b"transfer_manager", &[transfer_manager_bumps]]];
+
+        mint_to(
+            CpiContext::new(
+                self.token_program.clone(),
+                MintTo {
+                    mint: self.position_mint.clone(),
+                    to: self.position_token_account.clone(),
+                    authority: self.transfer_manager.clone(),
+                },
+            )
+            .with_signer(signer_seeds),
+            1,
+        )?;
+
+        create_metadata_accounts_v3(
+            CpiContext::new(
+                self.token_metadata_program.clone(),
+                CreateMetadataAccountsV3 {
+                    metadata: self.metadata_account.clone(),
+                    mint: self.position_mint.clone(),
+                    mint_authority: self.transfer_manager.clone(),
+                    update_authority: self.transfer_manager.clone(),
+                    payer: self.payer.clone(),
+                    system_program: self.system_program.clone(),
+                    rent: self.rent.clone(),
+                },
+            )
+            .with_signer(signer_seeds),
+            DataV2 {
+                name: format!("{} #{}", POSITION_NAME, user_staked.identifier),
+                symbol: POSITION_SYMBOL.to_string(),
+                uri: constants::TOKEN_URI.to_string(),
+                seller_fee_basis_points: 0,
+                creators: None,
+                collection: None,
+                uses: None,
+            },
+            false,
+            true,
+            None,
+        )?;
+
+        // Max supply of 0 makes the mint a one of one, the edition takes over the mint authority
+        create_master_edition_v3(
+            CpiContext::new(
+                self.token_metadata_program.clone(),
+                CreateMasterEditionV3 {
+                    edition: self.master_edition_account.clone(),
+                    mint: self.position_mint.clone(),
+                    update_authority: self.transfer_manager.clone(),
+                    mint_authority: self.transfer_manager.clone(),
+                    payer: self.payer.clone(),
+                    metadata: self.metadata_account.clone(),
+                    token_program: self.token_program.clone(),
+                    system_program: self.system_program.clone(),
+                    rent: self.rent.clone(),
+                },
+            )
+            .with_signer(signer_seeds),
+            Some(0),
+        )?;
+
+        user_staked.position_mint = self.position_mint.key();
+
+        emit!(PositionTokenized {
+            user: user_staked.user,
+            user_staked_account: user_staked.key(),
+            position_mint: user_staked.position_mint,
+            timestamp: now,
+        });
+
+        msg!("Position tokenized: {:?}", user_staked.position_mint);
+
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct TokenizePosition<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = global_config.config_bumps
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(mut, has_one = user)]
+    pub user_staked_account: Account<'info, UserStakedAccount>,
+
+    /// CHECK: empty PDA, manager for token accounts
+    #[account(
+        seeds = [b"transfer_manager"],
+        bump = global_config.transfer_manager_bumps,
+    )]
+    pub transfer_manager: AccountInfo<'info>,
+
+    #[account(
+        init,
+        seeds = [b"position-mint", user_staked_account.key().as_ref()],
+        bump,
+        payer = user,
+        mint::authority = transfer_manager,
+        mint::freeze_authority = transfer_manager,
+        mint::decimals = 0,
+    )]
+    pub position_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        init,
+        payer = user,
+        associated_token::mint = position_mint,
+        associated_token::authority = user,
+    )]
+    pub position_token_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK:
+    #[account(
+        mut,
+        seeds = [
+            b"metadata",
+            token_metadata_program.key().as_ref(),
+            position_mint.key().as_ref()
+        ],
+        bump,
+        seeds::program = token_metadata_program.key(),
+    )]
+    pub metadata_account: UncheckedAccount<'info>,
+
+    /// CHECK:
+    #[account(
+        mut,
+        seeds = [
+            b"metadata",
+            token_metadata_program.key().as_ref(),
+            position_mint.key().as_ref(),
+            b"edition"
+        ],
+        bump,
+        seeds::program = token_metadata_program.key(),
+    )]
+    pub master_edition_account: UncheckedAccount<'info>,
+
+    /// CHECK: blacklist entry of the user, empty unless the user is blacklisted
+    #[account(
+        seeds = [b"blacklist", user.key().as_ref()],
+        bump,
+        constraint = !BlacklistEntry::is_blacklisted(&blacklist_entry) @ ErrorCode::Blacklisted
+    )]
+    pub blacklist_entry: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    pub token_metadata_program: Program<'info, Metadata>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
+}
@@ -0,0 +1,379 @@
+use crate::{
+    errors::ErrorCode,
+    events::{EarlyUnstaked, Unstaked},
+    instructions::pay_rewards,
+    state::{BlacklistEntry, GlobalConfig, StakingAccount, TierRegistry, UserStakedAccount},
+};
+
+use anchor_lang::prelude::*;
+use anchor_spl::associated_token::get_associated_token_address;
+use anchor_spl::token::{self, Burn, Mint, MintTo, Token, TokenAccount, Transfer};
+
+#[derive(Accounts)]
+pub struct Unstake<'info> {
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = global_config.config_bumps
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"tiers"],
+        bump = tier_registry.load()?.bump
+    )]
+    pub tier_registry: AccountLoader<'info, TierRegistry>,
+
+    // Staking account the position was opened under, the signer may be another holder
+    #[account(
+        mut,
+        seeds = [b"staking", user_staked_account.user.as_ref()],
+        bump
+    )]
+    pub staking_account: Account<'info, StakingAccount>,
+
+    #[account(
+        mut,
+        close = user,
+        constraint = user_staked_account.is_authorized(&user.key(), position_token_account.as_deref()) @ ErrorCode::InvalidUser
+    )]
+    pub user_staked_account: Account<'info, UserStakedAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"mint"],
+        bump = global_config.mint_bumps
+    )]
+    pub mint: Account<'info, Mint>,
+
+    /// CHECK: empty PDA, manager for token accounts
+    #[account(
+        seeds = [b"transfer_manager"],
+        bump = global_config.transfer_manager_bumps,
+    )]
+    pub transfer_manager: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub user_token_account: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub treasury_ata: Account<'info, TokenAccount>,
+
+    // Position NFT and the holder's account, required only for tokenized positions
+    #[account(mut)]
+    pub position_mint: Option<Account<'info, Mint>>,
+
+    #[account(mut)]
+    pub position_token_account: Option<Account<'info, TokenAccount>>,
+
+    // Transfer manager ATA holding the rewards, required only in vault mode
+    #[account(mut)]
+    pub reward_vault: Option<Account<'info, TokenAccount>>,
+
+    /// CHECK: blacklist entry of the user, empty unless the user is blacklisted
+    #[account(
+        seeds = [b"blacklist", user.key().as_ref()],
+        bump,
+        constraint = !BlacklistEntry::is_blacklisted(&blacklist_entry) @ ErrorCode::Blacklisted
+    )]
+    pub blacklist_entry: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+pub fn handler(ctx: Context<Unstake>) -> Result<()> {
+    ctx.accounts.global_config.require_not_paused(GlobalConfig::PAUSE_CLAIM)?;
+
+    let now = Clock::get()?.unix_timestamp;
+
+    validate_accounts(ctx.accounts)?;
+
+    let user_staked_account = &ctx.accounts.user_staked_account;
+    let maturity = user_staked_account.maturity_timestamp();
+
+    if now < maturity {
+        msg!("Stake not matured: You may unstake at {:?}", maturity);
+        return Err(ErrorCode::StakeNotMatured.into());
+    }
+
+    // Rewards stop accruing at maturity, settle whatever is still owed up to it
+    let (reward_lamports_u64, _) = {
+        let mut tier_registry = ctx.accounts.tier_registry.load_mut()?;
+        let tier = tier_registry.get_tier_mut(user_staked_account.tier_id)?;
+
+        user_staked_account.stop_accrual(tier)?;
+        user_staked_account.claimable_rewards(
+            ctx.accounts.global_config.continuous_accrual,
+            tier,
+            maturity,
+        )?
+    };
+    let principal = user_staked_account.amount_staked;
+    let user = user_staked_account.user;
+    let user_staked_key = user_staked_account.key();
+
+    msg!("Calculated rewards: {:?}", reward_lamports_u64);
+
+    let granted = reserve_rewards(ctx.accounts, reward_lamports_u64, now)?;
+
+    let withdrawal_tax = release_principal(ctx.accounts, principal, principal, granted)?;
+
+    emit!(Unstaked {
+        user,
+        holder: ctx.accounts.user.key(),
+        user_staked_account: user_staked_key,
+        amount_staked: principal,
+        rewards: granted,
+        deferred: reward_lamports_u64
+            .checked_sub(granted)
+            .ok_or(ErrorCode::Underflow)?,
+        withdrawal_fee: withdrawal_tax,
+        timestamp: now,
+    });
+
+    msg!("Amount unstaked: {}", principal);
+
+    Ok(())
+}
+
+pub fn early_unstake(ctx: Context<Unstake>) -> Result<()> {
+    ctx.accounts.global_config.require_not_paused(GlobalConfig::PAUSE_CLAIM)?;
+
+    let now = Clock::get()?.unix_timestamp;
+
+    validate_accounts(ctx.accounts)?;
+
+    let global_config = &ctx.accounts.global_config;
+    let user_staked_account = &ctx.accounts.user_staked_account;
+    let maturity = user_staked_account.maturity_timestamp();
+
+    if now >= maturity {
+        return Err(ErrorCode::StakeMatured.into());
+    }
+
+    let (reward_lamports_u64, _) = {
+        let mut tier_registry = ctx.accounts.tier_registry.load_mut()?;
+        let tier = tier_registry.get_tier_mut(user_staked_account.tier_id)?;
+
+        user_staked_account.stop_accrual(tier)?;
+        user_staked_account.claimable_rewards(global_config.continuous_accrual, tier, now)?
+    };
+    let principal = user_staked_account.amount_staked;
+
+    let early_exit_penalty = global_config.early_exit_penalty;
+    let penalty_bps = early_exit_penalty.penalty_bps(
+        now - user_staked_account.staked_timestamp,
+        user_staked_account.lock_duration,
+    );
+
+    let penalty = principal
+        .checked_mul(penalty_bps as u64)
+        .ok_or(ErrorCode::Overflow)?
+        .checked_div(10_000)
+        .ok_or(ErrorCode::Overflow)?;
+
+    msg!("Calculated rewards: {:?}, early exit penalty: {:?}", reward_lamports_u64, penalty);
+
+    let user = user_staked_account.user;
+    let user_staked_key = user_staked_account.key();
+
+    let granted = reserve_rewards(ctx.accounts, reward_lamports_u64, now)?;
+
+    let released = principal
+        .checked_sub(penalty)
+        .ok_or(ErrorCode::Underflow)?;
+
+    let withdrawal_tax = release_principal(ctx.accounts, principal, released, granted)?;
+
+    // Principal was burned on stake, so a burned penalty is simply never re-minted
+    if penalty > 0 && !early_exit_penalty.burn {
+        let treasury_ata = ctx.accounts.treasury_ata.to_account_info();
+        mint_from_manager(ctx.accounts, treasury_ata, penalty)?;
+
+        msg!("Minted early exit penalty to treasury.");
+    }
+
+    emit!(EarlyUnstaked {
+        user,
+        user_staked_account: user_staked_key,
+        amount_staked: principal,
+        penalty,
+        penalty_bps,
+        penalty_burned: early_exit_penalty.burn,
+        rewards: granted,
+        withdrawal_fee: withdrawal_tax,
+        timestamp: now,
+    });
+
+    msg!("Amount unstaked early: {}", principal);
+
+    Ok(())
+}
+
+fn validate_accounts(accounts: &Unstake) -> Result<()> {
+    let treasury =
+        get_associated_token_address(&accounts.global_config.treasury, &accounts.global_config.mint);
+
+    if treasury != accounts.treasury_ata.key().clone() {
+        return Err(ErrorCode::InvalidTreasury.into());
+    }
+
+    Ok(())
+}
+
+/*
+ * The position is closed, rewards that do not fit into the emissions budget move to the staking account
+ * and are paid by its next claim. A holder of a tokenized position is not that account's owner,
+ * they have to wait for the next epoch instead.
+ */
+fn reserve_rewards(accounts: &mut Unstake, rewards: u64, now: i64) -> Result<u64> {
+    let mint_supply = accounts.mint.supply;
+    let granted = accounts.global_config.reserve_rewards(rewards, mint_supply, now)?;
+    let deferred = rewards.checked_sub(granted).ok_or(ErrorCode::Underflow)?;
+
+    if deferred > 0 {
+        let staking_account = &mut accounts.staking_account;
+
+        require!(
+            accounts.user.key() == staking_account.user,
+            ErrorCode::EmissionsBudgetExhausted
+        );
+
+        staking_account.deferred_rewards = staking_account
+            .deferred_rewards
+            .checked_add(deferred)
+            .ok_or(ErrorCode::Overflow)?;
+    }
+
+    Ok(granted)
+}
+
+// Returns the withdrawal fee charged on the payout
+fn release_principal<'info>(
+    accounts: &mut Unstake<'info>,
+    principal: u64,
+    released: u64,
+    rewards: u64,
+) -> Result<u64> {
+    burn_position_token(accounts)?;
+
+    let payout = released
+        .checked_add(rewards)
+        .ok_or(ErrorCode::Overflow)?;
+
+    // Principal was burned on stake, re-mint it together with the rewards unless the reward vault pays them
+    let user_token_account = accounts.user_token_account.to_account_info();
+
+    if accounts.global_config.vault_rewards {
+        mint_from_manager(accounts, user_token_account.clone(), released)?;
+
+        if rewards > 0 {
+            pay_rewards(
+                &accounts.global_config,
+                &accounts.mint,
+                &accounts.transfer_manager,
+                accounts.reward_vault.as_ref(),
+                user_token_account,
+                &accounts.token_program,
+                rewards,
+            )?;
+        }
+    } else {
+        mint_from_manager(accounts, user_token_account, payout)?;
+    }
+
+    msg!("Paid principal and rewards to user's account.");
+
+    let withdrawal_tax = payout
+        .checked_mul(accounts.global_config.withdrawal_fee_bps as u64)
+        .ok_or(ErrorCode::Overflow)?
+        .checked_div(10_000)
+        .ok_or(ErrorCode::Overflow)?;
+
+    let cpi_ctx_tax = CpiContext::new(
+        accounts.token_program.to_account_info(),
+        Transfer {
+            from: accounts.user_token_account.to_account_info(),
+            to: accounts.treasury_ata.to_account_info(),
+            authority: accounts.user.to_account_info(),
+        },
+    );
+    token::transfer(cpi_ctx_tax, withdrawal_tax)?;
+
+    let staking_account = &mut accounts.staking_account;
+
+    staking_account.total_staked = staking_account
+        .total_staked
+        .checked_sub(principal)
+        .ok_or(ErrorCode::Underflow)?;
+
+    staking_account.closed_stakes_counter = staking_account
+        .closed_stakes_counter
+        .checked_add(1)
+        .ok_or(ErrorCode::Overflow)?;
+
+    let global_config = &mut accounts.global_config;
+
+    global_config.total_staked = global_config
+        .total_staked
+        .checked_sub(principal)
+        .ok_or(ErrorCode::Underflow)?;
+
+    Ok(withdrawal_tax)
+}
+
+// The position NFT is worthless once the position is closed
+fn burn_position_token(accounts: &Unstake) -> Result<()> {
+    if !accounts.user_staked_account.is_tokenized() {
+        return Ok(());
+    }
+
+    let (Some(position_mint), Some(position_token_account)) =
+        (&accounts.position_mint, &accounts.position_token_account)
+    else {
+        return Err(ErrorCode::ShouldProvidePositionToken.into());
+    };
+
+    if position_mint.key() != accounts.user_staked_account.position_mint {
+        return Err(ErrorCode::ShouldProvidePositionToken.into());
+    }
+
+    let cpi_ctx_burn = CpiContext::new(
+        accounts.token_program.to_account_info(),
+        Burn {
+            from: position_token_account.to_account_info(),
+            mint: position_mint.to_account_info(),
+            authority: accounts.user.to_account_info(),
+        },
+    );
+    token::burn(cpi_ctx_burn, 1)?;
+
+    msg!("Burned position NFT.");
+
+    Ok(())
+}
+
+fn mint_from_manager<'info>(
+    accounts: &Unstake<'info>,
+    to: AccountInfo<'info>,
+    amount: u64,
+) -> Result<()> {
+    let manager_bumps = accounts.global_config.transfer_manager_bumps.clone();
+    let manager_seeds: &[&[&[u8]]] = &[&[b"transfer_manager", &[manager_bumps]]];
+
+    let context = CpiContext::new(
+        accounts.token_program.to_account_info(),
+        MintTo {
+            mint: accounts.mint.to_account_info(),
+            to,
+            authority: accounts.transfer_manager.to_account_info(),
+        },
+    )
+    .with_signer(manager_seeds);
+
+    anchor_spl::token::mint_to(context, amount)
+}
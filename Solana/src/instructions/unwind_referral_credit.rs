@@ -0,0 +1,94 @@
+use crate::{
+    errors::ErrorCode,
+    instructions::unwind_referral_rewards_for_all_referrer,
+    state::{GlobalConfig, ReferralCredit, ReferralCreditSource, StakingAccount, UserStakedAccount},
+};
+
+use anchor_lang::prelude::*;
+
+#[derive(Accounts)]
+pub struct UnwindReferralCredit<'info> {
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = global_config.config_bumps
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        close = rent_receiver,
+        seeds = [b"referral-credit", referral_credit.source.as_ref()],
+        bump = referral_credit.bump
+    )]
+    pub referral_credit: Account<'info, ReferralCredit>,
+
+    /// CHECK: position or referral entry the credit belongs to, may already be closed
+    #[account(address = referral_credit.source)]
+    pub source: UncheckedAccount<'info>,
+
+    #[account(address = referral_credit.staking_account)]
+    pub staking_account: Account<'info, StakingAccount>,
+
+    /// CHECK: owner of the staking account, gets back the rent of the credit
+    #[account(mut, address = staking_account.user)]
+    pub rent_receiver: UncheckedAccount<'info>,
+
+    pub cranker: Signer<'info>,
+
+    /*
+    REMAINING_ACCOUNTS: staking accounts of referral_credit.upline, in order
+     */
+}
+
+/*
+ * Permissionless crank, takes the commission of a finished source off every upline level:
+ * - positions once they mature or are unstaked, or merged positions whose credit was made for another upline
+ * - referral links once they are replaced or the referred user holds no stake anymore
+ */
+pub fn unwind_referral_credit(ctx: Context<UnwindReferralCredit>) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let referral_credit = &ctx.accounts.referral_credit;
+
+    require!(
+        is_source_finished(
+            referral_credit,
+            &ctx.accounts.source,
+            &ctx.accounts.staking_account,
+            now
+        )?,
+        ErrorCode::ReferralCreditActive
+    );
+
+    unwind_referral_rewards_for_all_referrer(
+        ctx.remaining_accounts,
+        &mut ctx.accounts.global_config,
+        referral_credit,
+    )?;
+
+    msg!("Referral credit unwound for {}", referral_credit.source);
+
+    Ok(())
+}
+
+fn is_source_finished(
+    referral_credit: &ReferralCredit,
+    source: &AccountInfo,
+    staking_account: &StakingAccount,
+    now: i64,
+) -> Result<bool> {
+    // Closed positions and replaced referral entries
+    if source.owner != &crate::ID || source.data_is_empty() {
+        return Ok(true);
+    }
+
+    match referral_credit.kind {
+        ReferralCreditSource::Position => {
+            let position = UserStakedAccount::try_deserialize(&mut &source.try_borrow_data()?[..])
+                .map_err(|_| ErrorCode::DeserializationError)?;
+
+            Ok(position.maturity_timestamp() < now)
+        }
+        ReferralCreditSource::Link => Ok(staking_account.total_staked == 0),
+    }
+}
@@ -1,87 +1,512 @@
 use crate::errors::ErrorCode;
-use crate::state::{ApyChange, GlobalConfig};
+use crate::events::{
+    AccrualModeUpdated, EarlyExitPenaltyUpdated, EmissionsBudgetUpdated, FeeKind, FeesUpdated,
+    LevelPercentagesUpdated, LockTierUpdated, OtcCommissionUpdated, ReferralApyUpdated,
+    ReferralRanksUpdated, RewardModeUpdated, TimelockDelayUpdated, TreasuryUpdated,
+};
+use crate::instructions::{authorize_privileged, lock_tiers::apply_lock_tier};
+use crate::state::{
+    ConfigChange, EarlyExitPenalty, GlobalConfig, Multisig, OtcCommission, PendingConfigChange,
+    Proposal, ProposalAction, ReferralRank, ReferralRanks, RoleRegistry, TierRegistry,
+    MAX_REFERRAL_RANKS,
+};
 use anchor_lang::prelude::*;
-pub fn update_apy(ctx: Context<UpdateGlobalConfig>, new_apy: u32) -> Result<()> {
-    let global_config = &mut ctx.accounts.global_config;
-    let now = Clock::get()?.unix_timestamp;
 
-    // Ensure the APY is within the valid range
-    require!((1_000..=100_000).contains(&new_apy), ErrorCode::InvalidAPY);
-
-    /*
-     * Update APY history:
-     * apy_bps - previous APY value
-     * timestamp - time while previous APY value is working
-     */
-    global_config.apy_history[0] = ApyChange {
-        apy_bps: global_config.current_apy_bps,
-        timestamp: now,
-    };
+pub fn update_referral_apy(ctx: Context<UpdateGlobalConfig>, new_apy: u32) -> Result<()> {
+    let change = ConfigChange::ReferralApy(new_apy);
 
-    // Update the current APY in the global configuration
-    global_config.current_apy_bps = new_apy;
+    ctx.accounts.authorize(ProposalAction::UpdateConfig { change })?;
 
-    msg!("APY updated to {} BPS", new_apy);
-    Ok(())
+    let global_config = &mut ctx.accounts.global_config;
+
+    require!(global_config.min_timelock_delay == 0, ErrorCode::TimelockRequired);
+
+    apply_config_change(global_config, None, change, ctx.accounts.authority.key())
 }
 
 pub fn update_withdrawal_fee(ctx: Context<UpdateGlobalConfig>, new_fee_bps: u16) -> Result<()> {
-    let global_config = &mut ctx.accounts.global_config;
+    let change = ConfigChange::WithdrawalFee(new_fee_bps);
 
-    require!(new_fee_bps <= 2_500, ErrorCode::InvalidWithdrawalFee);
+    ctx.accounts.authorize(ProposalAction::UpdateConfig { change })?;
 
-    global_config.withdrawal_fee_bps = new_fee_bps;
+    let global_config = &mut ctx.accounts.global_config;
 
-    msg!("Withdrawal fee updated to {} BPS", new_fee_bps);
-    Ok(())
+    require!(global_config.min_timelock_delay == 0, ErrorCode::TimelockRequired);
+
+    apply_config_change(global_config, None, change, ctx.accounts.authority.key())
 }
+
 pub fn update_lvl_percentages(
     ctx: Context<UpdateGlobalConfig>,
     new_percentages: [u8; 10],
 ) -> Result<()> {
+    let change = ConfigChange::LvlPercentages(new_percentages);
+
+    ctx.accounts.authorize(ProposalAction::UpdateConfig { change })?;
+
+    let global_config = &mut ctx.accounts.global_config;
+
+    require!(global_config.min_timelock_delay == 0, ErrorCode::TimelockRequired);
+
+    apply_config_change(global_config, None, change, ctx.accounts.authority.key())
+}
+
+pub fn update_compound_fee(ctx: Context<UpdateGlobalConfig>, new_fee_bps: u16) -> Result<()> {
+    let change = ConfigChange::CompoundFee(new_fee_bps);
+
+    ctx.accounts.authorize(ProposalAction::UpdateConfig { change })?;
+
+    let global_config = &mut ctx.accounts.global_config;
+
+    require!(global_config.min_timelock_delay == 0, ErrorCode::TimelockRequired);
+
+    apply_config_change(global_config, None, change, ctx.accounts.authority.key())
+}
+
+// Raising the delay only makes the admin slower, lowering it has to go through the timelock itself
+pub fn update_timelock_delay(ctx: Context<UpdateGlobalConfig>, new_delay: i64) -> Result<()> {
+    let change = ConfigChange::TimelockDelay(new_delay);
+
+    ctx.accounts.authorize(ProposalAction::UpdateConfig { change })?;
+
     let global_config = &mut ctx.accounts.global_config;
 
     require!(
-        new_percentages.iter().all(|&p| p <= 100),
-        ErrorCode::InvalidLevelPercentage
+        new_delay >= global_config.min_timelock_delay,
+        ErrorCode::TimelockRequired
     );
 
-    global_config.lvl_percentages = new_percentages;
+    apply_config_change(global_config, None, change, ctx.accounts.authority.key())
+}
+
+pub fn validate_config_change(change: &ConfigChange) -> Result<()> {
+    match change {
+        // Ensure the APY is within the valid range
+        ConfigChange::ReferralApy(new_apy) => {
+            require!((1_000..=100_000).contains(new_apy), ErrorCode::InvalidAPY);
+        }
+        ConfigChange::WithdrawalFee(new_fee_bps) => {
+            require!(*new_fee_bps <= 2_500, ErrorCode::InvalidWithdrawalFee);
+        }
+        ConfigChange::LvlPercentages(new_percentages) => {
+            require!(
+                new_percentages.iter().all(|&p| p <= 100),
+                ErrorCode::InvalidLevelPercentage
+            );
+        }
+        ConfigChange::CompoundFee(new_fee_bps) => {
+            require!(*new_fee_bps <= 2_500, ErrorCode::InvalidCompoundFee);
+        }
+        ConfigChange::TimelockDelay(new_delay) => {
+            require!(
+                (0..=PendingConfigChange::MAX_TIMELOCK_DELAY).contains(new_delay),
+                ErrorCode::InvalidTimelockDelay
+            );
+        }
+        ConfigChange::AccrualMode(_) => {}
+        ConfigChange::EarlyExitPenalty(new_penalty) => {
+            require!(new_penalty.is_valid(), ErrorCode::InvalidEarlyExitPenalty);
+        }
+        ConfigChange::LockTier {
+            duration,
+            apy_bps,
+            deposit_fee_bps,
+            ..
+        } => {
+            require!(*duration > 0, ErrorCode::InvalidLockDuration);
+            require!((1_000..=100_000).contains(apy_bps), ErrorCode::InvalidAPY);
+            // Ensure the new deposit fee is within the acceptable range (0% to 25% or 0-2500 BPS)
+            require!(*deposit_fee_bps <= 2_500, ErrorCode::InvalidDepositFee);
+        }
+        ConfigChange::OtcCommission(new_commission) => {
+            require!(new_commission.is_valid(), ErrorCode::InvalidOtcCommission);
+        }
+        ConfigChange::Treasury(_) | ConfigChange::RewardMode(_) => {}
+        ConfigChange::EmissionsBudget { epoch_duration, .. } => {
+            require!(*epoch_duration >= 0, ErrorCode::InvalidEmissionsBudget);
+        }
+        ConfigChange::ReferralRanks(new_ranks) => {
+            require!(
+                new_ranks.rank_count as usize <= MAX_REFERRAL_RANKS
+                    && ReferralRanks::is_valid(new_ranks.active()),
+                ErrorCode::InvalidReferralRanks
+            );
+        }
+    }
 
-    msg!("Level percentages updated to {:?}", new_percentages);
     Ok(())
 }
-pub fn update_deposit_fee(ctx: Context<UpdateGlobalConfig>, new_fee_bps: u16) -> Result<()> {
-    let global_config = &mut ctx.accounts.global_config;
 
-    // Ensure the new deposit fee is within the acceptable range (0% to 25% or 0-2500 BPS)
-    require!(new_fee_bps <= 2_500, ErrorCode::InvalidDepositFee);
+/*
+ * authority is the key that approved the change, the proposer for timelocked changes.
+ * tier_registry is only needed for lock tier changes.
+ */
+pub fn apply_config_change(
+    global_config: &mut GlobalConfig,
+    tier_registry: Option<&mut TierRegistry>,
+    change: ConfigChange,
+    authority: Pubkey,
+) -> Result<()> {
+    validate_config_change(&change)?;
 
-    // Update the deposit fee in the global configuration
-    global_config.deposit_fee_bps = new_fee_bps;
+    let now = Clock::get()?.unix_timestamp;
+
+    match change {
+        ConfigChange::ReferralApy(new_apy) => {
+            let old_apy_bps = global_config.current_apy_bps;
+
+            // Append to APY history, rewards for earlier periods keep using the rates that were working then
+            global_config.apy_history.record(new_apy, now)?;
+
+            // Update the current APY in the global configuration
+            global_config.current_apy_bps = new_apy;
+
+            emit!(ReferralApyUpdated {
+                old_apy_bps,
+                new_apy_bps: new_apy,
+                authority,
+                timestamp: now,
+            });
+
+            msg!("Referral APY updated to {} BPS", new_apy);
+        }
+        ConfigChange::WithdrawalFee(new_fee_bps) => {
+            emit!(FeesUpdated {
+                kind: FeeKind::Withdrawal,
+                old_fee_bps: global_config.withdrawal_fee_bps,
+                new_fee_bps,
+                authority,
+                timestamp: now,
+            });
+
+            global_config.withdrawal_fee_bps = new_fee_bps;
+
+            msg!("Withdrawal fee updated to {} BPS", new_fee_bps);
+        }
+        ConfigChange::LvlPercentages(new_percentages) => {
+            emit!(LevelPercentagesUpdated {
+                old_percentages: global_config.lvl_percentages,
+                new_percentages,
+                authority,
+                timestamp: now,
+            });
+
+            global_config.lvl_percentages = new_percentages;
+
+            msg!("Level percentages updated to {:?}", new_percentages);
+        }
+        ConfigChange::CompoundFee(new_fee_bps) => {
+            emit!(FeesUpdated {
+                kind: FeeKind::Compound,
+                old_fee_bps: global_config.compound_fee_bps,
+                new_fee_bps,
+                authority,
+                timestamp: now,
+            });
+
+            global_config.compound_fee_bps = new_fee_bps;
+
+            msg!("Compound fee updated to {} BPS", new_fee_bps);
+        }
+        ConfigChange::TimelockDelay(new_delay) => {
+            emit!(TimelockDelayUpdated {
+                old_delay: global_config.min_timelock_delay,
+                new_delay,
+                authority,
+                timestamp: now,
+            });
+
+            global_config.min_timelock_delay = new_delay;
+
+            msg!("Timelock delay updated to {} seconds", new_delay);
+        }
+        ConfigChange::AccrualMode(continuous_accrual) => {
+            global_config.continuous_accrual = continuous_accrual;
+
+            emit!(AccrualModeUpdated {
+                continuous_accrual,
+                authority,
+                timestamp: now,
+            });
+
+            msg!("Continuous accrual set to {}", continuous_accrual);
+        }
+        ConfigChange::EarlyExitPenalty(new_penalty) => {
+            emit!(EarlyExitPenaltyUpdated {
+                old_penalty: global_config.early_exit_penalty,
+                new_penalty,
+                authority,
+                timestamp: now,
+            });
+
+            global_config.early_exit_penalty = new_penalty;
+
+            msg!("Early exit penalty updated to {:?}", new_penalty);
+        }
+        ConfigChange::LockTier {
+            tier_id,
+            duration,
+            apy_bps,
+            deposit_fee_bps,
+            active,
+        } => {
+            let tier_registry = tier_registry.ok_or(ErrorCode::TierRegistryRequired)?;
+
+            apply_lock_tier(
+                tier_registry,
+                tier_id,
+                duration,
+                apy_bps,
+                deposit_fee_bps,
+                active,
+                now,
+            )?;
+
+            emit!(LockTierUpdated {
+                tier_id,
+                duration,
+                apy_bps,
+                deposit_fee_bps,
+                active,
+                authority,
+                timestamp: now,
+            });
+        }
+        ConfigChange::OtcCommission(new_commission) => {
+            emit!(OtcCommissionUpdated {
+                old_commission: global_config.otc_commission,
+                new_commission,
+                authority,
+                timestamp: now,
+            });
+
+            global_config.otc_commission = new_commission;
+
+            msg!("OTC commission updated to {:?}", new_commission);
+        }
+        ConfigChange::Treasury(new_treasury) => {
+            emit!(TreasuryUpdated {
+                old_treasury: global_config.treasury,
+                new_treasury,
+                authority,
+                timestamp: now,
+            });
+
+            global_config.treasury = new_treasury;
+
+            msg!("Treasury updated to {}", new_treasury);
+        }
+        // The reward vault has to be funded before switching, claims fail once it runs dry
+        ConfigChange::RewardMode(vault_rewards) => {
+            global_config.vault_rewards = vault_rewards;
+
+            emit!(RewardModeUpdated {
+                vault_rewards,
+                authority,
+                timestamp: now,
+            });
+
+            msg!("Vault rewards set to {}", vault_rewards);
+        }
+        // Changing the epoch length starts a new epoch, changing only the caps keeps what was emitted so far
+        ConfigChange::EmissionsBudget {
+            epoch_duration,
+            reward_cap,
+            admin_mint_cap,
+        } => {
+            let emissions = &mut global_config.emissions;
+
+            if emissions.epoch_duration != epoch_duration {
+                emissions.epoch_duration = epoch_duration;
+                emissions.epoch_start = now;
+                emissions.rewards_emitted = 0;
+                emissions.admin_minted = 0;
+            } else {
+                emissions.roll(now);
+            }
+
+            emissions.reward_cap = reward_cap;
+            emissions.admin_mint_cap = admin_mint_cap;
+
+            emit!(EmissionsBudgetUpdated {
+                epoch_duration,
+                reward_cap,
+                admin_mint_cap,
+                epoch_start: emissions.epoch_start,
+                authority,
+            });
+
+            msg!(
+                "Emissions budget updated: epoch {} seconds, reward cap {}, admin mint cap {}",
+                epoch_duration,
+                reward_cap,
+                admin_mint_cap
+            );
+        }
+        // Ranks apply from the next credit, commission already credited to a level stays until it is unwound
+        ConfigChange::ReferralRanks(new_ranks) => {
+            global_config.referral_ranks = new_ranks;
+
+            emit!(ReferralRanksUpdated {
+                ranks: new_ranks.active().to_vec(),
+                authority,
+                timestamp: now,
+            });
+
+            msg!("Referral ranks updated to {:?}", new_ranks.active());
+        }
+    }
 
-    msg!("Deposit fee updated to {} BPS", new_fee_bps);
     Ok(())
 }
 
 pub fn update_treasury(ctx: Context<UpdateGlobalConfig>, new_treasury: Pubkey) -> Result<()> {
+    let change = ConfigChange::Treasury(new_treasury);
+
+    ctx.accounts.authorize(ProposalAction::UpdateConfig { change })?;
+
     let global_config = &mut ctx.accounts.global_config;
 
-    global_config.treasury = new_treasury;
+    require!(global_config.min_timelock_delay == 0, ErrorCode::TimelockRequired);
 
-    msg!("Treasury updated to {}", new_treasury);
-    Ok(())
+    apply_config_change(global_config, None, change, ctx.accounts.authority.key())
+}
+
+pub fn update_accrual_mode(ctx: Context<UpdateGlobalConfig>, continuous_accrual: bool) -> Result<()> {
+    let change = ConfigChange::AccrualMode(continuous_accrual);
+
+    ctx.accounts.authorize(ProposalAction::UpdateConfig { change })?;
+
+    let global_config = &mut ctx.accounts.global_config;
+
+    require!(global_config.min_timelock_delay == 0, ErrorCode::TimelockRequired);
+
+    apply_config_change(global_config, None, change, ctx.accounts.authority.key())
 }
 
+pub fn update_reward_mode(ctx: Context<UpdateGlobalConfig>, vault_rewards: bool) -> Result<()> {
+    let change = ConfigChange::RewardMode(vault_rewards);
+
+    ctx.accounts.authorize(ProposalAction::UpdateConfig { change })?;
+
+    let global_config = &mut ctx.accounts.global_config;
+
+    require!(global_config.min_timelock_delay == 0, ErrorCode::TimelockRequired);
+
+    apply_config_change(global_config, None, change, ctx.accounts.authority.key())
+}
+
+pub fn update_early_exit_penalty(
+    ctx: Context<UpdateGlobalConfig>,
+    new_penalty: EarlyExitPenalty,
+) -> Result<()> {
+    let change = ConfigChange::EarlyExitPenalty(new_penalty);
+
+    ctx.accounts.authorize(ProposalAction::UpdateConfig { change })?;
+
+    let global_config = &mut ctx.accounts.global_config;
+
+    require!(global_config.min_timelock_delay == 0, ErrorCode::TimelockRequired);
+
+    apply_config_change(global_config, None, change, ctx.accounts.authority.key())
+}
+
+pub fn update_emissions_budget(
+    ctx: Context<UpdateGlobalConfig>,
+    epoch_duration: i64,
+    reward_cap: u64,
+    admin_mint_cap: u64,
+) -> Result<()> {
+    let change = ConfigChange::EmissionsBudget {
+        epoch_duration,
+        reward_cap,
+        admin_mint_cap,
+    };
+
+    ctx.accounts.authorize(ProposalAction::UpdateConfig { change })?;
+
+    let global_config = &mut ctx.accounts.global_config;
+
+    require!(global_config.min_timelock_delay == 0, ErrorCode::TimelockRequired);
+
+    apply_config_change(global_config, None, change, ctx.accounts.authority.key())
+}
+
+pub fn set_referral_ranks(ctx: Context<UpdateGlobalConfig>, ranks: Vec<ReferralRank>) -> Result<()> {
+    require!(ReferralRanks::is_valid(&ranks), ErrorCode::InvalidReferralRanks);
+
+    let mut new_ranks = ReferralRanks::default();
+    new_ranks.set(&ranks);
+
+    let change = ConfigChange::ReferralRanks(new_ranks);
+
+    ctx.accounts.authorize(ProposalAction::UpdateConfig { change })?;
+
+    let global_config = &mut ctx.accounts.global_config;
+
+    require!(global_config.min_timelock_delay == 0, ErrorCode::TimelockRequired);
+
+    apply_config_change(global_config, None, change, ctx.accounts.authority.key())
+}
+
+pub fn update_otc_commission(
+    ctx: Context<UpdateGlobalConfig>,
+    new_commission: OtcCommission,
+) -> Result<()> {
+    let change = ConfigChange::OtcCommission(new_commission);
+
+    ctx.accounts.authorize(ProposalAction::UpdateConfig { change })?;
+
+    let global_config = &mut ctx.accounts.global_config;
+
+    require!(global_config.min_timelock_delay == 0, ErrorCode::TimelockRequired);
+
+    apply_config_change(global_config, None, change, ctx.accounts.authority.key())
+}
+
+// Config updates are privileged like tier changes, a proposal may stand in for the role
 #[derive(Accounts)]
 pub struct UpdateGlobalConfig<'info> {
     #[account(
         mut,
-        has_one = admin,
         seeds = [b"config"],
         bump = global_config.config_bumps
     )]
     pub global_config: Account<'info, GlobalConfig>,
 
-    pub admin: Signer<'info>,
+    #[account(
+        seeds = [b"roles"],
+        bump = role_registry.bump
+    )]
+    pub role_registry: Account<'info, RoleRegistry>,
+
+    #[account(
+        seeds = [b"multisig"],
+        bump = multisig.bump
+    )]
+    pub multisig: Option<Account<'info, Multisig>>,
+
+    // Approved proposal, replaces the role check of the signer
+    #[account(
+        mut,
+        seeds = [b"proposal", proposal.id.to_le_bytes().as_ref()],
+        bump = proposal.bump
+    )]
+    pub proposal: Option<Account<'info, Proposal>>,
+
+    pub authority: Signer<'info>,
+}
+
+impl<'info> UpdateGlobalConfig<'info> {
+    fn authorize(&mut self, action: ProposalAction) -> Result<()> {
+        authorize_privileged(
+            &self.global_config,
+            &self.role_registry,
+            &self.authority.key(),
+            RoleRegistry::CONFIG_MANAGER,
+            self.multisig.as_deref(),
+            self.proposal.as_deref_mut(),
+            action,
+        )
+    }
 }
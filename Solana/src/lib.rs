@@ -2,6 +2,7 @@ use anchor_lang::prelude::*;
 
 pub mod constants;
 pub mod errors;
+pub mod events;
 pub mod instructions;
 pub mod state;
 use instructions::*;
@@ -12,15 +13,19 @@ pub mod staking {
 
     use super::*;
 
-    pub fn set_referrals_info(
-        ctx: Context<SetReferralsInfo>,
-        new_total_referral_staked: u64,
-        new_daily_referral_rewards: u64,
+    pub fn adjust_referral_info(
+        ctx: Context<AdjustReferralInfo>,
+        total_referral_staked_delta: i64,
+        daily_referral_rewards_delta: i64,
+        reason: u8,
+        memo_hash: [u8; 32],
     ) -> Result<()> {
-        instructions::set_referrals_info::set_referrals_info(
+        instructions::adjust_referral_info::adjust_referral_info(
             ctx,
-            new_total_referral_staked,
-            new_daily_referral_rewards,
+            total_referral_staked_delta,
+            daily_referral_rewards_delta,
+            reason,
+            memo_hash,
         )
     }
 
@@ -32,6 +37,42 @@ pub mod staking {
         instructions::ownable::accept_ownership(ctx)
     }
 
+    pub fn initialize_roles(ctx: Context<InitializeRoles>) -> Result<()> {
+        instructions::ownable::initialize_roles(ctx)
+    }
+
+    pub fn grant_roles(ctx: Context<ManageRoles>, member: Pubkey, roles: u8) -> Result<()> {
+        instructions::ownable::grant_roles(ctx, member, roles)
+    }
+
+    pub fn revoke_roles(ctx: Context<ManageRoles>, member: Pubkey, roles: u8) -> Result<()> {
+        instructions::ownable::revoke_roles(ctx, member, roles)
+    }
+
+    pub fn accept_roles(ctx: Context<AcceptRoles>) -> Result<()> {
+        instructions::ownable::accept_roles(ctx)
+    }
+
+    pub fn initialize_multisig(
+        ctx: Context<InitializeMultisig>,
+        signers: Vec<Pubkey>,
+        threshold: u8,
+    ) -> Result<()> {
+        instructions::multisig::initialize_multisig(ctx, signers, threshold)
+    }
+
+    pub fn create_proposal(ctx: Context<CreateProposal>, action: state::ProposalAction) -> Result<()> {
+        instructions::multisig::create_proposal(ctx, action)
+    }
+
+    pub fn approve_proposal(ctx: Context<ApproveProposal>) -> Result<()> {
+        instructions::multisig::approve_proposal(ctx)
+    }
+
+    pub fn update_multisig(ctx: Context<UpdateMultisig>) -> Result<()> {
+        instructions::multisig::update_multisig(ctx)
+    }
+
     pub fn initialize(ctx: Context<Initialize>, referrer: Pubkey) -> Result<()> {
         instructions::initialize_stake::handler(ctx, referrer)
     }
@@ -56,18 +97,14 @@ pub mod staking {
         )
     }
 
-    pub fn update_apy(ctx: Context<UpdateGlobalConfig>, new_apy: u32) -> Result<()> {
-        instructions::update_config::update_apy(ctx, new_apy)
+    pub fn update_referral_apy(ctx: Context<UpdateGlobalConfig>, new_apy: u32) -> Result<()> {
+        instructions::update_config::update_referral_apy(ctx, new_apy)
     }
 
     pub fn update_withdrawal_fee(ctx: Context<UpdateGlobalConfig>, new_fee_bps: u16) -> Result<()> {
         instructions::update_config::update_withdrawal_fee(ctx, new_fee_bps)
     }
 
-    pub fn update_deposit_fee(ctx: Context<UpdateGlobalConfig>, new_fee_bps: u16) -> Result<()> {
-        instructions::update_config::update_deposit_fee(ctx, new_fee_bps)
-    }
-
     pub fn update_treasury(ctx: Context<UpdateGlobalConfig>, new_treasury: Pubkey) -> Result<()> {
         instructions::update_config::update_treasury(ctx, new_treasury)
     }
@@ -79,22 +116,160 @@ pub mod staking {
         instructions::update_config::update_lvl_percentages(ctx, new_percentages)
     }
 
-    pub fn stake<'a>(ctx: Context<'_, '_, 'a, 'a, Stake<'a>>, amount: u64) -> Result<()> {
-        instructions::stake::handler(ctx, amount)
+    pub fn update_compound_fee(ctx: Context<UpdateGlobalConfig>, new_fee_bps: u16) -> Result<()> {
+        instructions::update_config::update_compound_fee(ctx, new_fee_bps)
+    }
+
+    pub fn update_timelock_delay(ctx: Context<UpdateGlobalConfig>, new_delay: i64) -> Result<()> {
+        instructions::update_config::update_timelock_delay(ctx, new_delay)
+    }
+
+    pub fn queue_config_change(
+        ctx: Context<QueueConfigChange>,
+        change: state::ConfigChange,
+        effective_at: i64,
+    ) -> Result<()> {
+        instructions::config_timelock::queue_config_change(ctx, change, effective_at)
+    }
+
+    pub fn execute_config_change(ctx: Context<ExecuteConfigChange>) -> Result<()> {
+        instructions::config_timelock::execute_config_change(ctx)
+    }
+
+    pub fn cancel_config_change(ctx: Context<CancelConfigChange>) -> Result<()> {
+        instructions::config_timelock::cancel_config_change(ctx)
+    }
+
+    pub fn set_pause(ctx: Context<SetPause>, paused_flags: u8) -> Result<()> {
+        instructions::pause::set_pause(ctx, paused_flags)
+    }
+
+    pub fn update_accrual_mode(
+        ctx: Context<UpdateGlobalConfig>,
+        continuous_accrual: bool,
+    ) -> Result<()> {
+        instructions::update_config::update_accrual_mode(ctx, continuous_accrual)
+    }
+
+    pub fn update_reward_mode(ctx: Context<UpdateGlobalConfig>, vault_rewards: bool) -> Result<()> {
+        instructions::update_config::update_reward_mode(ctx, vault_rewards)
+    }
+
+    pub fn update_early_exit_penalty(
+        ctx: Context<UpdateGlobalConfig>,
+        new_penalty: state::EarlyExitPenalty,
+    ) -> Result<()> {
+        instructions::update_config::update_early_exit_penalty(ctx, new_penalty)
+    }
+
+    pub fn update_emissions_budget(
+        ctx: Context<UpdateGlobalConfig>,
+        epoch_duration: i64,
+        reward_cap: u64,
+        admin_mint_cap: u64,
+    ) -> Result<()> {
+        instructions::update_config::update_emissions_budget(
+            ctx,
+            epoch_duration,
+            reward_cap,
+            admin_mint_cap,
+        )
+    }
+
+    pub fn set_referral_ranks(
+        ctx: Context<UpdateGlobalConfig>,
+        ranks: Vec<state::ReferralRank>,
+    ) -> Result<()> {
+        instructions::update_config::set_referral_ranks(ctx, ranks)
+    }
+
+    pub fn update_otc_commission(
+        ctx: Context<UpdateGlobalConfig>,
+        new_commission: state::OtcCommission,
+    ) -> Result<()> {
+        instructions::update_config::update_otc_commission(ctx, new_commission)
+    }
+
+    pub fn add_to_blacklist(
+        ctx: Context<AddToBlacklist>,
+        address: Pubkey,
+        reason: u8,
+    ) -> Result<()> {
+        instructions::blacklist::add_to_blacklist(ctx, address, reason)
+    }
+
+    pub fn update_blacklist_reason(ctx: Context<UpdateBlacklist>, reason: u8) -> Result<()> {
+        instructions::blacklist::update_blacklist_reason(ctx, reason)
+    }
+
+    pub fn remove_from_blacklist(ctx: Context<RemoveFromBlacklist>) -> Result<()> {
+        instructions::blacklist::remove_from_blacklist(ctx)
+    }
+
+    pub fn initialize_tier_registry(ctx: Context<InitializeTierRegistry>) -> Result<()> {
+        instructions::lock_tiers::initialize_tier_registry(ctx)
+    }
+
+    pub fn set_lock_tier(
+        ctx: Context<UpdateTierRegistry>,
+        tier_id: u8,
+        duration: i64,
+        apy: u32,
+        deposit_fee_bps: u16,
+        active: bool,
+    ) -> Result<()> {
+        instructions::lock_tiers::set_lock_tier(ctx, tier_id, duration, apy, deposit_fee_bps, active)
+    }
+
+    pub fn stake<'a>(
+        ctx: Context<'_, '_, 'a, 'a, Stake<'a>>,
+        amount: u64,
+        tier_id: u8,
+    ) -> Result<()> {
+        instructions::stake::handler(ctx, amount, tier_id)
+    }
+
+    pub fn increase_stake<'a>(
+        ctx: Context<'_, '_, 'a, 'a, IncreaseStake<'a>>,
+        amount: u64,
+    ) -> Result<()> {
+        instructions::increase_stake::increase_stake(ctx, amount)
+    }
+
+    pub fn merge_positions<'a>(ctx: Context<'_, '_, 'a, 'a, MergePositions<'a>>) -> Result<()> {
+        instructions::merge_positions::merge_positions(ctx)
+    }
+
+    pub fn split_position(ctx: Context<SplitPosition>, amount: u64) -> Result<()> {
+        instructions::split_position::split_position(ctx, amount)
+    }
+
+    pub fn tokenize_position(ctx: Context<TokenizePosition>) -> Result<()> {
+        instructions::tokenize_position::tokenize_position(ctx)
     }
 
     pub fn stake_airdrop<'a>(
         ctx: Context<'_, '_, 'a, 'a, Stake<'a>>,
         amount: u64,
+        tier_id: u8,
     ) -> Result<()> {
-        instructions::stake::stake_airdrop(ctx, amount)
+        instructions::stake::stake_airdrop(ctx, amount, tier_id)
     }
 
     pub fn stake_by_admin<'a>(
         ctx: Context<'_, '_, 'a, 'a, Stake<'a>>,
         amount: u64,
+        tier_id: u8,
     ) -> Result<()> {
-        instructions::stake::stake_by_admin(ctx, amount)
+        instructions::stake::stake_by_admin(ctx, amount, tier_id)
+    }
+
+    pub fn unstake(ctx: Context<Unstake>) -> Result<()> {
+        instructions::unstake::handler(ctx)
+    }
+
+    pub fn early_unstake(ctx: Context<Unstake>) -> Result<()> {
+        instructions::unstake::early_unstake(ctx)
     }
 
     pub fn claim_rewards<'a>(ctx: Context<'_, '_, '_, 'a, ClaimRewards<'a>>) -> Result<()> {
@@ -105,6 +280,21 @@ pub mod staking {
         instructions::claim_all_rewards::handler(ctx)
     }
 
+    pub fn compound_rewards<'a>(
+        ctx: Context<'_, '_, 'a, 'a, CompoundRewards<'a>>,
+        tier_id: u8,
+    ) -> Result<()> {
+        instructions::compound_rewards::compound_rewards(ctx, tier_id)
+    }
+
+    pub fn compound_all_rewards<'a>(
+        ctx: Context<'_, '_, 'a, 'a, CompoundAllRewards<'a>>,
+        tier_id: u8,
+        position_count: u8,
+    ) -> Result<()> {
+        instructions::compound_rewards::compound_all_rewards(ctx, tier_id, position_count)
+    }
+
     pub fn claim_referral_rewards(ctx: Context<ClaimReferralRewards>) -> Result<()> {
         instructions::claim_referral_rewards::handler(ctx)
     }
@@ -113,6 +303,10 @@ pub mod staking {
         instructions::get_referral_rewards::calculate_referral_rewards(ctx)
     }
 
+    pub fn get_referral_rank(ctx: Context<GetReferralRank>) -> Result<ReferralRankInfo> {
+        instructions::get_referral_rank::get_referral_rank(ctx)
+    }
+
     pub fn get_rewards_by_user(ctx: Context<GetRewardsByUser>) -> Result<u64> {
         instructions::get_rewards_by_user::calculate_rewards(ctx)
     }
@@ -125,6 +319,18 @@ pub mod staking {
         instructions::get_current_rewards_by_user::calculate_current_rewards(ctx)
     }
 
+    pub fn get_emissions_budget(ctx: Context<GetEmissionsBudget>) -> Result<EmissionsBudgetInfo> {
+        instructions::get_emissions_budget::get_emissions_budget(ctx)
+    }
+
+    pub fn fund_rewards(ctx: Context<FundRewards>, amount: u64) -> Result<()> {
+        instructions::reward_vault::fund_rewards(ctx, amount)
+    }
+
+    pub fn get_reward_runway(ctx: Context<GetRewardRunway>) -> Result<RewardRunway> {
+        instructions::reward_vault::get_reward_runway(ctx)
+    }
+
     pub fn remove_freeze_authority(ctx: Context<RevokeFreezeAuth>) -> Result<()> {
         instructions::remove_freeze_authority::revoke_freeze_auth(ctx)
     }
@@ -133,11 +339,67 @@ pub mod staking {
         instructions::add_referrer::add_referrer(ctx, new_referrer)
     }
 
+    pub fn sync_upline(ctx: Context<SyncUpline>) -> Result<()> {
+        instructions::add_referrer::sync_upline(ctx)
+    }
+
+    pub fn rebuild_upline(ctx: Context<RebuildUpline>) -> Result<()> {
+        instructions::add_referrer::rebuild_upline(ctx)
+    }
+
+    pub fn unwind_referral_credit(ctx: Context<UnwindReferralCredit>) -> Result<()> {
+        instructions::unwind_referral_credit::unwind_referral_credit(ctx)
+    }
+
     pub fn otc_buy<'a>(
         ctx: Context<'_, '_, 'a, 'a, OtcBuy<'a>>,
         sol_amount: u64,
-        toon_amount: u64,
+        max_price: u64,
+        tier_id: u8,
+    ) -> Result<()> {
+        instructions::otc_buy::handler(ctx, sol_amount, max_price, tier_id)
+    }
+
+    pub fn initialize_otc_desk(ctx: Context<InitializeOtcDesk>) -> Result<()> {
+        instructions::otc_desk::initialize_otc_desk(ctx)
+    }
+
+    pub fn update_otc_desk(
+        ctx: Context<UpdateOtcDesk>,
+        new_settings: state::OtcDeskSettings,
     ) -> Result<()> {
-        instructions::otc_buy::handler(ctx, sol_amount, toon_amount)
+        instructions::otc_desk::update_otc_desk(ctx, new_settings)
+    }
+
+    pub fn push_otc_price(ctx: Context<PushOtcPrice>, price: u64) -> Result<()> {
+        instructions::otc_desk::push_otc_price(ctx, price)
+    }
+
+    pub fn get_otc_quote(ctx: Context<GetOtcQuote>, payment: u64, user: Pubkey) -> Result<OtcQuote> {
+        instructions::otc_desk::get_otc_quote(ctx, payment, user)
+    }
+
+    pub fn migrate_global_config(ctx: Context<MigrateGlobalConfig>) -> Result<()> {
+        instructions::migrate_accounts::migrate_global_config(ctx)
+    }
+
+    pub fn migrate_staking_account(ctx: Context<MigrateStakingAccount>) -> Result<()> {
+        instructions::migrate_accounts::migrate_staking_account(ctx)
+    }
+
+    pub fn migrate_user_staked_account(ctx: Context<MigrateUserStakedAccount>) -> Result<()> {
+        instructions::migrate_accounts::migrate_user_staked_account(ctx)
+    }
+
+    pub fn backfill_referral_entry(ctx: Context<BackfillReferralEntry>) -> Result<()> {
+        instructions::migrate_accounts::backfill_referral_entry(ctx)
+    }
+
+    pub fn checkpoint_position(ctx: Context<CheckpointPosition>) -> Result<()> {
+        instructions::checkpoint_rewards::checkpoint_position(ctx)
+    }
+
+    pub fn checkpoint_referral_rewards(ctx: Context<CheckpointReferralRewards>) -> Result<()> {
+        instructions::checkpoint_rewards::checkpoint_referral_rewards(ctx)
     }
 }
@@ -0,0 +1,434 @@
+use anchor_lang::prelude::*;
+
+use crate::{constants::constants, errors::ErrorCode, state::ApyChange};
+
+pub const APY_HISTORY_LEN: usize = 16;
+pub const REWARD_PER_TOKEN_PRECISION: u128 = 1_000_000_000_000;
+
+/*
+ * Append-only APY ledger kept as a ring buffer:
+ * entries - APY values with the timestamp they started working, oldest at (head - len)
+ * pins - reward checkpoints per slot that still rely on the change in it
+ * head - slot for the next change
+ * len - number of recorded changes, capped by APY_HISTORY_LEN
+ *
+ * A checkpoint at time t pins the latest change before t, rewards from t on are computed
+ * from that change and every later one. Once the buffer is full the oldest change is dropped
+ * to make room, unless it is pinned, then new changes are rejected until the checkpoints
+ * relying on it move on.
+ *
+ * Every change also checkpoints the reward-per-token accumulator, so rewards accrued
+ * per second between any two moments are principal * (acc(end) - acc(start)).
+ * The accumulator counts from the unix epoch as if the first rate had always worked,
+ * so it stays positive for checkpoints before the oldest change.
+ */
+#[zero_copy]
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Default)]
+pub struct ApySchedule {
+    pub entries: [ApyChange; APY_HISTORY_LEN],
+    pub pins: [u32; APY_HISTORY_LEN],
+    pub head: u8,
+    pub len: u8,
+    pub _padding: [u8; 6],
+}
+
+impl ApySchedule {
+    pub fn new(apy_bps: u32, timestamp: i64) -> Self {
+        let mut schedule = Self::default();
+
+        schedule.entries[0] = ApyChange {
+            apy_bps,
+            timestamp,
+            reward_per_token: epoch_reward_per_token(apy_bps, timestamp).unwrap_or(u64::MAX),
+            ..ApyChange::default()
+        };
+        schedule.head = 1;
+        schedule.len = 1;
+
+        schedule
+    }
+
+    pub fn record(&mut self, apy_bps: u32, timestamp: i64) -> Result<()> {
+        // Several changes within the same second, only the last one ever worked
+        if self.len > 0 && self.get(self.len as usize - 1).timestamp == timestamp {
+            let last = self.slot(self.len as usize - 1);
+            self.entries[last].apy_bps = apy_bps;
+            return Ok(());
+        }
+
+        let reward_per_token = if self.len > 0 {
+            u64::try_from(self.reward_per_token_at(timestamp)?).map_err(|_| ErrorCode::Overflow)?
+        } else {
+            epoch_reward_per_token(apy_bps, timestamp)?
+        };
+
+        // Once the buffer is full head is the slot of the oldest change
+        if self.len as usize == APY_HISTORY_LEN {
+            require!(self.pins[self.head as usize] == 0, ErrorCode::ApyScheduleFull);
+        }
+
+        self.entries[self.head as usize] = ApyChange {
+            apy_bps,
+            timestamp,
+            reward_per_token,
+            ..ApyChange::default()
+        };
+        self.head = ((self.head as usize + 1) % APY_HISTORY_LEN) as u8;
+
+        if (self.len as usize) < APY_HISTORY_LEN {
+            self.len += 1;
+        }
+
+        Ok(())
+    }
+
+    fn slot(&self, index: usize) -> usize {
+        (self.head as usize + APY_HISTORY_LEN - self.len as usize + index) % APY_HISTORY_LEN
+    }
+
+    // index 0 is the oldest recorded change
+    pub fn get(&self, index: usize) -> &ApyChange {
+        &self.entries[self.slot(index)]
+    }
+
+    /*
+     * Slot of the change a checkpoint at timestamp relies on: the latest change strictly before it,
+     * or the oldest one. Later changes are always recorded at or after the checkpoint, so the slot
+     * found for a checkpoint does not change while it is pinned.
+     */
+    fn pinned_slot(&self, timestamp: i64) -> Option<usize> {
+        if self.len == 0 {
+            return None;
+        }
+
+        let index = (0..self.len as usize)
+            .rev()
+            .find(|index| self.get(*index).timestamp < timestamp)
+            .unwrap_or(0);
+
+        Some(self.slot(index))
+    }
+
+    pub fn pin(&mut self, timestamp: i64) -> Result<()> {
+        if let Some(slot) = self.pinned_slot(timestamp) {
+            self.pins[slot] = self.pins[slot].checked_add(1).ok_or(ErrorCode::Overflow)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn unpin(&mut self, timestamp: i64) -> Result<()> {
+        if let Some(slot) = self.pinned_slot(timestamp) {
+            self.pins[slot] = self.pins[slot].checked_sub(1).ok_or(ErrorCode::Underflow)?;
+        }
+
+        Ok(())
+    }
+
+    // The history is full and a checkpoint at timestamp holds the oldest change, new changes are rejected
+    pub fn is_blocked_by(&self, timestamp: i64) -> bool {
+        self.len as usize == APY_HISTORY_LEN && self.pinned_slot(timestamp) == Some(self.head as usize)
+    }
+
+    // A checkpoint moving from one timestamp to another, None when there was or will be no checkpoint
+    pub fn move_pin(&mut self, from: Option<i64>, to: Option<i64>) -> Result<()> {
+        if let Some(to) = to {
+            self.pin(to)?;
+        }
+
+        if let Some(from) = from {
+            self.unpin(from)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn current_apy(&self) -> u32 {
+        if self.len == 0 {
+            return 0;
+        }
+
+        self.get(self.len as usize - 1).apy_bps
+    }
+
+    pub fn reward_per_token_at(&self, timestamp: i64) -> Result<u128> {
+        if self.len == 0 {
+            return Ok(0);
+        }
+
+        let mut index = self.len as usize - 1;
+
+        while index > 0 && self.get(index).timestamp > timestamp {
+            index -= 1;
+        }
+
+        let change = self.get(index);
+
+        let accrued = (change.apy_bps as u128)
+            .checked_mul((timestamp - change.timestamp).unsigned_abs() as u128)
+            .ok_or(ErrorCode::Overflow)?
+            .checked_mul(REWARD_PER_TOKEN_PRECISION)
+            .ok_or(ErrorCode::Overflow)?
+            .checked_div(365 * 24 * 60 * 60 * 10_000)
+            .ok_or(ErrorCode::Overflow)?;
+
+        /*
+         * Only possible before the oldest recorded change, its rate is used backwards as well.
+         * The epoch offset keeps this exact back to the epoch for the first change ever recorded,
+         * a dropped oldest change is never needed by a checkpoint, see pins.
+         */
+        if timestamp < change.timestamp {
+            return Ok((change.reward_per_token as u128).saturating_sub(accrued));
+        }
+
+        (change.reward_per_token as u128)
+            .checked_add(accrued)
+            .ok_or(ErrorCode::Overflow.into())
+    }
+
+    /*
+     * Rewards for `periods` whole claim periods, the first one starting at `start`.
+     * Every period is paid at the APY that was working when the period started,
+     * so rewards are integrated over every rate segment the periods cross.
+     */
+    pub fn accrue(&self, principal: u64, start: i64, periods: i64) -> Result<u128> {
+        let mut rewards: u128 = 0;
+
+        if periods <= 0 || self.len == 0 {
+            return Ok(rewards);
+        }
+
+        let count_periods_before = |time: i64| -> i64 {
+            if time <= start {
+                return 0;
+            }
+
+            ((time - start + constants::CLAIM_PERIOD_SECONDS - 1) / constants::CLAIM_PERIOD_SECONDS)
+                .min(periods)
+        };
+
+        let len = self.len as usize;
+
+        for index in 0..len {
+            let segment_start = if index == 0 {
+                start
+            } else {
+                self.get(index).timestamp
+            };
+
+            let segment_end_periods = if index + 1 == len {
+                periods
+            } else {
+                count_periods_before(self.get(index + 1).timestamp)
+            };
+
+            let interval = segment_end_periods - count_periods_before(segment_start);
+
+            if interval <= 0 {
+                continue;
+            }
+
+            let rewards_by_day = (principal as u128)
+                .checked_mul(self.get(index).apy_bps as u128)
+                .ok_or(ErrorCode::Overflow)?
+                .checked_mul(constants::CLAIM_PERIOD_SECONDS as u128)
+                .ok_or(ErrorCode::Overflow)?
+                .checked_div(365 * 24 * 60 * 60 * 10_000) // Adjust based on staking period
+                .ok_or(ErrorCode::Overflow)?;
+
+            rewards = rewards
+                .checked_add(
+                    rewards_by_day
+                        .checked_mul(interval as u128)
+                        .ok_or(ErrorCode::Overflow)?,
+                )
+                .ok_or(ErrorCode::Overflow)?;
+        }
+
+        Ok(rewards)
+    }
+}
+
+// Accumulator of a rate that worked from the unix epoch up to timestamp
+fn epoch_reward_per_token(apy_bps: u32, timestamp: i64) -> Result<u64> {
+    let accrued = (apy_bps as u128)
+        .checked_mul(timestamp.max(0) as u128)
+        .ok_or(ErrorCode::Overflow)?
+        .checked_mul(REWARD_PER_TOKEN_PRECISION)
+        .ok_or(ErrorCode::Overflow)?
+        .checked_div(365 * 24 * 60 * 60 * 10_000)
+        .ok_or(ErrorCode::Overflow)?;
+
+    Ok(u64::try_from(accrued).map_err(|_| ErrorCode::Overflow)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PERIOD: i64 = constants::CLAIM_PERIOD_SECONDS;
+    const START: i64 = 1_700_000_000;
+
+    // One change per claim period, every change raises the APY by 100 BPS
+    fn schedule_with_changes(changes: i64) -> ApySchedule {
+        let mut schedule = ApySchedule::new(1_000, START);
+
+        for period in 1..=changes {
+            schedule
+                .record(1_000 + 100 * period as u32, START + period * PERIOD)
+                .unwrap();
+        }
+
+        schedule
+    }
+
+    fn accrued_per_token(apy_bps: u32, seconds: i64) -> u128 {
+        apy_bps as u128 * seconds as u128 * REWARD_PER_TOKEN_PRECISION / (365 * 24 * 60 * 60 * 10_000)
+    }
+
+    #[test]
+    fn keeps_the_latest_changes_in_order() {
+        let schedule = schedule_with_changes(40);
+
+        assert_eq!(schedule.len as usize, APY_HISTORY_LEN);
+        assert_eq!(schedule.current_apy(), 1_000 + 100 * 40);
+
+        for index in 0..APY_HISTORY_LEN {
+            let period = (41 - APY_HISTORY_LEN + index) as i64;
+
+            assert_eq!(schedule.get(index).timestamp, START + period * PERIOD);
+            assert_eq!(schedule.get(index).apy_bps, 1_000 + 100 * period as u32);
+        }
+    }
+
+    #[test]
+    fn accumulator_stays_continuous_across_dropped_changes() {
+        let schedule = schedule_with_changes(40);
+
+        for index in 1..APY_HISTORY_LEN {
+            let previous = schedule.get(index - 1);
+            let change = schedule.get(index);
+
+            assert_eq!(
+                change.reward_per_token as u128,
+                previous.reward_per_token as u128 + accrued_per_token(previous.apy_bps, PERIOD)
+            );
+        }
+
+        let last = schedule.get(APY_HISTORY_LEN - 1);
+
+        assert_eq!(
+            schedule.reward_per_token_at(last.timestamp + 10).unwrap(),
+            last.reward_per_token as u128 + accrued_per_token(last.apy_bps, 10)
+        );
+    }
+
+    #[test]
+    fn accrue_pays_every_period_at_its_starting_rate() {
+        let schedule = schedule_with_changes(20);
+        let principal = 1_000_000_000_u64;
+
+        let expected: u128 = (10..15)
+            .map(|period| {
+                principal as u128 * (1_000 + 100 * period as u128) * PERIOD as u128
+                    / (365 * 24 * 60 * 60 * 10_000)
+            })
+            .sum();
+
+        assert_eq!(schedule.accrue(principal, START + 10 * PERIOD, 5).unwrap(), expected);
+    }
+
+    #[test]
+    fn checkpoint_before_the_first_change_accrues_at_its_rate() {
+        let schedule = schedule_with_changes(2);
+
+        let before = schedule.reward_per_token_at(START - 3 * PERIOD).unwrap();
+        let first = schedule.reward_per_token_at(START).unwrap();
+
+        assert!(before > 0);
+        assert_eq!(first - before, accrued_per_token(1_000, 3 * PERIOD));
+        assert_eq!(
+            schedule.reward_per_token_at(START + PERIOD + 10).unwrap() - before,
+            accrued_per_token(1_000, 3 * PERIOD)
+                + accrued_per_token(1_000, PERIOD)
+                + accrued_per_token(1_100, 10)
+        );
+    }
+
+    #[test]
+    fn same_second_change_replaces_the_last_one() {
+        let mut schedule = schedule_with_changes(2);
+
+        schedule.record(7_000, START + 2 * PERIOD).unwrap();
+
+        assert_eq!(schedule.len, 3);
+        assert_eq!(schedule.current_apy(), 7_000);
+    }
+
+    #[test]
+    fn pinned_change_is_never_dropped() {
+        let mut schedule = ApySchedule::new(1_000, START);
+
+        // Position opened right after the first change
+        schedule.pin(START + 1).unwrap();
+
+        for period in 1..APY_HISTORY_LEN as i64 {
+            schedule.record(1_000 + 100 * period as u32, START + period * PERIOD).unwrap();
+        }
+
+        assert!(schedule.record(9_000, START + 100 * PERIOD).is_err());
+        assert_eq!(schedule.get(0).timestamp, START);
+        assert_eq!(schedule.current_apy(), 1_000 + 100 * (APY_HISTORY_LEN as u32 - 1));
+
+        // The position claims, its checkpoint no longer relies on the oldest change
+        schedule.move_pin(Some(START + 1), Some(START + 50 * PERIOD)).unwrap();
+        schedule.record(9_000, START + 100 * PERIOD).unwrap();
+
+        assert_eq!(schedule.get(0).timestamp, START + PERIOD);
+        assert_eq!(schedule.current_apy(), 9_000);
+    }
+
+    #[test]
+    fn checkpoint_pins_the_change_before_it() {
+        let mut schedule = schedule_with_changes(2);
+
+        schedule.pin(START + 2 * PERIOD).unwrap();
+        assert_eq!(schedule.pins[schedule.slot(1)], 1);
+
+        // A change recorded later at the checkpoint timestamp does not move the pin
+        schedule.pin(START + 3 * PERIOD).unwrap();
+        schedule.record(9_000, START + 3 * PERIOD).unwrap();
+        assert_eq!(schedule.pins[schedule.slot(2)], 1);
+
+        schedule.unpin(START + 2 * PERIOD).unwrap();
+        schedule.unpin(START + 3 * PERIOD).unwrap();
+
+        assert!(schedule.pins.iter().all(|pins| *pins == 0));
+    }
+
+    #[test]
+    fn only_checkpoints_on_the_oldest_change_of_a_full_history_block_it() {
+        let schedule = schedule_with_changes(APY_HISTORY_LEN as i64 - 1);
+
+        assert!(schedule.is_blocked_by(START + 1));
+        assert!(schedule.is_blocked_by(START - PERIOD));
+        assert!(!schedule.is_blocked_by(START + PERIOD + 1));
+        assert!(!schedule_with_changes(3).is_blocked_by(START + 1));
+    }
+
+    #[test]
+    fn checkpoint_before_every_change_pins_the_oldest() {
+        let mut schedule = schedule_with_changes(3);
+
+        schedule.pin(START - PERIOD).unwrap();
+
+        assert_eq!(schedule.pins[schedule.slot(0)], 1);
+    }
+
+    #[test]
+    fn unpin_without_pin_fails() {
+        let mut schedule = schedule_with_changes(3);
+
+        assert!(schedule.unpin(START + PERIOD).is_err());
+    }
+}
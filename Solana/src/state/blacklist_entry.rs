@@ -0,0 +1,24 @@
+use anchor_lang::prelude::*;
+
+/*
+ * One PDA per blacklisted address, seeds = [b"blacklist", address].
+ * Instructions take the PDA of the acting address and require it to be empty,
+ * so the check is a single account lookup instead of a string comparison.
+ */
+#[account]
+#[derive(Debug)]
+pub struct BlacklistEntry {
+    pub address: Pubkey,                      // Blacklisted address
+    pub reason: u8,                           // Reason code, meaning is defined off-chain
+    pub added_by: Pubkey,                     // Admin who blacklisted the address
+    pub added_at: i64,                        // Timestamp the address was blacklisted at
+    pub bump: u8,
+}
+
+impl BlacklistEntry {
+    pub const LEN: usize = 8 + std::mem::size_of::<BlacklistEntry>();
+
+    pub fn is_blacklisted(entry: &AccountInfo) -> bool {
+        !entry.data_is_empty()
+    }
+}
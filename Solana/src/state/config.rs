@@ -0,0 +1,554 @@
+use anchor_lang::prelude::*;
+
+use crate::{
+    constants::constants,
+    errors::ErrorCode,
+    state::{ApySchedule, MAX_UPLINE_LEVELS},
+};
+
+#[account]
+#[derive(Debug)]
+pub struct GlobalConfig {
+    pub admin: Pubkey,                        // Admin of the program
+    pub pending_admin: Pubkey,                // Admin waiting to accept ownership
+    pub treasury: Pubkey,                     // Treasury wallet
+    pub mint: Pubkey,                         // Staking token mint
+    pub transfer_manager: Pubkey,             // PDA used as mint authority
+    pub current_apy_bps: u32,                 // Current APY in BPS paid on referral rewards
+    pub deposit_fee_bps: u16,                 // Deposit fee of the first layout, copied to the legacy tier
+    pub withdrawal_fee_bps: u16,              // Withdrawal fee in BPS
+    pub lvl_percentages: [u8; 10],            // Referral commission per level
+    pub total_staked: u64,                    // Total staked amount by all users
+    pub config_bumps: u8,
+    pub mint_bumps: u8,
+    pub transfer_manager_bumps: u8,
+    pub legacy_apy_history: Vec<LegacyApyChange>, // APY log of the first layout, emptied by migrate_global_config
+    pub early_exit_penalty: EarlyExitPenalty, // Penalty schedule for unstaking before maturity
+    pub min_timelock_delay: i64,              // Minimal delay between queueing and executing a config change
+    pub config_change_counter: u64,           // Counter to track PendingConfigChanges
+    pub continuous_accrual: bool,             // Rewards accrue every second instead of per claim period
+    pub compound_fee_bps: u16,                // Fee in BPS on compounded rewards, minted to treasury
+    pub paused_flags: u8,                     // Paused instruction groups, see GlobalConfig::PAUSE_*
+    pub multisig_required: bool,              // Mint and referral overrides only run through approved proposals
+    pub emissions: EmissionsBudget,           // Per-epoch caps on reward and admin minting
+    pub vault_rewards: bool,                  // Rewards are paid from the reward vault instead of being minted
+    pub referral_ranks: ReferralRanks,        // Ranks that unlock deeper referral levels
+    pub otc_commission: OtcCommission,        // Share of OTC payments sent to the upline wallets
+    pub apy_history: ApySchedule,             // Every APY value with the time it started working
+}
+
+// Zero-copy so lock tiers can be read in place, the accumulator fits into u64 for any valid APY
+#[zero_copy]
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Default)]
+pub struct ApyChange {
+    pub apy_bps: u32,
+    pub _padding: [u8; 4],
+    pub timestamp: i64,
+    pub reward_per_token: u64,                // Rewards accumulated per token up to timestamp, scaled by REWARD_PER_TOKEN_PRECISION
+}
+
+// Entry of the APY log kept by the first config layout, before the accumulator was checkpointed
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default)]
+pub struct LegacyApyChange {
+    pub apy_bps: u32,
+    pub timestamp: i64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct EarlyExitPenalty {
+    pub max_penalty_bps: u16,   // Penalty charged right after staking
+    pub stepwise: bool,         // Decrease in equal steps instead of linearly
+    pub step_count: u8,         // Number of steps over the lock period
+    pub burn: bool,             // Burn the penalty instead of sending it to treasury
+}
+
+impl EarlyExitPenalty {
+    pub const MAX_PENALTY_BPS: u16 = 5_000;
+
+    pub fn is_valid(&self) -> bool {
+        self.max_penalty_bps <= Self::MAX_PENALTY_BPS && (!self.stepwise || self.step_count > 0)
+    }
+
+    /*
+     * Penalty shrinks toward zero as the position approaches maturity:
+     * linear - proportional to the remaining lock time
+     * stepwise - the lock is split into step_count equal steps, every finished step lowers the penalty
+     */
+    pub fn penalty_bps(&self, elapsed: i64, lock_duration: i64) -> u16 {
+        if lock_duration <= 0 || elapsed >= lock_duration {
+            return 0;
+        }
+
+        let remaining = (lock_duration - elapsed.max(0)) as u128;
+        let duration = lock_duration as u128;
+        let max_penalty = self.max_penalty_bps as u128;
+
+        let penalty = if self.stepwise {
+            let steps = self.step_count as u128;
+            let step_length = (duration / steps).max(1);
+            let steps_left = ((remaining + step_length - 1) / step_length).min(steps);
+
+            max_penalty * steps_left / steps
+        } else {
+            max_penalty * remaining / duration
+        };
+
+        penalty as u16
+    }
+}
+
+/*
+ * Minting caps per epoch, epochs are epoch_duration long and start at epoch_start.
+ * Rewards above the cap are not lost, the claim pays what fits and defers the rest.
+ * A zero epoch_duration disables both caps.
+ */
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default)]
+pub struct EmissionsBudget {
+    pub epoch_duration: i64,        // Length of an epoch in seconds
+    pub epoch_start: i64,           // Start of the current epoch
+    pub reward_cap: u64,            // Rewards that may be minted per epoch
+    pub admin_mint_cap: u64,        // Tokens the admin may mint per epoch
+    pub rewards_emitted: u64,       // Rewards minted in the current epoch
+    pub admin_minted: u64,          // Tokens minted by the admin in the current epoch
+    pub total_rewards_emitted: u64, // Rewards minted since the budget was introduced
+}
+
+impl EmissionsBudget {
+    pub fn is_enabled(&self) -> bool {
+        self.epoch_duration > 0
+    }
+
+    pub fn epoch_end(&self) -> i64 {
+        self.epoch_start.saturating_add(self.epoch_duration)
+    }
+
+    // Starts a new epoch once the current one is over, epochs stay aligned to epoch_start
+    pub fn roll(&mut self, now: i64) {
+        if !self.is_enabled() || now < self.epoch_end() {
+            return;
+        }
+
+        self.epoch_start = now - (now - self.epoch_start) % self.epoch_duration;
+        self.rewards_emitted = 0;
+        self.admin_minted = 0;
+    }
+
+    pub fn remaining_rewards(&self, now: i64) -> u64 {
+        if !self.is_enabled() {
+            return u64::MAX;
+        }
+
+        if now >= self.epoch_end() {
+            return self.reward_cap;
+        }
+
+        self.reward_cap.saturating_sub(self.rewards_emitted)
+    }
+
+    pub fn remaining_admin_mint(&self, now: i64) -> u64 {
+        if !self.is_enabled() {
+            return u64::MAX;
+        }
+
+        if now >= self.epoch_end() {
+            return self.admin_mint_cap;
+        }
+
+        self.admin_mint_cap.saturating_sub(self.admin_minted)
+    }
+}
+
+pub const MAX_REFERRAL_RANKS: usize = 8;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ReferralRank {
+    pub min_personal_stake: u64,    // total_staked of the referrer
+    pub min_team_volume: u64,       // total_referral_staked of the referrer
+    pub unlocked_levels: u8,        // Levels paid to the referrer, counted from level 1
+}
+
+/*
+ * Referral ranks ordered from the lowest up, a referrer holds the highest rank whose
+ * thresholds it meets and is only paid commission on the levels that rank unlocks.
+ * Without ranks every level is unlocked for everyone.
+ */
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ReferralRanks {
+    pub rank_count: u8,                                 // Number of ranks in use
+    pub ranks: [ReferralRank; MAX_REFERRAL_RANKS],
+}
+
+impl ReferralRanks {
+    pub fn is_enabled(&self) -> bool {
+        self.rank_count > 0
+    }
+
+    // Thresholds and unlocked levels may not go down from one rank to the next
+    pub fn is_valid(ranks: &[ReferralRank]) -> bool {
+        ranks.len() <= MAX_REFERRAL_RANKS
+            && ranks.iter().all(|rank| rank.unlocked_levels as usize <= MAX_UPLINE_LEVELS)
+            && ranks.windows(2).all(|pair| {
+                pair[1].min_personal_stake >= pair[0].min_personal_stake
+                    && pair[1].min_team_volume >= pair[0].min_team_volume
+                    && pair[1].unlocked_levels >= pair[0].unlocked_levels
+            })
+    }
+
+    pub fn set(&mut self, ranks: &[ReferralRank]) {
+        self.rank_count = ranks.len() as u8;
+        self.ranks = [ReferralRank::default(); MAX_REFERRAL_RANKS];
+        self.ranks[..ranks.len()].copy_from_slice(ranks);
+    }
+
+    pub fn active(&self) -> &[ReferralRank] {
+        &self.ranks[..(self.rank_count as usize).min(MAX_REFERRAL_RANKS)]
+    }
+
+    // Index of the highest rank reached, None below the first rank
+    pub fn rank_of(&self, personal_stake: u64, team_volume: u64) -> Option<u8> {
+        self.active()
+            .iter()
+            .rposition(|rank| personal_stake >= rank.min_personal_stake && team_volume >= rank.min_team_volume)
+            .map(|index| index as u8)
+    }
+
+    pub fn unlocked_levels(&self, personal_stake: u64, team_volume: u64) -> u8 {
+        if !self.is_enabled() {
+            return MAX_UPLINE_LEVELS as u8;
+        }
+
+        self.rank_of(personal_stake, team_volume)
+            .map_or(0, |rank| self.ranks[rank as usize].unlocked_levels)
+    }
+}
+
+/*
+ * Commission paid to the upline wallets out of every OTC payment, in BPS of the payment per level.
+ * Payments are made in SOL unless a quote mint is set, the treasury gets whatever is not paid out.
+ */
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct OtcCommission {
+    pub quote_mint: Pubkey,                   // Token OTC purchases are paid in, default key for SOL
+    pub level_bps: [u16; MAX_UPLINE_LEVELS],  // Share of the payment per upline level
+}
+
+impl OtcCommission {
+    pub fn is_valid(&self) -> bool {
+        self.level_bps.iter().map(|bps| *bps as u32).sum::<u32>() <= 10_000
+    }
+
+    pub fn pays_in_sol(&self) -> bool {
+        self.quote_mint == Pubkey::default()
+    }
+
+    pub fn level_amount(&self, payment: u64, level: usize) -> Result<u64> {
+        let bps = self.level_bps.get(level).copied().unwrap_or(0);
+
+        let amount = (payment as u128)
+            .checked_mul(bps as u128)
+            .ok_or(ErrorCode::Overflow)?
+            .checked_div(10_000)
+            .ok_or(ErrorCode::Overflow)?;
+
+        Ok(amount as u64)
+    }
+}
+
+impl GlobalConfig {
+    pub const LEN: usize = 8 + std::mem::size_of::<GlobalConfig>();
+
+    pub const PAUSE_STAKE: u8 = 1 << 0;
+    pub const PAUSE_CLAIM: u8 = 1 << 1;
+    pub const PAUSE_REFERRAL_CLAIM: u8 = 1 << 2;
+    pub const PAUSE_OTC: u8 = 1 << 3;
+    pub const PAUSE_MINT: u8 = 1 << 4;
+    pub const PAUSE_ALL: u8 = Self::PAUSE_STAKE
+        | Self::PAUSE_CLAIM
+        | Self::PAUSE_REFERRAL_CLAIM
+        | Self::PAUSE_OTC
+        | Self::PAUSE_MINT;
+
+    // Fails if any of the given groups is paused
+    pub fn require_not_paused(&self, flags: u8) -> Result<()> {
+        require!(self.paused_flags & flags == 0, ErrorCode::Paused);
+        Ok(())
+    }
+
+    // Staked principal is burned but will be minted again on unstake, so it counts as supply
+    pub fn remaining_supply(&self, mint_supply: u64) -> u64 {
+        constants::MAX_SUPPLY
+            .saturating_sub(mint_supply)
+            .saturating_sub(self.total_staked)
+    }
+
+    /*
+     * Books rewards against the epoch budget and, for minted rewards, the supply cap.
+     * Returns the part that may be minted now, the caller defers the rest.
+     */
+    pub fn reserve_rewards(&mut self, requested: u64, mint_supply: u64, now: i64) -> Result<u64> {
+        self.emissions.roll(now);
+
+        // Rewards paid from the vault already exist, only minted rewards grow the supply
+        let supply_room = if self.vault_rewards {
+            u64::MAX
+        } else {
+            self.remaining_supply(mint_supply)
+        };
+
+        let granted = requested
+            .min(self.emissions.remaining_rewards(now))
+            .min(supply_room);
+
+        self.emissions.rewards_emitted = self
+            .emissions
+            .rewards_emitted
+            .checked_add(granted)
+            .ok_or(ErrorCode::Overflow)?;
+
+        self.emissions.total_rewards_emitted = self
+            .emissions
+            .total_rewards_emitted
+            .checked_add(granted)
+            .ok_or(ErrorCode::Overflow)?;
+
+        if granted < requested {
+            msg!(
+                "Emissions budget exhausted: {} of {} rewards deferred",
+                requested - granted,
+                requested
+            );
+        }
+
+        Ok(granted)
+    }
+
+    // Admin minting is not deferred, it either fits into the budget or fails
+    pub fn reserve_admin_mint(&mut self, amount: u64, mint_supply: u64, now: i64) -> Result<()> {
+        require!(
+            amount <= self.remaining_supply(mint_supply),
+            ErrorCode::MaxSupplyReached
+        );
+
+        self.emissions.roll(now);
+
+        require!(
+            amount <= self.emissions.remaining_admin_mint(now),
+            ErrorCode::AdminMintCapReached
+        );
+
+        self.emissions.admin_minted = self
+            .emissions
+            .admin_minted
+            .checked_add(amount)
+            .ok_or(ErrorCode::Overflow)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOCK: i64 = 1_000;
+    const EPOCH: i64 = 100;
+    const EPOCH_START: i64 = 1_000;
+
+    fn linear(max_penalty_bps: u16) -> EarlyExitPenalty {
+        EarlyExitPenalty {
+            max_penalty_bps,
+            ..EarlyExitPenalty::default()
+        }
+    }
+
+    fn budget(reward_cap: u64, admin_mint_cap: u64) -> EmissionsBudget {
+        EmissionsBudget {
+            epoch_duration: EPOCH,
+            epoch_start: EPOCH_START,
+            reward_cap,
+            admin_mint_cap,
+            ..EmissionsBudget::default()
+        }
+    }
+
+    fn global_config(emissions: EmissionsBudget) -> GlobalConfig {
+        GlobalConfig {
+            admin: Pubkey::default(),
+            pending_admin: Pubkey::default(),
+            treasury: Pubkey::default(),
+            mint: Pubkey::default(),
+            transfer_manager: Pubkey::default(),
+            current_apy_bps: 1_000,
+            deposit_fee_bps: 0,
+            withdrawal_fee_bps: 0,
+            lvl_percentages: [0; 10],
+            total_staked: 0,
+            config_bumps: 0,
+            mint_bumps: 0,
+            transfer_manager_bumps: 0,
+            legacy_apy_history: Vec::new(),
+            early_exit_penalty: EarlyExitPenalty::default(),
+            min_timelock_delay: 0,
+            config_change_counter: 0,
+            continuous_accrual: false,
+            compound_fee_bps: 0,
+            paused_flags: 0,
+            multisig_required: false,
+            emissions,
+            vault_rewards: false,
+            referral_ranks: ReferralRanks::default(),
+            otc_commission: OtcCommission::default(),
+            apy_history: ApySchedule::new(1_000, EPOCH_START),
+        }
+    }
+
+    fn stepwise(max_penalty_bps: u16, step_count: u8) -> EarlyExitPenalty {
+        EarlyExitPenalty {
+            max_penalty_bps,
+            stepwise: true,
+            step_count,
+            burn: false,
+        }
+    }
+
+    #[test]
+    fn linear_penalty_follows_the_remaining_lock() {
+        let penalty = linear(5_000);
+
+        assert_eq!(penalty.penalty_bps(0, LOCK), 5_000);
+        assert_eq!(penalty.penalty_bps(250, LOCK), 3_750);
+        assert_eq!(penalty.penalty_bps(500, LOCK), 2_500);
+        assert_eq!(penalty.penalty_bps(999, LOCK), 5);
+    }
+
+    #[test]
+    fn stepwise_penalty_drops_once_a_step_is_finished() {
+        let penalty = stepwise(4_000, 4);
+
+        assert_eq!(penalty.penalty_bps(0, LOCK), 4_000);
+        assert_eq!(penalty.penalty_bps(249, LOCK), 4_000);
+        assert_eq!(penalty.penalty_bps(250, LOCK), 3_000);
+        assert_eq!(penalty.penalty_bps(749, LOCK), 2_000);
+        assert_eq!(penalty.penalty_bps(750, LOCK), 1_000);
+        assert_eq!(penalty.penalty_bps(999, LOCK), 1_000);
+    }
+
+    #[test]
+    fn no_penalty_from_maturity_on() {
+        for penalty in [linear(5_000), stepwise(5_000, 3)] {
+            assert_eq!(penalty.penalty_bps(LOCK, LOCK), 0);
+            assert_eq!(penalty.penalty_bps(LOCK + 1, LOCK), 0);
+            assert_eq!(penalty.penalty_bps(0, 0), 0);
+        }
+    }
+
+    #[test]
+    fn elapsed_time_before_staking_counts_as_zero() {
+        assert_eq!(linear(5_000).penalty_bps(-10, LOCK), 5_000);
+        assert_eq!(stepwise(5_000, 5).penalty_bps(-10, LOCK), 5_000);
+    }
+
+    #[test]
+    fn penalty_is_capped_and_needs_steps() {
+        assert!(linear(EarlyExitPenalty::MAX_PENALTY_BPS).is_valid());
+        assert!(!linear(EarlyExitPenalty::MAX_PENALTY_BPS + 1).is_valid());
+        assert!(stepwise(1_000, 1).is_valid());
+        assert!(!stepwise(1_000, 0).is_valid());
+    }
+
+    #[test]
+    fn roll_starts_a_new_epoch_aligned_to_the_first() {
+        let mut emissions = budget(1_000, 1_000);
+        emissions.rewards_emitted = 500;
+        emissions.admin_minted = 200;
+
+        emissions.roll(EPOCH_START + EPOCH - 1);
+
+        assert_eq!(emissions.epoch_start, EPOCH_START);
+        assert_eq!(emissions.rewards_emitted, 500);
+        assert_eq!(emissions.admin_minted, 200);
+
+        emissions.roll(EPOCH_START + 3 * EPOCH + 50);
+
+        assert_eq!(emissions.epoch_start, EPOCH_START + 3 * EPOCH);
+        assert_eq!(emissions.rewards_emitted, 0);
+        assert_eq!(emissions.admin_minted, 0);
+    }
+
+    #[test]
+    fn disabled_budget_never_rolls_or_caps() {
+        let mut emissions = EmissionsBudget {
+            rewards_emitted: 500,
+            ..EmissionsBudget::default()
+        };
+
+        emissions.roll(EPOCH_START + 10 * EPOCH);
+
+        assert_eq!(emissions.rewards_emitted, 500);
+        assert_eq!(emissions.remaining_rewards(EPOCH_START), u64::MAX);
+        assert_eq!(emissions.remaining_admin_mint(EPOCH_START), u64::MAX);
+    }
+
+    #[test]
+    fn remaining_budget_is_the_full_cap_once_the_epoch_is_over() {
+        let mut emissions = budget(1_000, 300);
+        emissions.rewards_emitted = 400;
+        emissions.admin_minted = 500;
+
+        // A lowered cap leaves nothing rather than underflowing
+        assert_eq!(emissions.remaining_rewards(EPOCH_START), 600);
+        assert_eq!(emissions.remaining_admin_mint(EPOCH_START), 0);
+
+        // Not rolled yet, the next epoch still starts with the full cap
+        assert_eq!(emissions.remaining_rewards(EPOCH_START + EPOCH), 1_000);
+        assert_eq!(emissions.remaining_admin_mint(EPOCH_START + EPOCH), 300);
+    }
+
+    #[test]
+    fn rewards_above_the_cap_are_granted_in_the_next_epoch() {
+        let mut global_config = global_config(budget(1_000, 0));
+
+        assert_eq!(global_config.reserve_rewards(1_500, 0, EPOCH_START).unwrap(), 1_000);
+        assert_eq!(global_config.reserve_rewards(200, 0, EPOCH_START + 1).unwrap(), 0);
+
+        // What the claim carried into deferred_rewards is paid from the next epoch's budget
+        let deferred = 1_500 - 1_000 + 200;
+        let next_epoch = EPOCH_START + EPOCH;
+
+        assert_eq!(global_config.reserve_rewards(deferred, 0, next_epoch).unwrap(), deferred);
+        assert_eq!(global_config.emissions.epoch_start, next_epoch);
+        assert_eq!(global_config.emissions.rewards_emitted, deferred);
+        assert_eq!(global_config.emissions.total_rewards_emitted, 1_000 + deferred);
+    }
+
+    #[test]
+    fn minted_rewards_stop_at_the_max_supply() {
+        let mut global_config = global_config(EmissionsBudget::default());
+        let mint_supply = constants::MAX_SUPPLY - 100;
+
+        global_config.total_staked = 40;
+
+        assert_eq!(global_config.reserve_rewards(1_000, mint_supply, EPOCH_START).unwrap(), 60);
+
+        // Vault rewards already exist, they do not grow the supply
+        global_config.vault_rewards = true;
+
+        assert_eq!(global_config.reserve_rewards(1_000, mint_supply, EPOCH_START).unwrap(), 1_000);
+    }
+
+    #[test]
+    fn admin_mint_fails_above_the_cap_until_the_next_epoch() {
+        let mut global_config = global_config(budget(0, 500));
+
+        global_config.reserve_admin_mint(400, 0, EPOCH_START).unwrap();
+
+        assert_eq!(
+            global_config.reserve_admin_mint(200, 0, EPOCH_START + 1).unwrap_err(),
+            ErrorCode::AdminMintCapReached.into()
+        );
+
+        global_config.reserve_admin_mint(200, 0, EPOCH_START + EPOCH).unwrap();
+
+        assert_eq!(global_config.emissions.admin_minted, 200);
+    }
+}
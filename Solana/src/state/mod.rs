@@ -1,9 +1,29 @@
+pub mod apy_schedule;
+pub mod blacklist_entry;
 pub mod config;
+pub mod multisig;
+pub mod otc_desk;
+pub mod pending_config_change;
+pub mod price_oracle;
+pub mod referral_correction;
+pub mod referral_credit;
 pub mod referral_entry;
+pub mod role_registry;
 pub mod staking_account;
+pub mod tier_registry;
 pub mod user_staked_account;
 
+pub use apy_schedule::*;
+pub use blacklist_entry::*;
 pub use config::*;
+pub use multisig::*;
+pub use otc_desk::*;
+pub use pending_config_change::*;
+pub use price_oracle::*;
+pub use referral_correction::*;
+pub use referral_credit::*;
 pub use referral_entry::*;
+pub use role_registry::*;
 pub use staking_account::*;
+pub use tier_registry::*;
 pub use user_staked_account::*;
@@ -0,0 +1,164 @@
+use anchor_lang::prelude::*;
+
+use crate::{
+    errors::ErrorCode,
+    state::ConfigChange,
+};
+
+pub const MAX_MULTISIG_SIGNERS: usize = 10;
+
+/*
+ * M-of-N signer set, seeds = [b"multisig"].
+ * nonce changes with every signer set update, approvals collected under an older set are void.
+ */
+#[account]
+#[derive(Debug)]
+pub struct Multisig {
+    pub signers: [Pubkey; MAX_MULTISIG_SIGNERS], // Keys allowed to create and approve proposals
+    pub signer_count: u8,                     // Number of used signer slots
+    pub threshold: u8,                        // Approvals required to execute a proposal
+    pub nonce: u32,                           // Version of the signer set
+    pub proposal_counter: u64,                // Counter to track Proposals
+    pub bump: u8,
+}
+
+impl Multisig {
+    pub const LEN: usize = 8 + std::mem::size_of::<Multisig>();
+
+    pub fn signer_index(&self, key: &Pubkey) -> Option<usize> {
+        self.signers[..self.signer_count as usize]
+            .iter()
+            .position(|signer| signer == key)
+    }
+
+    pub fn set_signers(&mut self, signers: &[Pubkey], threshold: u8) -> Result<()> {
+        require!(
+            !signers.is_empty() && signers.len() <= MAX_MULTISIG_SIGNERS,
+            ErrorCode::InvalidMultisig
+        );
+        require!(
+            threshold > 0 && threshold as usize <= signers.len(),
+            ErrorCode::InvalidMultisig
+        );
+
+        for (index, signer) in signers.iter().enumerate() {
+            require!(
+                *signer != Pubkey::default() && !signers[..index].contains(signer),
+                ErrorCode::InvalidMultisig
+            );
+        }
+
+        self.signers = [Pubkey::default(); MAX_MULTISIG_SIGNERS];
+        self.signers[..signers.len()].copy_from_slice(signers);
+        self.signer_count = signers.len() as u8;
+        self.threshold = threshold;
+        self.nonce = self.nonce.wrapping_add(1);
+
+        Ok(())
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq)]
+pub enum ProposalAction {
+    MintTokens {
+        amount: u64,
+        destination: Pubkey,                  // Token account receiving the minted tokens
+    },
+    AdjustReferralInfo {
+        staking_account: Pubkey,
+        total_referral_staked_delta: i64,
+        daily_referral_rewards_delta: i64,
+        reason: u8,
+        memo_hash: [u8; 32],
+    },
+    UpdateMultisig {
+        signers: [Pubkey; MAX_MULTISIG_SIGNERS],
+        signer_count: u8,
+        threshold: u8,
+    },
+    AirdropStake {
+        staking_account: Pubkey,              // Staking account the position is opened for
+        amount: u64,
+        tier_id: u8,
+        by_admin: bool,                       // stake_by_admin instead of stake_airdrop
+    },
+    GrantRoles {
+        member: Pubkey,
+        roles: u8,
+    },
+    RevokeRoles {
+        member: Pubkey,
+        roles: u8,
+    },
+    SetLockTier {
+        tier_id: u8,
+        duration: i64,
+        apy_bps: u32,
+        deposit_fee_bps: u16,
+        active: bool,
+    },
+    QueueConfigChange {
+        change: ConfigChange,
+    },
+    UpdateConfig {
+        change: ConfigChange,                 // Applied right away, only while the timelock delay is 0
+    },
+    CancelConfigChange {
+        id: u64,                              // Id of the pending change
+    },
+    TransferOwnership {
+        new_admin: Pubkey,
+    },
+}
+
+#[account]
+#[derive(Debug)]
+pub struct Proposal {
+    pub id: u64,                              // Identifier, part of the PDA seeds
+    pub action: ProposalAction,               // Privileged action to run once approved
+    pub proposer: Pubkey,                     // Signer that created the proposal
+    pub approvals: u16,                       // Bitmask of approving signer indexes
+    pub multisig_nonce: u32,                  // Signer set the approvals belong to
+    pub created_at: i64,
+    pub executed: bool,
+    pub bump: u8,
+}
+
+impl Proposal {
+    pub const LEN: usize = 8 + std::mem::size_of::<Proposal>();
+
+    pub fn approval_count(&self) -> u8 {
+        self.approvals.count_ones() as u8
+    }
+
+    pub fn approve(&mut self, multisig: &Multisig, signer: &Pubkey) -> Result<()> {
+        require!(!self.executed, ErrorCode::ProposalAlreadyExecuted);
+        require!(self.multisig_nonce == multisig.nonce, ErrorCode::ProposalStale);
+
+        let index = multisig
+            .signer_index(signer)
+            .ok_or(ErrorCode::NotMultisigSigner)?;
+        let bit = 1u16 << index;
+
+        require!(self.approvals & bit == 0, ErrorCode::AlreadyApproved);
+
+        self.approvals |= bit;
+
+        Ok(())
+    }
+
+    // Consumes the proposal if it carries exactly this action and enough approvals
+    pub fn execute(&mut self, multisig: &Multisig, action: &ProposalAction) -> Result<()> {
+        require!(!self.executed, ErrorCode::ProposalAlreadyExecuted);
+        require!(self.multisig_nonce == multisig.nonce, ErrorCode::ProposalStale);
+        require!(self.action == *action, ErrorCode::ProposalMismatch);
+        require!(
+            self.approval_count() >= multisig.threshold,
+            ErrorCode::ThresholdNotMet
+        );
+
+        self.executed = true;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,213 @@
+use anchor_lang::prelude::*;
+
+use crate::{errors::ErrorCode, state::PriceOracle};
+
+pub const OTC_DAY_SECONDS: i64 = 86_400;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OtcPriceSource {
+    #[default]
+    Fixed,                                    // fixed_price set by the config manager
+    Twap,                                     // Time weighted average of the price oracle
+}
+
+/*
+ * Prices are in quote units per whole token: lamports, or base units of the OTC quote mint.
+ * Purchase sizes and the daily cap are in token base units, zero max_purchase or
+ * daily_wallet_cap means no limit.
+ */
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct OtcDeskSettings {
+    pub price_source: OtcPriceSource,
+    pub fixed_price: u64,                     // Price used by OtcPriceSource::Fixed
+    pub twap_window: i64,                     // Seconds averaged by OtcPriceSource::Twap
+    pub max_price_age: i64,                   // Oldest oracle update a TWAP purchase may rely on
+    pub min_purchase: u64,                    // Smallest purchase
+    pub max_purchase: u64,                    // Largest purchase
+    pub daily_wallet_cap: u64,                // Tokens one wallet may buy per day
+}
+
+impl OtcDeskSettings {
+    pub fn is_valid(&self) -> bool {
+        let price_valid = match self.price_source {
+            OtcPriceSource::Fixed => self.fixed_price > 0,
+            OtcPriceSource::Twap => self.twap_window > 0 && self.max_price_age > 0,
+        };
+
+        price_valid
+            && self.twap_window >= 0
+            && self.max_price_age >= 0
+            && (self.max_purchase == 0 || self.max_purchase >= self.min_purchase)
+    }
+}
+
+// Pricing of otc_buy, seeds = [b"otc-desk"]
+#[account]
+#[derive(Debug)]
+pub struct OtcDesk {
+    pub settings: OtcDeskSettings,
+    pub bump: u8,
+}
+
+impl OtcDesk {
+    pub const LEN: usize = 8 + std::mem::size_of::<OtcDesk>();
+
+    pub fn price(&self, oracle: &PriceOracle, now: i64) -> Result<u64> {
+        let settings = &self.settings;
+
+        let price = match settings.price_source {
+            OtcPriceSource::Fixed => settings.fixed_price,
+            OtcPriceSource::Twap => oracle.twap(settings.twap_window, settings.max_price_age, now)?,
+        };
+
+        require!(price > 0, ErrorCode::OtcPriceUnavailable);
+
+        Ok(price)
+    }
+
+    // Tokens bought with payment at price, rounded down
+    pub fn tokens_for(payment: u64, price: u64, decimals: u8) -> Result<u64> {
+        let tokens = (payment as u128)
+            .checked_mul(10u128.pow(decimals as u32))
+            .ok_or(ErrorCode::Overflow)?
+            .checked_div(price as u128)
+            .ok_or(ErrorCode::OtcPriceUnavailable)?;
+
+        Ok(u64::try_from(tokens).map_err(|_| ErrorCode::Overflow)?)
+    }
+
+    pub fn check_purchase_size(&self, tokens: u64) -> Result<()> {
+        let settings = &self.settings;
+
+        require!(tokens > 0 && tokens >= settings.min_purchase, ErrorCode::OtcPurchaseTooSmall);
+        require!(
+            settings.max_purchase == 0 || tokens <= settings.max_purchase,
+            ErrorCode::OtcPurchaseTooLarge
+        );
+
+        Ok(())
+    }
+}
+
+// Tokens a wallet bought over the OTC desk today, seeds = [b"otc-limit", user]
+#[account]
+#[derive(Debug)]
+pub struct OtcWalletLimit {
+    pub user: Pubkey,
+    pub day: i64,                             // Day of the purchases, unix time / OTC_DAY_SECONDS
+    pub purchased: u64,                       // Tokens bought on that day
+    pub bump: u8,
+}
+
+impl OtcWalletLimit {
+    pub const LEN: usize = 8 + std::mem::size_of::<OtcWalletLimit>();
+
+    pub fn remaining(&self, daily_cap: u64, now: i64) -> u64 {
+        if daily_cap == 0 {
+            return u64::MAX;
+        }
+
+        if self.day != now / OTC_DAY_SECONDS {
+            return daily_cap;
+        }
+
+        daily_cap.saturating_sub(self.purchased)
+    }
+
+    pub fn record(&mut self, tokens: u64, daily_cap: u64, now: i64) -> Result<()> {
+        require!(tokens <= self.remaining(daily_cap, now), ErrorCode::OtcDailyCapExceeded);
+
+        let today = now / OTC_DAY_SECONDS;
+
+        if self.day != today {
+            self.day = today;
+            self.purchased = 0;
+        }
+
+        self.purchased = self
+            .purchased
+            .checked_add(tokens)
+            .ok_or(ErrorCode::Overflow)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: i64 = OTC_DAY_SECONDS;
+
+    fn wallet_limit() -> OtcWalletLimit {
+        OtcWalletLimit {
+            user: Pubkey::default(),
+            day: 0,
+            purchased: 0,
+            bump: 0,
+        }
+    }
+
+    #[test]
+    fn tokens_for_scales_by_decimals_and_rounds_down() {
+        // 1.5 SOL at 0.5 SOL per token
+        assert_eq!(OtcDesk::tokens_for(1_500_000_000, 500_000_000, 9).unwrap(), 3_000_000_000);
+        assert_eq!(OtcDesk::tokens_for(10, 3, 0).unwrap(), 3);
+        assert_eq!(OtcDesk::tokens_for(2, 3, 0).unwrap(), 0);
+    }
+
+    #[test]
+    fn tokens_for_rejects_a_zero_price_and_overflow() {
+        assert_eq!(
+            OtcDesk::tokens_for(100, 0, 9).unwrap_err(),
+            ErrorCode::OtcPriceUnavailable.into()
+        );
+        assert_eq!(
+            OtcDesk::tokens_for(u64::MAX, 1, 9).unwrap_err(),
+            ErrorCode::Overflow.into()
+        );
+    }
+
+    #[test]
+    fn wallet_limit_caps_purchases_per_day() {
+        let mut limit = wallet_limit();
+        let now = 10 * DAY + 5;
+
+        limit.record(600, 1_000, now).unwrap();
+
+        assert_eq!(
+            limit.record(500, 1_000, now + 1).unwrap_err(),
+            ErrorCode::OtcDailyCapExceeded.into()
+        );
+
+        limit.record(400, 1_000, 11 * DAY - 1).unwrap();
+
+        assert_eq!(limit.day, 10);
+        assert_eq!(limit.purchased, 1_000);
+        assert_eq!(limit.remaining(1_000, 11 * DAY - 1), 0);
+    }
+
+    #[test]
+    fn wallet_limit_starts_over_the_next_day() {
+        let mut limit = wallet_limit();
+
+        limit.record(1_000, 1_000, 10 * DAY).unwrap();
+
+        assert_eq!(limit.remaining(1_000, 11 * DAY), 1_000);
+
+        limit.record(700, 1_000, 11 * DAY).unwrap();
+
+        assert_eq!(limit.day, 11);
+        assert_eq!(limit.purchased, 700);
+    }
+
+    #[test]
+    fn zero_daily_cap_means_no_limit() {
+        let mut limit = wallet_limit();
+
+        limit.record(u64::MAX / 2, 0, DAY).unwrap();
+        limit.record(u64::MAX / 2, 0, DAY).unwrap();
+
+        assert_eq!(limit.remaining(0, DAY), u64::MAX);
+    }
+}
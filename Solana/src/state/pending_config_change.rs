@@ -0,0 +1,48 @@
+use anchor_lang::prelude::*;
+
+use crate::state::{EarlyExitPenalty, OtcCommission, ReferralRanks};
+
+// Deposit fees and staking APYs are set per lock tier, the global APY only pays referral rewards
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq)]
+pub enum ConfigChange {
+    ReferralApy(u32),
+    WithdrawalFee(u16),
+    LvlPercentages([u8; 10]),
+    TimelockDelay(i64),
+    CompoundFee(u16),
+    AccrualMode(bool),
+    EarlyExitPenalty(EarlyExitPenalty),
+    LockTier {
+        tier_id: u8,                          // Equal to the tier count to add a new tier
+        duration: i64,
+        apy_bps: u32,
+        deposit_fee_bps: u16,
+        active: bool,
+    },
+    OtcCommission(OtcCommission),
+    Treasury(Pubkey),
+    RewardMode(bool),                         // Rewards paid from the reward vault instead of minted
+    EmissionsBudget {
+        epoch_duration: i64,
+        reward_cap: u64,
+        admin_mint_cap: u64,
+    },
+    ReferralRanks(ReferralRanks),
+}
+
+#[account]
+#[derive(Debug)]
+pub struct PendingConfigChange {
+    pub id: u64,                              // Value of config_change_counter when queued
+    pub change: ConfigChange,                 // Parameter and its new value
+    pub proposer: Pubkey,                     // Admin who queued the change
+    pub queued_at: i64,
+    pub effective_at: i64,                    // Change can be executed by anyone from this time
+    pub bump: u8,
+}
+
+impl PendingConfigChange {
+    pub const LEN: usize = 8 + std::mem::size_of::<PendingConfigChange>();
+
+    pub const MAX_TIMELOCK_DELAY: i64 = 30 * 24 * 60 * 60;
+}
@@ -0,0 +1,225 @@
+use anchor_lang::prelude::*;
+
+use crate::errors::ErrorCode;
+
+pub const MAX_PRICE_OBSERVATIONS: usize = 32;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default)]
+pub struct PriceObservation {
+    pub price: u64,                           // Quote units per whole token
+    pub timestamp: i64,
+    pub cumulative: u128,                     // Sum of price * seconds of all earlier observations
+}
+
+/*
+ * Token price pushed by the PRICE_FEEDER role, seeds = [b"price-oracle"].
+ * Observations are kept in a ring buffer, each price holds until the next one is pushed.
+ */
+#[account]
+#[derive(Debug)]
+pub struct PriceOracle {
+    pub observations: [PriceObservation; MAX_PRICE_OBSERVATIONS],
+    pub head: u8,                             // Slot of the latest observation
+    pub observation_count: u8,                // Number of used slots
+    pub bump: u8,
+}
+
+impl PriceOracle {
+    pub const LEN: usize = 8 + std::mem::size_of::<PriceOracle>();
+
+    pub fn latest(&self) -> Option<&PriceObservation> {
+        if self.observation_count == 0 {
+            return None;
+        }
+
+        Some(&self.observations[self.head as usize])
+    }
+
+    pub fn push(&mut self, price: u64, now: i64) -> Result<()> {
+        require!(price > 0, ErrorCode::InvalidOtcPrice);
+
+        let cumulative = match self.latest() {
+            Some(latest) => {
+                require!(now > latest.timestamp, ErrorCode::InvalidOtcPrice);
+
+                latest.cumulative_at(now)?
+            }
+            None => 0,
+        };
+
+        if self.observation_count > 0 {
+            self.head = ((self.head as usize + 1) % MAX_PRICE_OBSERVATIONS) as u8;
+        }
+
+        self.observations[self.head as usize] = PriceObservation {
+            price,
+            timestamp: now,
+            cumulative,
+        };
+
+        self.observation_count = (self.observation_count as usize + 1).min(MAX_PRICE_OBSERVATIONS) as u8;
+
+        Ok(())
+    }
+
+    /*
+     * Average price over the window seconds before now. When the oldest kept observation
+     * is younger than the window, the average starts at that observation instead.
+     */
+    pub fn twap(&self, window: i64, max_age: i64, now: i64) -> Result<u64> {
+        let latest = self.latest().ok_or(ErrorCode::OtcPriceUnavailable)?;
+
+        require!(now.saturating_sub(latest.timestamp) <= max_age, ErrorCode::OtcPriceStale);
+
+        let start = now.saturating_sub(window);
+        let mut base = *latest;
+
+        for offset in 0..self.observation_count as usize {
+            let index = (self.head as usize + MAX_PRICE_OBSERVATIONS - offset) % MAX_PRICE_OBSERVATIONS;
+            base = self.observations[index];
+
+            if base.timestamp <= start {
+                break;
+            }
+        }
+
+        let from = base.timestamp.max(start);
+
+        if now <= from {
+            return Ok(latest.price);
+        }
+
+        let total = latest
+            .cumulative_at(now)?
+            .checked_sub(base.cumulative_at(from)?)
+            .ok_or(ErrorCode::Underflow)?;
+
+        let twap = total
+            .checked_div((now - from) as u128)
+            .ok_or(ErrorCode::Overflow)?;
+
+        Ok(u64::try_from(twap).map_err(|_| ErrorCode::Overflow)?)
+    }
+}
+
+impl PriceObservation {
+    // Cumulative price at a time after this observation, while its price still holds
+    pub fn cumulative_at(&self, at: i64) -> Result<u128> {
+        let elapsed = at.saturating_sub(self.timestamp).max(0) as u128;
+
+        Ok(self
+            .cumulative
+            .checked_add((self.price as u128).checked_mul(elapsed).ok_or(ErrorCode::Overflow)?)
+            .ok_or(ErrorCode::Overflow)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const START: i64 = 1_700_000_000;
+    const MAX_AGE: i64 = 1_000_000;
+
+    fn oracle() -> PriceOracle {
+        PriceOracle {
+            observations: [PriceObservation::default(); MAX_PRICE_OBSERVATIONS],
+            head: 0,
+            observation_count: 0,
+            bump: 0,
+        }
+    }
+
+    // One price every 10 seconds, the i-th price is 100 + i
+    fn oracle_with_prices(count: usize) -> PriceOracle {
+        let mut oracle = oracle();
+
+        for i in 0..count {
+            oracle.push(100 + i as u64, START + 10 * i as i64).unwrap();
+        }
+
+        oracle
+    }
+
+    #[test]
+    fn twap_needs_a_price() {
+        assert_eq!(
+            oracle().twap(60, MAX_AGE, START).unwrap_err(),
+            ErrorCode::OtcPriceUnavailable.into()
+        );
+    }
+
+    #[test]
+    fn twap_rejects_a_stale_latest_price() {
+        let mut oracle = oracle();
+        oracle.push(100, START).unwrap();
+
+        assert_eq!(oracle.twap(60, 30, START + 30).unwrap(), 100);
+        assert_eq!(
+            oracle.twap(60, 30, START + 31).unwrap_err(),
+            ErrorCode::OtcPriceStale.into()
+        );
+    }
+
+    #[test]
+    fn twap_weights_prices_by_the_time_they_held() {
+        let mut oracle = oracle();
+        oracle.push(100, START).unwrap();
+        oracle.push(300, START + 100).unwrap();
+
+        // Whole window: 100 for 100 seconds, then 300 for 100 seconds
+        assert_eq!(oracle.twap(200, MAX_AGE, START + 200).unwrap(), 200);
+
+        // Window inside the latest price
+        assert_eq!(oracle.twap(50, MAX_AGE, START + 200).unwrap(), 300);
+
+        // Window over the change: 100 for 50 seconds, then 300 for 100 seconds
+        assert_eq!(oracle.twap(150, MAX_AGE, START + 200).unwrap(), 233);
+    }
+
+    #[test]
+    fn twap_starts_at_the_oldest_price_when_the_window_reaches_before_it() {
+        let mut oracle = oracle();
+        oracle.push(100, START).unwrap();
+        oracle.push(300, START + 100).unwrap();
+
+        assert_eq!(oracle.twap(10_000, MAX_AGE, START + 200).unwrap(), 200);
+    }
+
+    #[test]
+    fn twap_at_the_time_of_the_latest_price_is_that_price() {
+        let oracle = oracle_with_prices(3);
+
+        assert_eq!(oracle.twap(0, MAX_AGE, START + 20).unwrap(), 102);
+    }
+
+    #[test]
+    fn ring_buffer_keeps_the_latest_prices() {
+        let count = MAX_PRICE_OBSERVATIONS + 8;
+        let oracle = oracle_with_prices(count);
+        let now = START + 10 * count as i64;
+
+        assert_eq!(oracle.observation_count as usize, MAX_PRICE_OBSERVATIONS);
+        assert_eq!(oracle.head as usize, (count - 1) % MAX_PRICE_OBSERVATIONS);
+        assert_eq!(oracle.latest().unwrap().price, 100 + count as u64 - 1);
+
+        // Overwritten prices are gone, the average starts at the oldest kept one: 108..=139 for 10 seconds each
+        assert_eq!(oracle.twap(10_000, MAX_AGE, now).unwrap(), 123);
+
+        // 138 for 5 seconds, then 139 for 10 seconds
+        assert_eq!(oracle.twap(15, MAX_AGE, now).unwrap(), 138);
+    }
+
+    #[test]
+    fn push_needs_a_positive_price_and_a_later_time() {
+        let mut oracle = oracle();
+
+        assert!(oracle.push(0, START).is_err());
+
+        oracle.push(100, START).unwrap();
+
+        assert!(oracle.push(200, START).is_err());
+        assert!(oracle.push(200, START - 1).is_err());
+        assert_eq!(oracle.observation_count, 1);
+    }
+}
@@ -0,0 +1,43 @@
+use anchor_lang::prelude::*;
+
+/*
+ * Running totals of the manual referral corrections of one staking account,
+ * seeds = [b"referral-corrections", staking_account]. correction_count is the index
+ * of the next ReferralCorrection of the account.
+ */
+#[account]
+#[derive(Debug)]
+pub struct ReferralCorrectionLog {
+    pub staking_account: Pubkey,              // Corrected staking account
+    pub correction_count: u64,                // Number of corrections applied
+    pub net_total_referral_staked: i64,       // Sum of all total_referral_staked deltas
+    pub net_daily_referral_rewards: i64,      // Sum of all daily_referral_rewards deltas
+    pub bump: u8,
+}
+
+impl ReferralCorrectionLog {
+    pub const LEN: usize = 8 + std::mem::size_of::<ReferralCorrectionLog>();
+}
+
+/*
+ * Audit record of one manual referral correction,
+ * seeds = [b"referral-correction", staking_account, correction_index]. Records are never
+ * overwritten, together they are the full history of the account's corrections.
+ */
+#[account]
+#[derive(Debug)]
+pub struct ReferralCorrection {
+    pub staking_account: Pubkey,              // Corrected staking account
+    pub correction_index: u64,                // Position of the correction in the account's history
+    pub total_referral_staked_delta: i64,
+    pub daily_referral_rewards_delta: i64,
+    pub reason: u8,                           // Reason code, meaning is defined off-chain
+    pub memo_hash: [u8; 32],                  // Hash of the off-chain memo justifying the correction
+    pub authority: Pubkey,                    // Operator, or proposer when approved through the multisig
+    pub corrected_at: i64,
+    pub bump: u8,
+}
+
+impl ReferralCorrection {
+    pub const LEN: usize = 8 + std::mem::size_of::<ReferralCorrection>();
+}
@@ -0,0 +1,135 @@
+use anchor_lang::prelude::*;
+
+use crate::{errors::ErrorCode, state::MAX_UPLINE_LEVELS};
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReferralCreditSource {
+    Position,                                 // Stake, compound, increase_stake or OTC purchase, source is the UserStakedAccount
+    Link,                                     // Stake already held when add_referrer ran, source is the ReferralEntry
+}
+
+// Volume and commission the referral engine credited to each level of the upline
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LevelCredits {
+    pub volumes: [u64; MAX_UPLINE_LEVELS],    // Added to total_referral_staked
+    pub commissions: [u64; MAX_UPLINE_LEVELS], // Added to daily_referral_rewards, zero on levels locked by rank
+}
+
+impl LevelCredits {
+    pub fn is_empty(&self) -> bool {
+        self.volumes.iter().all(|volume| *volume == 0)
+    }
+}
+
+/*
+ * Referral commission credited to the upline on behalf of one position or referral link,
+ * seeds = [b"referral-credit", source]. It is taken back from every level by the
+ * unwind_referral_credit crank once the source matures or is closed.
+ */
+#[account]
+#[derive(Debug)]
+pub struct ReferralCredit {
+    pub source: Pubkey,                       // Position or referral entry the credit belongs to
+    pub kind: ReferralCreditSource,
+    pub staking_account: Pubkey,              // Staking account of the referred user
+    pub upline: [Pubkey; MAX_UPLINE_LEVELS],  // Upline that was credited
+    pub volumes: [u64; MAX_UPLINE_LEVELS],    // Amount added to total_referral_staked, per level
+    pub commissions: [u64; MAX_UPLINE_LEVELS], // Amount added to daily_referral_rewards, per level
+    pub bump: u8,
+}
+
+impl ReferralCredit {
+    pub const LEN: usize = 8 + std::mem::size_of::<ReferralCredit>();
+
+    pub fn is_empty(&self) -> bool {
+        self.volumes.iter().all(|volume| *volume == 0)
+    }
+
+    pub fn level_credits(&self) -> LevelCredits {
+        LevelCredits {
+            volumes: self.volumes,
+            commissions: self.commissions,
+        }
+    }
+
+    /*
+     * Takes the share of `amount` out of the `total` staked in the source off every level,
+     * for a position split off the source. Rounded down, the remainder stays with the source.
+     */
+    pub fn split_off(&mut self, amount: u64, total: u64) -> Result<LevelCredits> {
+        let mut share = LevelCredits::default();
+
+        for level in 0..MAX_UPLINE_LEVELS {
+            share.volumes[level] = proportion(self.volumes[level], amount, total)?;
+            share.commissions[level] = proportion(self.commissions[level], amount, total)?;
+
+            self.volumes[level] = self.volumes[level]
+                .checked_sub(share.volumes[level])
+                .ok_or(ErrorCode::Underflow)?;
+
+            self.commissions[level] = self.commissions[level]
+                .checked_sub(share.commissions[level])
+                .ok_or(ErrorCode::Underflow)?;
+        }
+
+        Ok(share)
+    }
+
+    // Forgets what was credited once it has been unwound, the next record starts over for the current upline
+    pub fn clear(&mut self) {
+        self.source = Pubkey::default();
+        self.volumes = [0; MAX_UPLINE_LEVELS];
+        self.commissions = [0; MAX_UPLINE_LEVELS];
+    }
+
+    /*
+     * Adds what the referral engine credited to the upline.
+     * The account is only required when some level was actually credited.
+     */
+    pub fn record(
+        credit: Option<&mut ReferralCredit>,
+        bump: Option<u8>,
+        source: (Pubkey, ReferralCreditSource),
+        staking_account: Pubkey,
+        upline: &[Pubkey; MAX_UPLINE_LEVELS],
+        credits: &LevelCredits,
+    ) -> Result<()> {
+        let Some(credit) = credit else {
+            require!(credits.is_empty(), ErrorCode::ShouldProvideReferralCredit);
+            return Ok(());
+        };
+
+        if credit.source == Pubkey::default() {
+            credit.source = source.0;
+            credit.kind = source.1;
+            credit.staking_account = staking_account;
+            credit.upline = *upline;
+            credit.bump = bump.ok_or(ErrorCode::ShouldProvideReferralCredit)?;
+        } else {
+            // Credits of one source are unwound together, they have to go to the same upline
+            require!(credit.upline == *upline, ErrorCode::ReferralCreditUplineChanged);
+        }
+
+        for level in 0..MAX_UPLINE_LEVELS {
+            credit.volumes[level] = credit.volumes[level]
+                .checked_add(credits.volumes[level])
+                .ok_or(ErrorCode::Overflow)?;
+
+            credit.commissions[level] = credit.commissions[level]
+                .checked_add(credits.commissions[level])
+                .ok_or(ErrorCode::Overflow)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn proportion(value: u64, amount: u64, total: u64) -> Result<u64> {
+    let share = (value as u128)
+        .checked_mul(amount as u128)
+        .ok_or(ErrorCode::Overflow)?
+        .checked_div(total as u128)
+        .ok_or(ErrorCode::Overflow)?;
+
+    Ok(u64::try_from(share).map_err(|_| ErrorCode::Overflow)?)
+}
@@ -1,7 +1,43 @@
 use anchor_lang::prelude::*;
 
-#[derive(AnchorSerialize, Debug, AnchorDeserialize, Clone)]
+use crate::errors::ErrorCode;
+
+/*
+ * One referral link, seeds = [b"referral", referrer, referee], both keys are staking accounts.
+ * Links are not capped, the referrals of a referrer are enumerated with getProgramAccounts
+ * filtering on the referrer field.
+ */
+#[account]
+#[derive(Debug)]
 pub struct ReferralEntry {
-    pub referrer: Pubkey,
-    pub level: u8,
+    pub referrer: Pubkey,                     // Staking account of the referrer
+    pub referee: Pubkey,                      // Staking account of the referred user
+    pub level: u8,                            // Depth of the referee in the referral tree, 1 below a referrer without referrer
+    pub created_at: i64,                      // Time the link was created
+    pub volume: u64,                          // Amount staked by the referee under this link
+    pub bump: u8,
+}
+
+impl ReferralEntry {
+    pub const LEN: usize = 8 + std::mem::size_of::<ReferralEntry>();
+
+    // parent is the link of the referrer to its own referrer, None for a referrer at the top of the tree
+    pub fn level_below(parent: Option<&ReferralEntry>) -> u8 {
+        parent.map_or(1, |parent| parent.level.saturating_add(1))
+    }
+
+    // The entry has to be passed whenever the staking account has a referrer
+    pub fn record_volume(entry: Option<&mut ReferralEntry>, has_referrer: bool, amount: u64) -> Result<()> {
+        let Some(entry) = entry else {
+            require!(!has_referrer, ErrorCode::ShouldProvideReferralEntry);
+            return Ok(());
+        };
+
+        entry.volume = entry
+            .volume
+            .checked_add(amount)
+            .ok_or(ErrorCode::Overflow)?;
+
+        Ok(())
+    }
 }
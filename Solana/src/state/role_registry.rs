@@ -0,0 +1,125 @@
+use anchor_lang::prelude::*;
+
+use crate::errors::ErrorCode;
+
+pub const MAX_ROLE_MEMBERS: usize = 16;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default)]
+pub struct RoleMember {
+    pub key: Pubkey,                          // Key holding the roles
+    pub roles: u8,                            // Accepted roles, see RoleRegistry::*
+    pub pending_roles: u8,                    // Granted roles waiting to be accepted by the key
+}
+
+/*
+ * Operational roles, seeds = [b"roles"].
+ * The admin grants roles and the key accepts them, same two steps as an ownership transfer.
+ * Revoking takes effect right away, a compromised key must not be able to block its own removal.
+ */
+#[account]
+#[derive(Debug)]
+pub struct RoleRegistry {
+    pub members: [RoleMember; MAX_ROLE_MEMBERS], // Keys with accepted or pending roles
+    pub member_count: u8,                     // Number of used member slots
+    pub bump: u8,
+}
+
+impl RoleRegistry {
+    pub const LEN: usize = 8 + std::mem::size_of::<RoleRegistry>();
+
+    pub const CONFIG_MANAGER: u8 = 1 << 0;
+    pub const MINTER: u8 = 1 << 1;
+    pub const AIRDROP_OPERATOR: u8 = 1 << 2;
+    pub const OTC_OPERATOR: u8 = 1 << 3;
+    pub const REFERRAL_OPERATOR: u8 = 1 << 4;
+    pub const PAUSER: u8 = 1 << 5;
+    pub const PRICE_FEEDER: u8 = 1 << 6;
+    pub const ALL_ROLES: u8 = Self::CONFIG_MANAGER
+        | Self::MINTER
+        | Self::AIRDROP_OPERATOR
+        | Self::OTC_OPERATOR
+        | Self::REFERRAL_OPERATOR
+        | Self::PAUSER
+        | Self::PRICE_FEEDER;
+
+    fn find(&self, key: &Pubkey) -> Option<usize> {
+        self.members[..self.member_count as usize]
+            .iter()
+            .position(|member| member.key == *key)
+    }
+
+    pub fn has_role(&self, key: &Pubkey, role: u8) -> bool {
+        match self.find(key) {
+            Some(index) => self.members[index].roles & role == role,
+            None => false,
+        }
+    }
+
+    pub fn roles_of(&self, key: &Pubkey) -> u8 {
+        self.find(key).map(|index| self.members[index].roles).unwrap_or(0)
+    }
+
+    pub fn grant(&mut self, key: Pubkey, roles: u8) -> Result<()> {
+        require!(
+            roles != 0 && roles & !Self::ALL_ROLES == 0,
+            ErrorCode::InvalidRoles
+        );
+
+        let index = match self.find(&key) {
+            Some(index) => index,
+            None => {
+                require!(
+                    (self.member_count as usize) < MAX_ROLE_MEMBERS,
+                    ErrorCode::RoleRegistryFull
+                );
+
+                let index = self.member_count as usize;
+                self.members[index] = RoleMember {
+                    key,
+                    roles: 0,
+                    pending_roles: 0,
+                };
+                self.member_count += 1;
+                index
+            }
+        };
+
+        self.members[index].pending_roles |= roles;
+
+        Ok(())
+    }
+
+    // Returns the roles that became active
+    pub fn accept(&mut self, key: &Pubkey) -> Result<u8> {
+        let index = self.find(key).ok_or(ErrorCode::NoPendingRoles)?;
+        let member = &mut self.members[index];
+
+        require!(member.pending_roles != 0, ErrorCode::NoPendingRoles);
+
+        let accepted = member.pending_roles;
+        member.roles |= accepted;
+        member.pending_roles = 0;
+
+        Ok(accepted)
+    }
+
+    // Drops both accepted and pending roles, the slot is freed once the key holds nothing
+    pub fn revoke(&mut self, key: &Pubkey, roles: u8) -> Result<()> {
+        require!(roles & !Self::ALL_ROLES == 0, ErrorCode::InvalidRoles);
+
+        let index = self.find(key).ok_or(ErrorCode::MissingRole)?;
+        let member = &mut self.members[index];
+
+        member.roles &= !roles;
+        member.pending_roles &= !roles;
+
+        if member.roles == 0 && member.pending_roles == 0 {
+            let last = self.member_count as usize - 1;
+            self.members[index] = self.members[last];
+            self.members[last] = RoleMember::default();
+            self.member_count -= 1;
+        }
+
+        Ok(())
+    }
+}
@@ -2,11 +2,15 @@ use anchor_lang::prelude::*;
 use serde::{Serialize, Deserialize};
 
 use crate::{
-    constants::constants, errors::ErrorCode, state::GlobalConfig
+    constants::constants,
+    errors::ErrorCode,
+    state::{ApySchedule, GlobalConfig, TierRegistry, REWARD_PER_TOKEN_PRECISION},
 };
 
 use super::UserStakedAccount;
 
+pub const MAX_UPLINE_LEVELS: usize = 10;
+
 #[account]
 #[derive(Debug, Serialize, Deserialize)]
 pub struct StakingAccount {
@@ -18,92 +22,208 @@ pub struct StakingAccount {
     pub user_staked_counter: u64,             // Counter to track UserStakedAccounts
     pub last_referral_rewards_claimed: i64,   // Last referral rewards claimed timestamp
     pub referrer: Pubkey,                     // Referrer of the user
-    pub referral_history: Vec<Pubkey>,        // Referral history addresses
+    pub referral_history: Vec<Pubkey>,        // Referees of the first layout, emptied by migrate_staking_account
+    pub closed_stakes_counter: u64,           // Counter of closed UserStakedAccounts
+    pub deferred_rewards: u64,                // Rewards that did not fit into the emissions budget, paid by the next claim
+    pub upline: [Pubkey; MAX_UPLINE_LEVELS],  // Referrer staking accounts by level, upline[0] is the referrer, default keys past the top
+    pub referral_apy_pinned: bool,            // Referral checkpoint holds a pin in the global APY history
 }
 
 impl StakingAccount {
-    pub const LEN: usize = 8 + std::mem::size_of::<StakingAccount>() + 8 + (32 * constants::MAX_REFERRAL);
+    pub const LEN: usize = 8 + std::mem::size_of::<StakingAccount>();
 
     pub fn is_initialized(&self) -> bool {
         self.user != Pubkey::default()
     }
 
+    pub fn active_stakes(&self) -> u64 {
+        self.user_staked_counter.saturating_sub(self.closed_stakes_counter)
+    }
+
+    // Upline of an account referred by referrer_key: the referrer followed by its own upline
+    pub fn upline_below(referrer_key: Pubkey, referrer: &StakingAccount) -> [Pubkey; MAX_UPLINE_LEVELS] {
+        let mut upline = [Pubkey::default(); MAX_UPLINE_LEVELS];
+
+        upline[0] = referrer_key;
+        upline[1..].copy_from_slice(&referrer.upline[..MAX_UPLINE_LEVELS - 1]);
+
+        upline
+    }
+
+    pub fn upline_depth(upline: &[Pubkey]) -> usize {
+        upline.iter().take_while(|key| **key != Pubkey::default()).count()
+    }
+
+    /*
+     * Referrer accounts passed through remaining accounts must be exactly the cached upline, in order.
+     * A shorter list would silently pay fewer levels, so truncated chains are rejected.
+     */
+    pub fn verify_referrer_accounts(upline: &[Pubkey], referrer_accounts: &[AccountInfo]) -> Result<()> {
+        let depth = Self::upline_depth(upline);
+
+        require!(referrer_accounts.len() <= MAX_UPLINE_LEVELS, ErrorCode::OnlyTenReferralsLevel);
+
+        if referrer_accounts.is_empty() {
+            require!(depth == 0, ErrorCode::ShouldProvideReferrer);
+        }
+
+        require!(referrer_accounts.len() >= depth, ErrorCode::IncompleteReferrerChain);
+        require!(referrer_accounts.len() == depth, ErrorCode::ReferrerMismatch);
+
+        for (account_info, expected) in referrer_accounts.iter().zip(upline) {
+            require_keys_eq!(account_info.key(), *expected, ErrorCode::ReferrerMismatch);
+        }
+
+        Ok(())
+    }
+
+    // Position passed through remaining accounts, must be owned by the program and by this user
+    pub fn load_user_staked_account(&self, account_info: &AccountInfo) -> Result<UserStakedAccount> {
+        if account_info.owner != &crate::ID {
+            return Err(ErrorCode::InvalidStakedAccount.into());
+        }
+
+        let user_staked_account_data: UserStakedAccount = {
+            let user_staked_account = account_info.try_borrow_data()?;
+            UserStakedAccount::try_deserialize(&mut &user_staked_account[..])
+                .map_err(|_| ErrorCode::DeserializationError)?
+        };
+
+        if user_staked_account_data.user.key() != self.user.key() {
+            return Err(ErrorCode::InvalidUser.into());
+        }
+
+        Ok(user_staked_account_data)
+    }
+
+    // The same position passed twice would be paid twice
+    pub fn require_unique_accounts(remaining_accounts: &[AccountInfo]) -> Result<()> {
+        for (index, account_info) in remaining_accounts.iter().enumerate() {
+            if remaining_accounts[..index]
+                .iter()
+                .any(|previous| previous.key() == account_info.key())
+            {
+                return Err(ErrorCode::DuplicateStakedAccount.into());
+            }
+        }
+
+        Ok(())
+    }
+
+    // Rank is taken from the referrer's own stake and the volume of its team
+    pub fn referral_rank(&self, global_config: &GlobalConfig) -> Option<u8> {
+        global_config
+            .referral_ranks
+            .rank_of(self.total_staked, self.total_referral_staked)
+    }
+
+    pub fn unlocked_referral_levels(&self, global_config: &GlobalConfig) -> u8 {
+        global_config
+            .referral_ranks
+            .unlocked_levels(self.total_staked, self.total_referral_staked)
+    }
+
     pub fn calculate_referral_rewards(
         &self,
-        global_config: &Account<GlobalConfig>,
+        global_config: &GlobalConfig,
         now: i64,
     ) -> Result<u64> {
-        let mut rewards: u128 = 0;
         let last_claimed = self.last_referral_rewards_claimed;
-        let principal = self.daily_referral_rewards as u128;
-        let apy_history = global_config.apy_history.clone();
-        let previous_apy = apy_history.first().unwrap().apy_bps as u128;
-        let current_apy_start = apy_history.first().unwrap().timestamp;
-        let current_apy = global_config.current_apy_bps as u128;
-
-        let mut last_claimed_day = last_claimed - (last_claimed % constants::CLAIM_PERIOD_SECONDS);
-        let current_apy_start_day = current_apy_start - (current_apy_start % constants::CLAIM_PERIOD_SECONDS);
 
-        if last_claimed_day < current_apy_start_day {
-            let interval = (current_apy_start_day - last_claimed_day) / constants::CLAIM_PERIOD_SECONDS;
+        let rewards = if global_config.continuous_accrual {
+            let reward_per_token = global_config
+                .apy_history
+                .reward_per_token_at(now)?
+                .saturating_sub(global_config.apy_history.reward_per_token_at(last_claimed)?);
 
-            let rewards_by_day = principal
-                .checked_mul(previous_apy)
+            (self.daily_referral_rewards as u128)
+                .checked_mul(reward_per_token)
                 .ok_or(ErrorCode::Overflow)?
-                .checked_mul(constants::CLAIM_PERIOD_SECONDS as u128)
+                .checked_div(REWARD_PER_TOKEN_PRECISION)
                 .ok_or(ErrorCode::Overflow)?
-                .checked_div(365 * 24 * 60 * 60 * 10_000) // Adjust based on staking period
-                .ok_or(ErrorCode::Overflow)?;
+        } else {
+            let last_claimed_day = last_claimed - (last_claimed % constants::CLAIM_PERIOD_SECONDS);
+            let today = now - (now % constants::CLAIM_PERIOD_SECONDS);
+            let interval = (today - last_claimed_day).max(0) / constants::CLAIM_PERIOD_SECONDS;
+
+            global_config
+                .apy_history
+                .accrue(self.daily_referral_rewards, last_claimed_day, interval)?
+        };
+
+        msg!("Total rewards: {:?}", rewards);
+
+        Ok(u64::try_from(rewards).map_err(|_| ErrorCode::Overflow)?)
+    }
 
-            rewards += rewards_by_day * interval as u128;
+    // Referral rewards accrue from the start of the claim period of the last claim, in either accrual mode
+    pub fn referral_checkpoint(&self) -> Option<i64> {
+        let last_claimed = self.last_referral_rewards_claimed;
+
+        self.referral_apy_pinned
+            .then(|| last_claimed - (last_claimed % constants::CLAIM_PERIOD_SECONDS))
+    }
+
+    /*
+     * Moves the pin of the referral checkpoint in the global APY history, see ApySchedule.
+     * Called after daily_referral_rewards or last_referral_rewards_claimed changed, with the
+     * checkpoint from before the change. Accounts without daily referral rewards hold no pin.
+     */
+    pub fn repin_referral_checkpoint(&mut self, apy_history: &mut ApySchedule, previous: Option<i64>) -> Result<()> {
+        self.referral_apy_pinned = self.daily_referral_rewards > 0;
 
-            last_claimed_day = current_apy_start_day;
+        apy_history.move_pin(previous, self.referral_checkpoint())
+    }
+
+    // Referral rewards accrued at the current rate move to deferred_rewards before the rate goes down,
+    // the caller moves the pin of the referral checkpoint
+    pub fn settle_referral_rewards(&mut self, global_config: &GlobalConfig, now: i64) -> Result<u64> {
+        if self.daily_referral_rewards == 0 {
+            return Ok(0);
         }
 
-        let today = now - (now % constants::CLAIM_PERIOD_SECONDS);
-        let interval = (today - last_claimed_day) / constants::CLAIM_PERIOD_SECONDS;
+        let settled = self.calculate_referral_rewards(global_config, now)?;
 
-        let rewards_by_day = principal
-            .checked_mul(current_apy)
-            .ok_or(ErrorCode::Overflow)?
-            .checked_mul(constants::CLAIM_PERIOD_SECONDS as u128)
-            .ok_or(ErrorCode::Overflow)?
-            .checked_div(365 * 24 * 60 * 60 * 10_000) // Adjust based on staking period
+        self.deferred_rewards = self
+            .deferred_rewards
+            .checked_add(settled)
             .ok_or(ErrorCode::Overflow)?;
 
-        rewards += rewards_by_day * interval as u128;
-
-        msg!("Total rewards: {:?}", rewards);
+        self.last_referral_rewards_claimed = if global_config.continuous_accrual {
+            now
+        } else {
+            now - (now % constants::CLAIM_PERIOD_SECONDS)
+        };
 
-        Ok(u64::try_from(rewards).map_err(|_| ErrorCode::Overflow)?)
+        Ok(settled)
     }
 
     pub fn calculate_all_rewards(
         &self,
         remaining_accounts: &[AccountInfo],
-        global_config: &Account<GlobalConfig>,
+        tier_registry: &TierRegistry,
+        continuous_accrual: bool,
         now: i64
     ) -> Result<u64> {
         let mut total_rewards = 0_u64;
+
+        Self::require_unique_accounts(remaining_accounts)?;
     
         for account_info in remaining_accounts.iter() {
-            let user_staked_account_data: UserStakedAccount = {
-                let user_staked_account = account_info.try_borrow_data()?;
-                UserStakedAccount::try_deserialize(&mut &user_staked_account[..])
-                    .map_err(|_| ErrorCode::DeserializationError)?
-            };
+            let user_staked_account_data = self.load_user_staked_account(account_info)?;
     
-            if user_staked_account_data.user.key() != self.user.key() {
-                return Err(ErrorCode::InvalidUser.into());
+            if user_staked_account_data.maturity_timestamp() < now {
+                continue;
             }
-    
-            if user_staked_account_data.staked_timestamp + constants::ONE_YEAR_PERIOD < now {
+
+            // Tokenized positions are claimed one by one by their holder
+            if user_staked_account_data.is_tokenized() {
                 continue;
             }
     
             let last_claim_time = user_staked_account_data.last_claimed_timestamp;
     
-            if now - last_claim_time < constants::CLAIM_PERIOD_SECONDS {
+            if user_staked_account_data.claim_too_soon(continuous_accrual, now) {
                 msg!(
                     "Claim too soon for account: {:?}. You may claim at {:?}",
                     account_info.key(),
@@ -112,14 +232,18 @@ impl StakingAccount {
                 continue;
             }
     
-            let reward_lamports_u64 = user_staked_account_data.calculate_rewards(global_config, now)?;
+            let tier = tier_registry.get_tier(user_staked_account_data.tier_id)?;
+            let (reward_lamports_u64, _) =
+                user_staked_account_data.claimable_rewards(continuous_accrual, tier, now)?;
     
             if reward_lamports_u64 == 0 {
                 msg!("No rewards available to claim for account: {:?}", account_info.key());
                 continue;
             }
     
-            total_rewards += reward_lamports_u64;
+            total_rewards = total_rewards
+                .checked_add(reward_lamports_u64)
+                .ok_or(ErrorCode::Overflow)?;
         }
     
         Ok(total_rewards)
@@ -0,0 +1,54 @@
+use anchor_lang::prelude::*;
+
+use crate::{errors::ErrorCode, state::ApySchedule};
+
+pub const MAX_LOCK_TIERS: usize = 8;
+
+// Tier that positions of the first layout are migrated to, its one-year lock can not be changed
+pub const LEGACY_TIER_ID: u8 = 0;
+
+#[zero_copy]
+#[derive(Debug, Default)]
+pub struct LockTier {
+    pub apy_history: ApySchedule,             // Every APY value with the time it started working
+    pub duration: i64,                        // Lock period in seconds
+    pub current_apy_bps: u32,                 // Current APY in BPS
+    pub deposit_fee_bps: u16,                 // Deposit fee in BPS
+    pub active: u8,                           // New positions can be opened under this tier
+    pub _padding: [u8; 1],
+}
+
+impl LockTier {
+    pub fn is_active(&self) -> bool {
+        self.active != 0
+    }
+}
+
+/*
+ * Eight tiers with their APY histories take close to 4KB, more than a stack frame holds,
+ * so the registry is zero-copy and tiers are only ever borrowed from the account data.
+ */
+#[account(zero_copy)]
+#[derive(Debug)]
+pub struct TierRegistry {
+    pub tiers: [LockTier; MAX_LOCK_TIERS],    // Lock tiers, indexed by tier id
+    pub tier_count: u8,                       // Number of configured tiers
+    pub bump: u8,
+    pub _padding: [u8; 6],
+}
+
+impl TierRegistry {
+    pub const LEN: usize = 8 + std::mem::size_of::<TierRegistry>();
+
+    pub fn get_tier(&self, tier_id: u8) -> Result<&LockTier> {
+        require!(tier_id < self.tier_count, ErrorCode::InvalidTier);
+
+        Ok(&self.tiers[tier_id as usize])
+    }
+
+    pub fn get_tier_mut(&mut self, tier_id: u8) -> Result<&mut LockTier> {
+        require!(tier_id < self.tier_count, ErrorCode::InvalidTier);
+
+        Ok(&mut self.tiers[tier_id as usize])
+    }
+}
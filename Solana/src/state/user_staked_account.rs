@@ -1,7 +1,10 @@
 use anchor_lang::prelude::*;
+use anchor_spl::token::TokenAccount;
 
 use crate::{
-    constants::constants, errors::ErrorCode, state::GlobalConfig
+    constants::constants,
+    errors::ErrorCode,
+    state::{LockTier, REWARD_PER_TOKEN_PRECISION},
 };
 
 #[account]
@@ -12,56 +15,192 @@ pub struct UserStakedAccount {
     pub last_claimed_timestamp: i64,
     pub staked_timestamp: i64,
     pub user: Pubkey,
+    pub tier_id: u8,                          // Lock tier the position was opened under
+    pub lock_duration: i64,                   // Tier duration at the time the position was opened
+    pub reward_per_token_paid: u128,          // Tier reward-per-token accumulator at last_claimed_timestamp
+    pub pending_rewards: u64,                 // Rewards settled before last_claimed_timestamp but not paid yet
+    pub position_mint: Pubkey,                // NFT representing the position, default if not tokenized
 }
 
 impl UserStakedAccount {
     pub const LEN: usize = 8 + std::mem::size_of::<UserStakedAccount>();
 
-    pub fn calculate_rewards(
+    pub fn maturity_timestamp(&self) -> i64 {
+        self.staked_timestamp + self.lock_duration
+    }
+
+    pub fn is_tokenized(&self) -> bool {
+        self.position_mint != Pubkey::default()
+    }
+
+    // Tokenized positions are controlled by whoever holds the position NFT, the rest by their user
+    pub fn is_authorized(&self, signer: &Pubkey, position_token_account: Option<&TokenAccount>) -> bool {
+        if !self.is_tokenized() {
+            return self.user == *signer;
+        }
+
+        match position_token_account {
+            Some(token_account) => {
+                token_account.mint == self.position_mint
+                    && token_account.owner == *signer
+                    && token_account.amount == 1
+            }
+            None => false,
+        }
+    }
+
+    // Latest claim period boundary, claim periods are aligned to the hour the position was staked at
+    pub fn aligned_claim_timestamp(&self, now: i64) -> i64 {
+        let stake_hours = self.staked_timestamp % constants::CLAIM_PERIOD_SECONDS;
+        let today = now - (now % constants::CLAIM_PERIOD_SECONDS);
+        let yesterday = today - constants::CLAIM_PERIOD_SECONDS;
+
+        if now >= today + stake_hours {
+            today + stake_hours
+        } else {
+            yesterday + stake_hours
+        }
+    }
+
+    /*
+     * Checkpoints of an open position pin the APY change they rely on in the tier's history,
+     * see ApySchedule. Every open position holds exactly one pin, from start_accrual until stop_accrual.
+     */
+    pub fn start_accrual(&mut self, tier: &mut LockTier, timestamp: i64) -> Result<()> {
+        self.last_claimed_timestamp = timestamp;
+        self.reward_per_token_paid = tier.apy_history.reward_per_token_at(timestamp)?;
+        self.pending_rewards = 0;
+
+        tier.apy_history.pin(timestamp)
+    }
+
+    // The position is closed or merged into another one
+    pub fn stop_accrual(&self, tier: &mut LockTier) -> Result<()> {
+        tier.apy_history.unpin(self.last_claimed_timestamp)
+    }
+
+    // Everything accrued up to timestamp is considered paid out, including pending_rewards
+    pub fn set_last_claimed(&mut self, tier: &mut LockTier, timestamp: i64) -> Result<()> {
+        tier.apy_history
+            .move_pin(Some(self.last_claimed_timestamp), Some(timestamp))?;
+
+        self.last_claimed_timestamp = timestamp;
+        self.reward_per_token_paid = tier.apy_history.reward_per_token_at(timestamp)?;
+        self.pending_rewards = 0;
+
+        Ok(())
+    }
+
+    /*
+     * Moves rewards accrued up to now (capped by maturity) into pending_rewards, in the configured accrual mode.
+     * In daily mode only whole claim periods are settled and the checkpoint moves to the end of the last one,
+     * the period in progress keeps accruing and is paid once it completes.
+     */
+    pub fn settle_rewards(&mut self, tier: &mut LockTier, continuous_accrual: bool, now: i64) -> Result<u64> {
+        let accrued_until = now.min(self.maturity_timestamp());
+
+        let (settled, checkpoint) = if continuous_accrual {
+            (self.calculate_continuous_rewards(tier, accrued_until)?, now)
+        } else {
+            let periods =
+                (accrued_until - self.last_claimed_timestamp).max(0) / constants::CLAIM_PERIOD_SECONDS;
+
+            (
+                self.calculate_rewards(tier, accrued_until)?,
+                self.last_claimed_timestamp + periods * constants::CLAIM_PERIOD_SECONDS,
+            )
+        };
+
+        let pending_rewards = self
+            .pending_rewards
+            .checked_add(settled)
+            .ok_or(ErrorCode::Overflow)?;
+
+        self.set_last_claimed(tier, checkpoint)?;
+        self.pending_rewards = pending_rewards;
+
+        Ok(settled)
+    }
+
+    /*
+     * Restarts the lock and the claim schedule at now, for the whole position.
+     * Rewards are settled in the configured mode first. In daily mode the claim period in progress
+     * is not paid, the same as for a position that ends in the middle of a period.
+     */
+    pub fn restart_lock(&mut self, tier: &mut LockTier, continuous_accrual: bool, now: i64) -> Result<u64> {
+        let settled = self.settle_rewards(tier, continuous_accrual, now)?;
+        let pending_rewards = self.pending_rewards;
+
+        self.set_last_claimed(tier, now)?;
+        self.pending_rewards = pending_rewards;
+        self.staked_timestamp = now;
+        self.lock_duration = tier.duration;
+
+        Ok(settled)
+    }
+
+    // Daily claims wait for a whole claim period since the last claim, continuous ones are never too soon
+    pub fn claim_too_soon(&self, continuous_accrual: bool, now: i64) -> bool {
+        !continuous_accrual && now - self.last_claimed_timestamp < constants::CLAIM_PERIOD_SECONDS
+    }
+
+    // Rewards for the next claim and the timestamp the claim moves last_claimed_timestamp to
+    pub fn claimable_rewards(
+        &self,
+        continuous_accrual: bool,
+        tier: &LockTier,
+        now: i64,
+    ) -> Result<(u64, i64)> {
+        let (rewards, new_last_claimed_timestamp) = if continuous_accrual {
+            (self.calculate_continuous_rewards(tier, now)?, now)
+        } else {
+            (self.calculate_rewards(tier, now)?, self.aligned_claim_timestamp(now))
+        };
+
+        let rewards = rewards
+            .checked_add(self.pending_rewards)
+            .ok_or(ErrorCode::Overflow)?;
+
+        Ok((rewards, new_last_claimed_timestamp))
+    }
+
+    pub fn calculate_continuous_rewards(
         &self,
-        global_config: &Account<GlobalConfig>,
+        tier: &LockTier,
         now: i64,
     ) -> Result<u64> {
-        let mut rewards: u128 = 0;
-        let stake_time = self.staked_timestamp;
-        let mut last_claimed = self.last_claimed_timestamp;
-        let principal = self.amount_staked as u128;
-        let apy_history = global_config.apy_history.clone();
-        let previous_apy = apy_history.first().unwrap().apy_bps as u128;
-        let current_apy_start = apy_history.first().unwrap().timestamp;
-        let current_apy = global_config.current_apy_bps as u128;
-        let stake_hours = stake_time % constants::CLAIM_PERIOD_SECONDS;
-
-        let last_claimed_day = last_claimed - (last_claimed % constants::CLAIM_PERIOD_SECONDS);
-        let current_apy_start_day = current_apy_start - (current_apy_start % constants::CLAIM_PERIOD_SECONDS);
-
-        if last_claimed_day < current_apy_start_day {
-            let interval = (current_apy_start_day - last_claimed_day) / constants::CLAIM_PERIOD_SECONDS;
-
-            let rewards_by_day = principal
-                .checked_mul(previous_apy)
-                .ok_or(ErrorCode::Overflow)?
-                .checked_mul(constants::CLAIM_PERIOD_SECONDS as u128)
-                .ok_or(ErrorCode::Overflow)?
-                .checked_div(365 * 24 * 60 * 60 * 10_000) // Adjust based on staking period
-                .ok_or(ErrorCode::Overflow)?;
-
-            rewards += rewards_by_day * interval as u128;
-
-            last_claimed = current_apy_start_day + stake_hours;
-        }
+        // Positions that never checkpointed the accumulator start from their last claim
+        let reward_per_token_paid = if self.reward_per_token_paid == 0 {
+            tier.apy_history.reward_per_token_at(self.last_claimed_timestamp)?
+        } else {
+            self.reward_per_token_paid
+        };
 
-        let interval = (now - last_claimed) / constants::CLAIM_PERIOD_SECONDS;
+        let reward_per_token = tier.apy_history.reward_per_token_at(now)?;
 
-        let rewards_by_day = principal
-            .checked_mul(current_apy)
-            .ok_or(ErrorCode::Overflow)?
-            .checked_mul(constants::CLAIM_PERIOD_SECONDS as u128)
+        let rewards = (self.amount_staked as u128)
+            .checked_mul(reward_per_token.saturating_sub(reward_per_token_paid))
             .ok_or(ErrorCode::Overflow)?
-            .checked_div(365 * 24 * 60 * 60 * 10_000) // Adjust based on staking period
+            .checked_div(REWARD_PER_TOKEN_PRECISION)
             .ok_or(ErrorCode::Overflow)?;
 
-        rewards += rewards_by_day * interval as u128;
+        msg!("Total rewards: {:?}", rewards);
+
+        Ok(u64::try_from(rewards).map_err(|_| ErrorCode::Overflow)?)
+    }
+
+    pub fn calculate_rewards(
+        &self,
+        tier: &LockTier,
+        now: i64,
+    ) -> Result<u64> {
+        // last_claimed_timestamp is kept aligned to the staking hour, so claim periods start from it
+        let last_claimed = self.last_claimed_timestamp;
+        let interval = (now - last_claimed).max(0) / constants::CLAIM_PERIOD_SECONDS;
+
+        let rewards = tier
+            .apy_history
+            .accrue(self.amount_staked, last_claimed, interval)?;
 
         msg!("Total rewards: {:?}", rewards);
 